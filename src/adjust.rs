@@ -0,0 +1,266 @@
+use image::{imageops, DynamicImage, RgbaImage};
+
+/// How the histogram of the image is spread out to boost its contrast.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Equalization {
+    None,
+    /// Equalizes the histogram of the whole image at once
+    Global,
+    /// Contrast limited adaptive histogram equalization, which equalizes each tile of the
+    /// image separately, so that faint lines in a dark region are boosted as much as those
+    /// in a bright one
+    Clahe,
+}
+
+/// Tonal and sharpness adjustments applied to the image before thresholding.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct AdjustParams {
+    /// Standard deviation of the Gaussian blur smoothing the image, in pixels. 0.0 disables it.
+    pub blur_sigma: f32,
+    pub equalization: Equalization,
+    /// Number of tiles along each side of the image for `Equalization::Clahe`.
+    pub clahe_tiles: u32,
+    /// How many times the average count a histogram bin may hold for `Equalization::Clahe`,
+    /// which limits how much noise is amplified in flat regions.
+    pub clahe_clip_limit: f32,
+    /// Offset added to every channel, as a fraction of the full range.
+    pub brightness: f32,
+    /// Multiplier applied to the distance of every channel from mid-grey.
+    pub contrast: f32,
+    /// Exponent applied to each normalized channel. Values above 1.0 darken mid-tones.
+    pub gamma: f32,
+    /// Strength of the unsharp mask. 0.0 disables it.
+    pub sharpen_amount: f32,
+    /// Standard deviation of the blur that the unsharp mask subtracts, in pixels.
+    pub sharpen_sigma: f32,
+    pub invert: bool,
+}
+
+impl Default for AdjustParams {
+    fn default() -> Self {
+        Self {
+            blur_sigma: 0.0,
+            equalization: Equalization::None,
+            clahe_tiles: 8,
+            clahe_clip_limit: 2.0,
+            brightness: 0.0,
+            contrast: 1.0,
+            gamma: 1.0,
+            sharpen_amount: 0.0,
+            sharpen_sigma: 1.0,
+            invert: false,
+        }
+    }
+}
+
+impl AdjustParams {
+    /// Whether these settings change the image at all.
+    pub fn is_identity(&self) -> bool {
+        self.blur_sigma <= 0.0
+            && self.equalization == Equalization::None
+            && self.brightness == 0.0
+            && self.contrast == 1.0
+            && self.gamma == 1.0
+            && self.sharpen_amount <= 0.0
+            && !self.invert
+    }
+}
+
+/// Applies the adjustments to an image, keeping its alpha channel.
+///
+/// The steps run in a fixed order: blur, equalization, brightness and contrast, gamma,
+/// unsharp mask, then inversion. Equalization works on the luminance of the image and
+/// shifts the three color channels together. Fully transparent pixels are left out of the
+/// histograms, so that a removed background doesn't skew them.
+pub fn adjust_image(img: &DynamicImage, params: &AdjustParams) -> DynamicImage {
+    let mut rgba = img.to_rgba8();
+
+    if params.blur_sigma > 0.0 {
+        rgba = imageops::blur(&rgba, params.blur_sigma);
+    }
+
+    match params.equalization {
+        Equalization::None => {}
+        Equalization::Global => {
+            let (width, height) = rgba.dimensions();
+            let mapping = equalization_mapping(&luma_histogram(&rgba, 0, 0, width, height), None);
+            shift_luma(&mut rgba, |_, _, luma| mapping[luma as usize]);
+        }
+        Equalization::Clahe => clahe(&mut rgba, params.clahe_tiles.max(1), params.clahe_clip_limit),
+    }
+
+    // brightness, contrast, gamma and inversion are all per channel, so they share a lookup table
+    let tone_curve: [u8; 256] = std::array::from_fn(|value| {
+        let mut v = value as f32 / 255.0;
+        v = (v - 0.5) * params.contrast + 0.5 + params.brightness;
+        v = v.clamp(0.0, 1.0).powf(params.gamma);
+        (v * 255.0).round().clamp(0.0, 255.0) as u8
+    });
+    for pixel in rgba.pixels_mut() {
+        for channel in 0..3 {
+            pixel[channel] = tone_curve[pixel[channel] as usize];
+        }
+    }
+
+    if params.sharpen_amount > 0.0 {
+        let blurred = imageops::blur(&rgba, params.sharpen_sigma.max(0.1));
+        for (pixel, blurred) in rgba.pixels_mut().zip(blurred.pixels()) {
+            for channel in 0..3 {
+                let value = pixel[channel] as f32;
+                let sharpened = value + params.sharpen_amount * (value - blurred[channel] as f32);
+                pixel[channel] = sharpened.round().clamp(0.0, 255.0) as u8;
+            }
+        }
+    }
+
+    if params.invert {
+        for pixel in rgba.pixels_mut() {
+            for channel in 0..3 {
+                pixel[channel] = 255 - pixel[channel];
+            }
+        }
+    }
+
+    DynamicImage::ImageRgba8(rgba)
+}
+
+fn luma(pixel: &image::Rgba<u8>) -> u8 {
+    (0.299 * pixel[0] as f32 + 0.587 * pixel[1] as f32 + 0.114 * pixel[2] as f32).round() as u8
+}
+
+/// Counts the luminance values of the visible pixels within a rectangle of the image.
+fn luma_histogram(img: &RgbaImage, x0: u32, y0: u32, x1: u32, y1: u32) -> [u32; 256] {
+    let mut histogram = [0; 256];
+    for y in y0..y1 {
+        for x in x0..x1 {
+            let pixel = img.get_pixel(x, y);
+            if pixel[3] > 0 {
+                histogram[luma(pixel) as usize] += 1;
+            }
+        }
+    }
+    histogram
+}
+
+/// Maps each luminance value through the normalized cumulative histogram.
+///
+/// With a `clip_limit`, bins holding more than that many pixels are clipped and the excess is
+/// spread evenly over all bins before accumulating, as in CLAHE.
+fn equalization_mapping(histogram: &[u32; 256], clip_limit: Option<f32>) -> [f32; 256] {
+    let mut bins = histogram.map(|count| count as f32);
+    let total: f32 = bins.iter().sum();
+    if total == 0.0 {
+        return std::array::from_fn(|value| value as f32);
+    }
+
+    if let Some(clip_limit) = clip_limit {
+        let mut excess = 0.0;
+        for bin in bins.iter_mut() {
+            if *bin > clip_limit {
+                excess += *bin - clip_limit;
+                *bin = clip_limit;
+            }
+        }
+        for bin in bins.iter_mut() {
+            *bin += excess / 256.0;
+        }
+    }
+
+    let mut mapping = [0.0; 256];
+    let mut cumulative = 0.0;
+    for (value, bin) in bins.iter().enumerate() {
+        cumulative += bin;
+        mapping[value] = cumulative / total * 255.0;
+    }
+    mapping
+}
+
+/// Moves the luminance of every visible pixel to `target(x, y, luma)`, shifting the
+/// three color channels by the same amount.
+fn shift_luma(img: &mut RgbaImage, target: impl Fn(u32, u32, u8) -> f32) {
+    for (x, y, pixel) in img.enumerate_pixels_mut() {
+        if pixel[3] == 0 {
+            continue;
+        }
+        let current = luma(pixel);
+        let shift = target(x, y, current) - current as f32;
+        for channel in 0..3 {
+            pixel[channel] = (pixel[channel] as f32 + shift).round().clamp(0.0, 255.0) as u8;
+        }
+    }
+}
+
+/// Contrast limited adaptive histogram equalization over a `tiles` by `tiles` grid.
+///
+/// Each pixel's mapping is interpolated bilinearly between the mappings of the four
+/// nearest tile centers, so that no seams appear at the tile borders.
+fn clahe(img: &mut RgbaImage, tiles: u32, clip_limit: f32) {
+    let (width, height) = img.dimensions();
+    let tiles_x = tiles.min(width.max(1));
+    let tiles_y = tiles.min(height.max(1));
+    let tile_width = width as f32 / tiles_x as f32;
+    let tile_height = height as f32 / tiles_y as f32;
+
+    let mut mappings = Vec::with_capacity((tiles_x * tiles_y) as usize);
+    for ty in 0..tiles_y {
+        for tx in 0..tiles_x {
+            let x0 = (tx as f32 * tile_width) as u32;
+            let y0 = (ty as f32 * tile_height) as u32;
+            let x1 = (((tx + 1) as f32 * tile_width) as u32).min(width);
+            let y1 = (((ty + 1) as f32 * tile_height) as u32).min(height);
+            let histogram = luma_histogram(img, x0, y0, x1, y1);
+            let visible: u32 = histogram.iter().sum();
+            let limit = (clip_limit * visible as f32 / 256.0).max(1.0);
+            mappings.push(equalization_mapping(&histogram, Some(limit)));
+        }
+    }
+    let mapping = |tx: u32, ty: u32| &mappings[(ty * tiles_x + tx) as usize];
+
+    shift_luma(img, |x, y, luma| {
+        // position relative to the tile centers, clamped at the edges of the image
+        let fx = ((x as f32 + 0.5) / tile_width - 0.5).clamp(0.0, (tiles_x - 1) as f32);
+        let fy = ((y as f32 + 0.5) / tile_height - 0.5).clamp(0.0, (tiles_y - 1) as f32);
+        let (tx0, ty0) = (fx.floor() as u32, fy.floor() as u32);
+        let (tx1, ty1) = ((tx0 + 1).min(tiles_x - 1), (ty0 + 1).min(tiles_y - 1));
+        let (wx, wy) = (fx - tx0 as f32, fy - ty0 as f32);
+
+        let luma = luma as usize;
+        let top = mapping(tx0, ty0)[luma] * (1.0 - wx) + mapping(tx1, ty0)[luma] * wx;
+        let bottom = mapping(tx0, ty1)[luma] * (1.0 - wx) + mapping(tx1, ty1)[luma] * wx;
+        top * (1.0 - wy) + bottom * wy
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    #[test]
+    fn tone_adjustments_keep_the_alpha_channel() {
+        let img = DynamicImage::ImageRgba8(RgbaImage::from_pixel(2, 2, Rgba([64, 128, 192, 77])));
+
+        let inverted = adjust_image(&img, &AdjustParams { invert: true, ..AdjustParams::default() }).to_rgba8();
+        assert_eq!(*inverted.get_pixel(0, 0), Rgba([191, 127, 63, 77]));
+
+        // contrast pushes each channel away from mid-grey
+        let contrasted = adjust_image(&img, &AdjustParams { contrast: 2.0, ..AdjustParams::default() }).to_rgba8();
+        assert_eq!(*contrasted.get_pixel(0, 0), Rgba([1, 129, 255, 77]));
+
+        let darkened = adjust_image(&img, &AdjustParams { gamma: 2.0, ..AdjustParams::default() }).to_rgba8();
+        assert!(darkened.get_pixel(0, 0).0[..3].iter().zip([64, 128, 192]).all(|(&v, original)| v < original));
+    }
+
+    #[test]
+    fn equalization_spreads_a_narrow_histogram() {
+        let img = DynamicImage::ImageRgba8(RgbaImage::from_fn(16, 16, |x, _| {
+            let v = 100 + (x as u8) * 2;
+            Rgba([v, v, v, 255])
+        }));
+        let equalized = adjust_image(&img, &AdjustParams { equalization: Equalization::Global, ..AdjustParams::default() }).to_rgba8();
+        let lumas: Vec<u8> = equalized.pixels().map(|p| p.0[0]).collect();
+        assert!(*lumas.iter().max().unwrap() - *lumas.iter().min().unwrap() > 200);
+        assert!(AdjustParams::default().is_identity());
+    }
+}
@@ -0,0 +1,251 @@
+use std::{fs::File, io::BufReader, path::Path};
+
+use image::{codecs::gif::GifDecoder, AnimationDecoder, DynamicImage};
+
+use crate::{utils::Coordinate, vector::load_image};
+
+/// Loads every frame of an animation.
+///
+/// `path` may either be an animated GIF, or a directory holding a numbered image sequence
+/// (e.g. `frame_001.png`, `frame_002.png`, ...). Sequence frames are ordered by the number
+/// in their file name, so `frame_10.png` comes after `frame_9.png`.
+pub fn load_frames(path: &Path, resize: Option<(u32, u32)>) -> Result<Vec<DynamicImage>, String> {
+    if path.is_dir() {
+        let mut files: Vec<_> = std::fs::read_dir(path)
+            .map_err(|e| format!("{:?}", e))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|file| {
+                file.extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| matches!(ext.to_lowercase().as_str(), "png" | "jpg" | "jpeg" | "svg"))
+            })
+            .collect();
+        files.sort_by_key(|file| (frame_number(file), file.clone()));
+
+        if files.is_empty() {
+            return Err(format!("No image frames found in '{}'", path.display()));
+        }
+        files.iter().map(|file| load_image(file, resize)).collect()
+    } else {
+        let is_gif = path.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("gif"));
+        if !is_gif {
+            return Err("Animations must be a GIF file or a directory of numbered frames".to_string());
+        }
+
+        let file = File::open(path).map_err(|e| format!("{:?}", e))?;
+        let decoder = GifDecoder::new(BufReader::new(file)).map_err(|e| format!("{:?}", e))?;
+        let frames = decoder.into_frames().collect_frames().map_err(|e| format!("{:?}", e))?;
+        Ok(frames
+            .into_iter()
+            .map(|frame| DynamicImage::ImageRgba8(frame.into_buffer()))
+            .collect())
+    }
+}
+
+/// Extracts the last run of digits in a file name, used to order image sequences.
+fn frame_number(path: &Path) -> Option<u64> {
+    let stem = path.file_stem()?.to_str()?;
+    let end = stem.rfind(|c: char| c.is_ascii_digit())? + 1;
+    let start = stem[..end]
+        .rfind(|c: char| !c.is_ascii_digit())
+        .map_or(0, |i| i + 1);
+    stem[start..end].parse().ok()
+}
+
+/// Assigns the sampled points of every frame to the same `drones`, so that drone `i` moves as
+/// little as possible from one frame to the next.
+///
+/// A frame with fewer points than drones leaves the unmatched drones holding their previous
+/// position, and a blank frame holds every drone. The drones start on the points of the first
+/// frame that isn't blank, several to a point if it has fewer points than drones, and spread
+/// out as later frames gain points.
+///
+/// Returns `None` if every frame is blank.
+pub fn match_frames(frames: &[Vec<Coordinate>], drones: usize) -> Option<Vec<Vec<Coordinate>>> {
+    let first = frames.iter().find(|points| !points.is_empty())?;
+    let mut positions: Vec<Coordinate> = first.iter().copied().cycle().take(drones).collect();
+
+    Some(
+        frames
+            .iter()
+            .map(|points| {
+                positions = match_to_previous(&positions, points);
+                positions.clone()
+            })
+            .collect()
+    )
+}
+
+/// Reorders a frame's coordinates so that drone `i` moves as little as possible from its
+/// position `previous[i]` in the frame before.
+///
+/// This solves the assignment problem on squared distances, so the total squared travel
+/// between the two frames is minimal. The output always has `previous.len()` drones: if
+/// `next` has more points than that, the ones left unassigned are dropped, and if it has
+/// fewer, the drones left without a target hold their previous position.
+pub fn match_to_previous(previous: &[Coordinate], next: &[Coordinate]) -> Vec<Coordinate> {
+    if previous.is_empty() || next.is_empty() {
+        return previous.to_vec();
+    }
+
+    let assignment = if previous.len() <= next.len() {
+        let costs: Vec<Vec<f64>> = previous
+            .iter()
+            .map(|p| next.iter().map(|q| p.distance_squared(q)).collect())
+            .collect();
+        hungarian(&costs).into_iter().map(Some).collect::<Vec<_>>()
+    } else {
+        // solve the transposed problem, assigning each point of `next` to a drone
+        let costs: Vec<Vec<f64>> = next
+            .iter()
+            .map(|q| previous.iter().map(|p| p.distance_squared(q)).collect())
+            .collect();
+        let mut assignment = vec![None; previous.len()];
+        for (point, drone) in hungarian(&costs).into_iter().enumerate() {
+            assignment[drone] = Some(point);
+        }
+        assignment
+    };
+
+    assignment
+        .into_iter()
+        .zip(previous)
+        .map(|(point, &held)| point.map_or(held, |point| next[point]))
+        .collect()
+}
+
+/// Solves the rectangular assignment problem with the Hungarian algorithm.
+///
+/// This implementation is O(n^2 * m), for an `n x m` cost matrix with `n <= m`.
+///
+/// # Returns
+/// For each row, the index of the column it is assigned to.
+fn hungarian(costs: &[Vec<f64>]) -> Vec<usize> {
+    let n = costs.len();
+    let m = costs[0].len();
+
+    // potentials for rows (u) and columns (v), and the row matched to each column,
+    // all 1-indexed so that index 0 can act as a virtual starting column
+    let mut u = vec![0.0; n + 1];
+    let mut v = vec![0.0; m + 1];
+    let mut matched_row = vec![0usize; m + 1];
+    let mut way = vec![0usize; m + 1];
+
+    for row in 1..=n {
+        matched_row[0] = row;
+        let mut column = 0;
+        let mut min_slack = vec![f64::INFINITY; m + 1];
+        let mut used = vec![false; m + 1];
+
+        // grow an alternating path until it reaches a free column
+        loop {
+            used[column] = true;
+            let current_row = matched_row[column];
+            let mut delta = f64::INFINITY;
+            let mut next_column = 0;
+            for j in 1..=m {
+                if !used[j] {
+                    let slack = costs[current_row - 1][j - 1] - u[current_row] - v[j];
+                    if slack < min_slack[j] {
+                        min_slack[j] = slack;
+                        way[j] = column;
+                    }
+                    if min_slack[j] < delta {
+                        delta = min_slack[j];
+                        next_column = j;
+                    }
+                }
+            }
+            for j in 0..=m {
+                if used[j] {
+                    u[matched_row[j]] += delta;
+                    v[j] -= delta;
+                } else {
+                    min_slack[j] -= delta;
+                }
+            }
+            column = next_column;
+            if matched_row[column] == 0 {
+                break;
+            }
+        }
+
+        // flip the alternating path to include the new row
+        loop {
+            let previous_column = way[column];
+            matched_row[column] = matched_row[previous_column];
+            column = previous_column;
+            if column == 0 {
+                break;
+            }
+        }
+    }
+
+    let mut assignment = vec![0; n];
+    for j in 1..=m {
+        if matched_row[j] != 0 {
+            assignment[matched_row[j] - 1] = j - 1;
+        }
+    }
+    assignment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_are_ordered_by_the_number_in_their_name() {
+        let mut files: Vec<&Path> = ["frame_10.png", "frame_9.png", "frame_100.png", "frame_1.png"]
+            .iter()
+            .map(Path::new)
+            .collect();
+        files.sort_by_key(|file| frame_number(file));
+        assert_eq!(files, ["frame_1.png", "frame_9.png", "frame_10.png", "frame_100.png"].map(Path::new));
+
+        assert_eq!(frame_number(Path::new("shot2_take_042.png")), Some(42));
+        assert_eq!(frame_number(Path::new("cover.png")), None);
+    }
+
+    #[test]
+    fn hungarian_finds_the_cheapest_assignment() {
+        // greedily taking the cheapest cell (row 0 to column 0) costs 1 + 10 = 11, while the
+        // optimum crosses over for 2 + 3 = 5
+        let costs = vec![vec![1.0, 2.0], vec![3.0, 10.0]];
+        assert_eq!(hungarian(&costs), vec![1, 0]);
+
+        // with more columns than rows, the spare column is left out
+        let costs = vec![vec![5.0, 1.0, 9.0], vec![1.0, 5.0, 9.0]];
+        assert_eq!(hungarian(&costs), vec![1, 0]);
+    }
+
+    #[test]
+    fn drones_move_to_the_closest_points_of_the_next_frame() {
+        let previous = [Coordinate::new(0, 0), Coordinate::new(10, 0)];
+        let next = [Coordinate::new(11, 1), Coordinate::new(1, 1)];
+        assert_eq!(match_to_previous(&previous, &next), vec![Coordinate::new(1, 1), Coordinate::new(11, 1)]);
+
+        // a drone without a point left holds its position, and spare points are dropped
+        assert_eq!(match_to_previous(&previous, &[Coordinate::new(9, 0)]), vec![Coordinate::new(0, 0), Coordinate::new(9, 0)]);
+        assert_eq!(match_to_previous(&previous[..1], &next), vec![Coordinate::new(1, 1)]);
+    }
+
+    #[test]
+    fn matched_frames_keep_the_number_of_drones() {
+        let frames = vec![
+            vec![],
+            vec![Coordinate::new(0, 0)],
+            vec![Coordinate::new(5, 5), Coordinate::new(0, 1), Coordinate::new(9, 9)],
+        ];
+        let matched = match_frames(&frames, 2).unwrap();
+
+        // the blank first frame starts on the first frame with points
+        assert_eq!(matched[0], vec![Coordinate::new(0, 0); 2]);
+        assert_eq!(matched[1], vec![Coordinate::new(0, 0); 2]);
+        assert_eq!(matched[2].len(), 2);
+        assert!(matched[2].contains(&Coordinate::new(0, 1)));
+        assert!(match_frames(&[vec![], vec![]], 2).is_none());
+    }
+}
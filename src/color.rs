@@ -0,0 +1,31 @@
+/// Converts an sRGB color to CIELAB (D65 white point), where euclidean distances
+/// approximate perceived color differences.
+///
+/// # Returns
+/// The `[L, a, b]` components, with `L` in `[0.0, 100.0]`.
+pub fn rgb_to_lab(rgb: [u8; 3]) -> [f32; 3] {
+    // undo the sRGB transfer curve
+    let linear = rgb.map(|channel| {
+        let c = channel as f32 / 255.0;
+        if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+    });
+
+    // linear sRGB to XYZ, normalized by the D65 reference white
+    let x = (0.4124 * linear[0] + 0.3576 * linear[1] + 0.1805 * linear[2]) / 0.95047;
+    let y = 0.2126 * linear[0] + 0.7152 * linear[1] + 0.0722 * linear[2];
+    let z = (0.0193 * linear[0] + 0.1192 * linear[1] + 0.9505 * linear[2]) / 1.08883;
+
+    let f = |t: f32| {
+        if t > 216.0 / 24389.0 { t.cbrt() } else { (24389.0 / 27.0 * t + 16.0) / 116.0 }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+/// The squared CIE76 color difference between two CIELAB colors.
+pub fn lab_distance_squared(lhs: &[f32; 3], rhs: &[f32; 3]) -> f64 {
+    let dl = (lhs[0] - rhs[0]) as f64;
+    let da = (lhs[1] - rhs[1]) as f64;
+    let db = (lhs[2] - rhs[2]) as f64;
+    dl.mul_add(dl, da.mul_add(da, db * db))
+}
@@ -0,0 +1,292 @@
+use std::path::Path;
+
+use crate::{export::normalize_coordinates, utils::Coordinate};
+
+/// Settings for rehearsing a formation indoors on a Crazyswarm setup.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct CrazyswarmParams {
+    /// Side length of the flight volume the formation is fitted into, in meters.
+    pub cube_size: f64,
+    /// Minimum allowed distance between any two drones, in meters.
+    pub min_spacing: f64,
+    /// Height of the lowest drone in the formation above the floor, in meters.
+    pub floor_margin: f64,
+    /// Distance between neighbouring drones on the takeoff grid, in meters. It is shrunk, down to
+    /// `min_spacing`, when the grid wouldn't fit in the flight volume.
+    pub ground_spacing: f64,
+    /// Height drones climb to above their initial position before moving into formation.
+    pub takeoff_height: f64,
+    /// Time in seconds to climb from the floor to `takeoff_height`.
+    pub takeoff_time: f64,
+    /// Time in seconds to fly from the takeoff point into the formation.
+    pub duration: f64,
+    /// Radio channel written to `crazyflies.yaml` for every drone.
+    pub channel: u8,
+}
+
+impl Default for CrazyswarmParams {
+    fn default() -> Self {
+        Self {
+            cube_size: 4.0,
+            min_spacing: 0.3,
+            floor_margin: 0.5,
+            ground_spacing: 0.5,
+            takeoff_height: 0.5,
+            takeoff_time: 2.0,
+            duration: 5.0,
+            channel: 80,
+        }
+    }
+}
+
+/// A single drone of an indoor formation, in Crazyswarm world coordinates (z up).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CrazyflieDrone {
+    pub id: u32,
+    pub initial_position: [f64; 3],
+    pub formation_position: [f64; 3],
+}
+
+/// Fits a formation into the indoor flight volume and assigns takeoff positions.
+///
+/// The formation stands in the vertical y-z plane, centered on the origin, with its
+/// lowest drone `floor_margin` above the floor. Drones take off from a square grid
+/// on the floor. Fails if any two drones end up closer than `min_spacing`, or if the
+/// takeoff grid can't fit in the flight volume without doing so.
+pub fn crazyswarm_formation(coords: &[Coordinate], params: &CrazyswarmParams) -> Result<Vec<CrazyflieDrone>, String> {
+    let max_dim = params.cube_size - params.floor_margin;
+    if max_dim <= 0.0 {
+        return Err("The floor margin must be smaller than the cube size".to_string());
+    }
+    let normalized = normalize_coordinates(coords, max_dim)?;
+
+    // a wide formation is centered vertically within `max_dim`, so it is lowered back onto the margin
+    let lowest = normalized.iter().map(|coord| coord.y()).fold(f64::INFINITY, f64::min);
+    let formation: Vec<[f64; 3]> = normalized
+        .iter()
+        .map(|coord| [coord.z(), coord.x() - max_dim / 2.0, coord.y() - lowest + params.floor_margin])
+        .collect();
+
+    if let Some((i, j, dist)) = closest_pair(&formation) {
+        if dist < params.min_spacing {
+            return Err(format!(
+                "Drones {} and {} are only {:.3} m apart, below the minimum spacing of {} m",
+                i + 1, j + 1, dist, params.min_spacing
+            ));
+        }
+    }
+
+    // lay out the takeoff positions on a centered square grid, tightened if needed so that
+    // it fits on the floor of the flight volume
+    let columns = (formation.len() as f64).sqrt().ceil() as usize;
+    let rows = formation.len().div_ceil(columns);
+    let ground_spacing = takeoff_spacing(columns, params)?;
+    let grid_offset_x = (rows - 1) as f64 * ground_spacing / 2.0;
+    let grid_offset_y = (columns - 1) as f64 * ground_spacing / 2.0;
+
+    Ok(formation
+        .into_iter()
+        .enumerate()
+        .map(|(i, formation_position)| {
+            let initial_position = [
+                (i / columns) as f64 * ground_spacing - grid_offset_x,
+                (i % columns) as f64 * ground_spacing - grid_offset_y,
+                0.0,
+            ];
+            CrazyflieDrone {
+                id: i as u32 + 1,
+                initial_position,
+                formation_position,
+            }
+        })
+        .collect())
+}
+
+/// The spacing of a square takeoff grid of `columns` columns, which is `ground_spacing` unless
+/// the grid would then be wider than the flight volume. The spacing is then shrunk to fit, but
+/// never below `min_spacing`, in which case the drones can't all take off from the floor.
+fn takeoff_spacing(columns: usize, params: &CrazyswarmParams) -> Result<f64, String> {
+    let gaps = columns.saturating_sub(1) as f64;
+    if gaps * params.ground_spacing <= params.cube_size {
+        return Ok(params.ground_spacing);
+    }
+    let spacing = params.cube_size / gaps;
+    if spacing < params.min_spacing {
+        return Err(format!(
+            "The takeoff grid of {} by {} drones doesn't fit in the {} m flight volume at the minimum spacing of {} m",
+            columns, columns, params.cube_size, params.min_spacing
+        ));
+    }
+    Ok(spacing)
+}
+
+/// Finds the two closest points in a set, returning their indices and distance.
+fn closest_pair(points: &[[f64; 3]]) -> Option<(usize, usize, f64)> {
+    let mut closest: Option<(usize, usize, f64)> = None;
+    for i in 0..points.len() {
+        for j in (i + 1)..points.len() {
+            let dx = points[i][0] - points[j][0];
+            let dy = points[i][1] - points[j][1];
+            let dz = points[i][2] - points[j][2];
+            let dist_sq = dx.mul_add(dx, dy.mul_add(dy, dz * dz));
+            if closest.is_none_or(|(_, _, best)| dist_sq < best) {
+                closest = Some((i, j, dist_sq));
+            }
+        }
+    }
+    closest.map(|(i, j, dist_sq)| (i, j, dist_sq.sqrt()))
+}
+
+/// Builds a Crazyswarm-style `crazyflies.yaml` listing every drone's id and initial position.
+pub fn crazyflies_yaml(drones: &[CrazyflieDrone], params: &CrazyswarmParams) -> String {
+    let mut yaml = String::from("crazyflies:\n");
+    for drone in drones {
+        let [x, y, z] = drone.initial_position;
+        yaml.push_str(&format!("  - id: {}\n", drone.id));
+        yaml.push_str(&format!("    channel: {}\n", params.channel));
+        yaml.push_str(&format!("    initialPosition: [{:.3}, {:.3}, {:.3}]\n", x, y, z));
+        yaml.push_str("    type: default\n");
+    }
+    yaml
+}
+
+/// Builds the waypoint CSV for a single drone, with the structure:
+/// t (seconds), x (meters), y, z
+///
+/// The drone starts on the floor, climbs to `takeoff_height` and then flies
+/// into its formation position.
+pub fn crazyflie_waypoints_csv(drone: &CrazyflieDrone, params: &CrazyswarmParams) -> Result<Vec<u8>, String> {
+    let [x0, y0, _] = drone.initial_position;
+    let waypoints = [
+        (0.0, drone.initial_position),
+        (params.takeoff_time, [x0, y0, params.takeoff_height]),
+        (params.takeoff_time + params.duration, drone.formation_position),
+    ];
+
+    let mut wtr = csv::Writer::from_writer(vec![]);
+    wtr.write_record(["t", "x", "y", "z"]).map_err(|e| format!("CSV error: {}", e))?;
+    for (t, [x, y, z]) in waypoints {
+        wtr.write_record(&[
+            format!("{:.3}", t),
+            format!("{:.3}", x),
+            format!("{:.3}", y),
+            format!("{:.3}", z),
+        ]).map_err(|e| format!("CSV error: {}", e))?;
+    }
+    wtr.into_inner().map_err(|e| format!("CSV error: {}", e))
+}
+
+/// Writes `crazyflies.yaml` and one `waypoints/cf<id>.csv` per drone into `dir`.
+pub fn write_crazyswarm(dir: &Path, coords: &[Coordinate], params: &CrazyswarmParams) -> Result<(), String> {
+    let drones = crazyswarm_formation(coords, params)?;
+
+    let waypoint_dir = dir.join("waypoints");
+    std::fs::create_dir_all(&waypoint_dir)
+        .map_err(|e| format!("Failed to create directory '{}': {}", waypoint_dir.display(), e))?;
+
+    let yaml_path = dir.join("crazyflies.yaml");
+    std::fs::write(&yaml_path, crazyflies_yaml(&drones, params))
+        .map_err(|e| format!("Failed to save file '{}': {}", yaml_path.display(), e))?;
+
+    for drone in &drones {
+        let path = waypoint_dir.join(format!("cf{}.csv", drone.id));
+        std::fs::write(&path, crazyflie_waypoints_csv(drone, params)?)
+            .map_err(|e| format!("Failed to save file '{}': {}", path.display(), e))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(size: u32) -> Vec<Coordinate> {
+        vec![
+            Coordinate::new(0, 0),
+            Coordinate::new(size, 0),
+            Coordinate::new(0, size),
+            Coordinate::new(size, size),
+        ]
+    }
+
+    #[test]
+    fn formations_fit_in_the_flight_volume() {
+        let params = CrazyswarmParams::default();
+        let drones = crazyswarm_formation(&square(100), &params).unwrap();
+
+        let half = params.cube_size / 2.0;
+        for drone in &drones {
+            let [x, y, z] = drone.formation_position;
+            assert_eq!(x, 0.0);
+            assert!((-half..=half).contains(&y));
+            assert!((params.floor_margin..=params.cube_size).contains(&z));
+        }
+        let lowest = drones.iter().map(|d| d.formation_position[2]).fold(f64::INFINITY, f64::min);
+        let highest = drones.iter().map(|d| d.formation_position[2]).fold(0.0, f64::max);
+        assert!((lowest - params.floor_margin).abs() < 1e-9);
+        assert!((highest - params.cube_size).abs() < 1e-9);
+    }
+
+    #[test]
+    fn wide_formations_rest_on_the_floor_margin() {
+        let params = CrazyswarmParams::default();
+        let coords = [Coordinate::new(0, 0), Coordinate::new(100, 0), Coordinate::new(0, 20), Coordinate::new(100, 20)];
+        let drones = crazyswarm_formation(&coords, &params).unwrap();
+
+        let heights: Vec<f64> = drones.iter().map(|d| d.formation_position[2]).collect();
+        let lowest = heights.iter().copied().fold(f64::INFINITY, f64::min);
+        let highest = heights.iter().copied().fold(0.0, f64::max);
+        assert!((lowest - params.floor_margin).abs() < 1e-9);
+        // a fifth of the 3.5 m width
+        assert!((highest - lowest - 0.7).abs() < 1e-9);
+    }
+
+    #[test]
+    fn drones_closer_than_the_minimum_spacing_are_rejected() {
+        // 1 pixel out of 1000 is 3.5 mm once fitted in the default volume
+        let coords = [Coordinate::new(0, 0), Coordinate::new(1000, 0), Coordinate::new(1, 0)];
+        let error = crazyswarm_formation(&coords, &CrazyswarmParams::default()).unwrap_err();
+        assert!(error.contains("minimum spacing of 0.3 m"), "{}", error);
+    }
+
+    #[test]
+    fn takeoff_grid_is_centered_and_shrinks_to_fit() {
+        let params = CrazyswarmParams::default();
+        let drones = crazyswarm_formation(&square(100), &params).unwrap();
+        let initial: Vec<[f64; 3]> = drones.iter().map(|d| d.initial_position).collect();
+        assert_eq!(initial, vec![
+            [-0.25, -0.25, 0.0],
+            [-0.25, 0.25, 0.0],
+            [0.25, -0.25, 0.0],
+            [0.25, 0.25, 0.0],
+        ]);
+
+        // 11 columns at 0.5 m would span 5 m of the 4 m volume
+        assert_eq!(takeoff_spacing(3, &params), Ok(0.5));
+        assert!((takeoff_spacing(11, &params).unwrap() - 0.4).abs() < 1e-9);
+        // 15 columns would need less than 0.3 m between drones
+        assert!(takeoff_spacing(15, &params).is_err());
+    }
+
+    #[test]
+    fn yaml_and_waypoints_describe_each_drone() {
+        let params = CrazyswarmParams::default();
+        let drone = CrazyflieDrone {
+            id: 3,
+            initial_position: [0.25, -0.5, 0.0],
+            formation_position: [0.0, 1.0, 2.0],
+        };
+
+        assert_eq!(
+            crazyflies_yaml(&[drone], &params),
+            "crazyflies:\n  - id: 3\n    channel: 80\n    initialPosition: [0.250, -0.500, 0.000]\n    type: default\n",
+        );
+
+        let csv = String::from_utf8(crazyflie_waypoints_csv(&drone, &params).unwrap()).unwrap();
+        assert_eq!(
+            csv,
+            "t,x,y,z\n0.000,0.250,-0.500,0.000\n2.000,0.250,-0.500,0.500\n7.000,0.000,1.000,2.000\n",
+        );
+    }
+}
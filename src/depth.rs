@@ -0,0 +1,104 @@
+use image::DynamicImage;
+
+use crate::utils::{Coordinate, ExportCoordinate};
+
+/// Settings for turning the brightness of a depth image into the depth of a formation.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct DepthParams {
+    /// Depth given to black pixels, in meters.
+    pub min_depth: f64,
+    /// Depth given to white pixels, in meters.
+    pub max_depth: f64,
+    /// Swaps the two ends of the range, so that white pixels get `min_depth`.
+    pub invert: bool,
+}
+
+impl Default for DepthParams {
+    fn default() -> Self {
+        Self {
+            min_depth: 0.0,
+            max_depth: 4.0,
+            invert: false,
+        }
+    }
+}
+
+/// Reads a depth for each coordinate from the luminance of a depth image.
+///
+/// The coordinates are expected in the pixel space of a `width x height` image, such as the
+/// processed source image. The depth image doesn't need to have the same resolution, as long
+/// as it has the same aspect ratio: each coordinate is scaled onto it proportionally.
+///
+/// # Arguments
+/// * `depth_img` - The image whose luminance encodes depth. This may be the source image itself.
+/// * `coords` - The sampled coordinates to read the depth of.
+/// * `width` - The width of the image space of `coords`.
+/// * `height` - The height of the image space of `coords`.
+/// * `params` - The depth range and direction.
+///
+/// # Returns
+/// The depth of each coordinate in meters, in the same order as `coords`.
+pub fn sample_depths(
+    depth_img: &DynamicImage,
+    coords: &[Coordinate],
+    width: u32,
+    height: u32,
+    params: &DepthParams,
+) -> Vec<f64> {
+    let luma = depth_img.to_luma8();
+    let scale_x = luma.width() as f64 / width.max(1) as f64;
+    let scale_y = luma.height() as f64 / height.max(1) as f64;
+
+    coords
+        .iter()
+        .map(|coord| {
+            let x = ((coord.x() as f64 + 0.5) * scale_x) as u32;
+            let y = ((coord.y() as f64 + 0.5) * scale_y) as u32;
+            let brightness = luma
+                .get_pixel(x.min(luma.width() - 1), y.min(luma.height() - 1))
+                .0[0] as f64 / 255.0;
+            let brightness = if params.invert { 1.0 - brightness } else { brightness };
+            params.min_depth + brightness * (params.max_depth - params.min_depth)
+        })
+        .collect()
+}
+
+/// Sets the depth of each normalized coordinate, for relief formations.
+///
+/// Coordinates without a matching depth are left on the formation's plane.
+pub fn apply_depths(coords: Vec<ExportCoordinate>, depths: &[f64]) -> Vec<ExportCoordinate> {
+    coords
+        .into_iter()
+        .enumerate()
+        .map(|(i, coord)| ExportCoordinate::new_3d(coord.x(), coord.y(), depths.get(i).copied().unwrap_or(0.0)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{GrayImage, Luma};
+
+    use super::*;
+
+    #[test]
+    fn depths_are_read_from_the_scaled_depth_image() {
+        // a depth image at half the resolution of the coordinates: black, mid gray and white columns
+        let depth_img = DynamicImage::ImageLuma8(GrayImage::from_fn(3, 1, |x, _| Luma([[0, 51, 255][x as usize]])));
+        let coords = [Coordinate::new(0, 1), Coordinate::new(3, 0), Coordinate::new(5, 1)];
+        let params = DepthParams { min_depth: 1.0, max_depth: 6.0, invert: false };
+
+        let depths = sample_depths(&depth_img, &coords, 6, 2, &params);
+        assert_eq!(depths, vec![1.0, 2.0, 6.0]);
+
+        let inverted = sample_depths(&depth_img, &coords, 6, 2, &DepthParams { invert: true, ..params });
+        assert_eq!(inverted, vec![6.0, 5.0, 1.0]);
+    }
+
+    #[test]
+    fn coordinates_without_a_depth_stay_on_the_plane() {
+        let coords = vec![ExportCoordinate::new(1.0, 2.0), ExportCoordinate::new(3.0, 4.0)];
+        let placed = apply_depths(coords, &[2.5]);
+        assert_eq!((placed[0].x(), placed[0].y(), placed[0].z()), (1.0, 2.0, 2.5));
+        assert_eq!(placed[1].z(), 0.0);
+    }
+}
@@ -0,0 +1,143 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use pyo3::{
+    create_exception,
+    exceptions::{PyKeyboardInterrupt, PyValueError},
+    prelude::*,
+};
+
+use crate::progress::Cancelled;
+
+create_exception!(
+    raster_drone,
+    RasterDroneError,
+    PyValueError,
+    "Base class of every error raised by raster_drone. It derives from ValueError, which the library raised for every error before."
+);
+create_exception!(raster_drone, ImageLoadError, RasterDroneError, "An input file couldn't be read.");
+create_exception!(
+    raster_drone,
+    DecodeError,
+    ImageLoadError,
+    "An input couldn't be decoded into an image, animation, mesh or font."
+);
+create_exception!(
+    raster_drone,
+    InvalidParameterError,
+    RasterDroneError,
+    "An argument is outside of its valid values, or doesn't apply to the input."
+);
+create_exception!(
+    raster_drone,
+    EmptyResultError,
+    RasterDroneError,
+    "A step of the pipeline left nothing to work with, such as an image without any candidate pixels."
+);
+create_exception!(raster_drone, ExportError, RasterDroneError, "The output couldn't be encoded or written.");
+
+/// Everything that can go wrong in the Python API.
+///
+/// Each variant is raised as one of the exceptions above, with the path involved and the
+/// underlying cause available as its `path` and `cause` attributes.
+#[derive(Debug)]
+pub enum Error {
+    /// An input file couldn't be read
+    Load { path: PathBuf, cause: String },
+    /// An input couldn't be decoded. `path` is `None` for images given in memory
+    Decode { path: Option<PathBuf>, cause: String },
+    InvalidParameter(String),
+    /// Nothing was left to sample or export
+    EmptyResult(String),
+    /// The output couldn't be encoded in the format of `path`
+    Export { path: PathBuf, cause: String },
+    /// The output couldn't be written to `path`
+    Io { path: PathBuf, cause: std::io::Error },
+    /// The run was cancelled before it finished, and is raised as a `KeyboardInterrupt`
+    Cancelled,
+}
+
+impl Error {
+    pub fn invalid_parameter(message: impl Into<String>) -> Self {
+        Self::InvalidParameter(message.into())
+    }
+
+    pub fn export(path: &Path, cause: String) -> Self {
+        Self::Export { path: path.to_path_buf(), cause }
+    }
+
+    fn path(&self) -> Option<&Path> {
+        match self {
+            Self::Load { path, .. } | Self::Export { path, .. } | Self::Io { path, .. } => Some(path),
+            Self::Decode { path, .. } => path.as_deref(),
+            Self::InvalidParameter(_) | Self::EmptyResult(_) | Self::Cancelled => None,
+        }
+    }
+
+    fn cause(&self) -> String {
+        match self {
+            Self::Load { cause, .. } | Self::Decode { cause, .. } | Self::Export { cause, .. } => cause.clone(),
+            Self::Io { cause, .. } => cause.to_string(),
+            Self::InvalidParameter(message) | Self::EmptyResult(message) => message.clone(),
+            Self::Cancelled => self.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Load { path, cause } => write!(f, "Unable to read '{}': {}", path.display(), cause),
+            Self::Decode { path: Some(path), cause } => write!(f, "Unable to decode '{}': {}", path.display(), cause),
+            Self::Decode { path: None, cause } => write!(f, "Unable to decode the image: {}", cause),
+            Self::InvalidParameter(message) | Self::EmptyResult(message) => write!(f, "{}", message),
+            Self::Export { path, cause } => write!(f, "Unable to export to '{}': {}", path.display(), cause),
+            Self::Io { path, cause } => write!(f, "Unable to create file in path '{}': {}", path.display(), cause),
+            Self::Cancelled => write!(f, "The run was cancelled."),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<Cancelled> for Error {
+    fn from(_: Cancelled) -> Self {
+        Self::Cancelled
+    }
+}
+
+impl From<Error> for PyErr {
+    fn from(error: Error) -> Self {
+        let message = error.to_string();
+        let err = match error {
+            Error::Load { .. } => ImageLoadError::new_err(message),
+            Error::Decode { .. } => DecodeError::new_err(message),
+            Error::InvalidParameter(_) => InvalidParameterError::new_err(message),
+            Error::EmptyResult(_) => EmptyResultError::new_err(message),
+            Error::Export { .. } | Error::Io { .. } => ExportError::new_err(message),
+            Error::Cancelled => PyKeyboardInterrupt::new_err(message),
+        };
+        Python::with_gil(|py| {
+            let value = err.value(py);
+            let path = error.path().map(|path| path.to_string_lossy().into_owned());
+            // setting attributes on a fresh exception instance can't fail in practice
+            let _ = value.setattr("path", path);
+            let _ = value.setattr("cause", error.cause());
+        });
+        err
+    }
+}
+
+/// Registers the exception classes on the Python module.
+pub fn register_exceptions(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    let py = m.py();
+    m.add("RasterDroneError", py.get_type::<RasterDroneError>())?;
+    m.add("ImageLoadError", py.get_type::<ImageLoadError>())?;
+    m.add("DecodeError", py.get_type::<DecodeError>())?;
+    m.add("InvalidParameterError", py.get_type::<InvalidParameterError>())?;
+    m.add("EmptyResultError", py.get_type::<EmptyResultError>())?;
+    m.add("ExportError", py.get_type::<ExportError>())?;
+    Ok(())
+}
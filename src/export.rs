@@ -0,0 +1,502 @@
+use std::path::Path;
+
+use image::{DynamicImage, ImageError};
+
+use crate::{
+    error::Error,
+    utils::{Coordinate, Coordinate3D, ExportCoordinate},
+};
+
+/// Normalizes a set of image coordinates into a square of side `max_dim`,
+/// preserving aspect ratio and centering the shorter axis.
+///
+/// The y-axis is flipped, so that the top of the image ends up at the top
+/// of the formation rather than at the bottom.
+///
+/// # Arguments
+/// * `coords` - A slice of `Coordinate` points in image space.
+/// * `max_dim` - The size of the largest dimension of the output, in meters.
+///
+/// # Returns
+/// A `Vec<ExportCoordinate>` in meters, or an error message if the coordinates
+/// are empty or all identical.
+pub fn normalize_coordinates(coords: &[Coordinate], max_dim: f64) -> Result<Vec<ExportCoordinate>, String> {
+    let bounds = bounding_box(coords.iter())?;
+    Ok(normalize_with_bounds(coords, bounds, max_dim))
+}
+
+/// Normalizes several formations of the same show like `normalize_coordinates`, using
+/// one bounding box across all of them so that they share the same scale and origin.
+pub fn normalize_frames(frames: &[Vec<Coordinate>], max_dim: f64) -> Result<Vec<Vec<ExportCoordinate>>, String> {
+    let bounds = bounding_box(frames.iter().flatten())?;
+    Ok(frames
+        .iter()
+        .map(|coords| normalize_with_bounds(coords, bounds, max_dim))
+        .collect())
+}
+
+/// Normalizes a set of 3D coordinates into a cube of side `max_dim`, preserving proportions.
+///
+/// Like `normalize_coordinates`, x and y are centered within `[0, max_dim]`, while the
+/// depth is centered on 0.0 so that the middle of the shape sits on the formation's plane.
+pub fn normalize_coordinates_3d(coords: &[Coordinate3D], max_dim: f64) -> Result<Vec<ExportCoordinate>, String> {
+    let Some(first) = coords.first() else {
+        return Err("No coordinates to export".to_string());
+    };
+    let mut min = [first.x(), first.y(), first.z()];
+    let mut max = min;
+    for coord in coords {
+        for (axis, value) in [coord.x(), coord.y(), coord.z()].into_iter().enumerate() {
+            min[axis] = min[axis].min(value);
+            max[axis] = max[axis].max(value);
+        }
+    }
+
+    let max_range = (0..3).map(|axis| max[axis] - min[axis]).fold(0.0, f64::max);
+    if max_range == 0.0 {
+        return Err("All coordinates are identical".to_string());
+    }
+
+    let scale_factor = max_dim / max_range;
+    let offset_x = (max_dim - (max[0] - min[0]) * scale_factor) / 2.0;
+    let offset_y = (max_dim - (max[1] - min[1]) * scale_factor) / 2.0;
+    let center_z = (min[2] + max[2]) / 2.0;
+
+    Ok(coords
+        .iter()
+        .map(|coord| ExportCoordinate::new_3d(
+            (coord.x() - min[0]) * scale_factor + offset_x,
+            (coord.y() - min[1]) * scale_factor + offset_y,
+            (coord.z() - center_z) * scale_factor,
+        ))
+        .collect())
+}
+
+/// Encodes a formation in the file format matching the extension of `path`:
+/// a Skybrush CSV for `.csv`, a binary glTF for `.glb` or a point cloud for `.ply`.
+pub fn encode_by_extension(path: &std::path::Path, coords: Vec<ExportCoordinate>, colors: &[[u8; 3]]) -> Result<Vec<u8>, String> {
+    let extension = path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase());
+    match extension.as_deref() {
+        Some("csv") => skybrush_csv(&coords, colors),
+        Some("glb") => gltf_binary(&[coords], colors, &GltfParams::default()),
+        Some("ply") => Ok(ply_point_cloud(&coords, colors).into_bytes()),
+        _ => Err("The valid extensions for `output_path` are '.csv', '.glb' and '.ply'.".to_string()),
+    }
+}
+
+/// Finds `(min_x, max_x, min_y, max_y)` over a set of coordinates.
+fn bounding_box<'a>(mut coords: impl Iterator<Item = &'a Coordinate>) -> Result<(u32, u32, u32, u32), String> {
+    // Initialize with the first coordinate's values
+    let Some(first) = coords.next() else {
+        return Err("No coordinates to export".to_string());
+    };
+    let bounds = coords.fold(
+        (first.x(), first.x(), first.y(), first.y()),
+        |mut acc, coord| {
+            let x = coord.x();
+            let y = coord.y();
+            if x < acc.0 { acc.0 = x; }
+            if x > acc.1 { acc.1 = x; }
+            if y < acc.2 { acc.2 = y; }
+            if y > acc.3 { acc.3 = y; }
+            acc
+        },
+    );
+
+    // Handle edge case where all points are identical
+    if bounds.0 == bounds.1 && bounds.2 == bounds.3 {
+        return Err("All coordinates are identical".to_string());
+    }
+    Ok(bounds)
+}
+
+fn normalize_with_bounds(
+    coords: &[Coordinate],
+    (min_x, max_x, min_y, max_y): (u32, u32, u32, u32),
+    max_dim: f64,
+) -> Vec<ExportCoordinate> {
+    let x_space = max_x - min_x;
+    let y_space = max_y - min_y;
+    let max_range = x_space.max(y_space);
+
+    let scale_factor = 1.0 / max_range as f64;
+    let new_width = x_space as f64 * scale_factor;
+    let new_height = y_space as f64 * scale_factor;
+    let offset_x = (1.0 - new_width) / 2.0;
+    let offset_y = (1.0 - new_height) / 2.0;
+
+    coords
+        .iter()
+        .map(|coord| {
+            let normalized_x = (coord.x() as f64 - min_x as f64) * scale_factor + offset_x;
+            // Flip y-axis (1.0 - ...)
+            let normalized_y = 1.0 - ((coord.y() as f64 - min_y as f64) * scale_factor + offset_y);
+
+            // Scale to the final desired dimension
+            ExportCoordinate::new(normalized_x * max_dim, normalized_y * max_dim)
+        })
+        .collect()
+}
+
+/// Builds a static CSV compatible with Skybrush Studio, with the following structure:
+/// Name, x_m (x in meters), y_m, z_m, Red, Green, Blue
+///
+/// The formation's plane is the vertical y-z plane, so its depth goes on the x axis.
+/// If `colors` is shorter than `coords`, the remaining drones are white.
+pub fn skybrush_csv(coords: &[ExportCoordinate], colors: &[[u8; 3]]) -> Result<Vec<u8>, String> {
+    let mut wtr = csv::Writer::from_writer(vec![]);
+
+    wtr.write_record([
+        "Name", "x_m", "y_m", "z_m", "Red", "Green", "Blue"
+    ]).map_err(|e| format!("CSV error: {}", e))?;
+
+    for (count, coord) in coords.iter().enumerate() {
+        let [red, green, blue] = colors.get(count).copied().unwrap_or([255; 3]);
+        wtr.write_record(&[
+            format!("Drone{}", count + 1),
+            coord.z().to_string(),
+            coord.x().to_string(),
+            coord.y().to_string(),
+            red.to_string(),
+            green.to_string(),
+            blue.to_string(),
+        ]).map_err(|e| format!("CSV error: {}", e))?;
+    }
+
+    wtr.into_inner().map_err(|e| format!("CSV error: {}", e))
+}
+
+/// Converts a normalized formation point into Skybrush world axes (x depth, y right, z up).
+fn to_world(coord: &ExportCoordinate) -> [f64; 3] {
+    [coord.z(), coord.x(), coord.y()]
+}
+
+/// Builds an ASCII PLY point cloud with one colored vertex per drone, in Skybrush world axes.
+///
+/// If `colors` is shorter than `coords`, the remaining drones are white.
+pub fn ply_point_cloud(coords: &[ExportCoordinate], colors: &[[u8; 3]]) -> String {
+    let mut ply = String::from("ply\nformat ascii 1.0\n");
+    ply.push_str(&format!("element vertex {}\n", coords.len()));
+    ply.push_str("property float x\nproperty float y\nproperty float z\n");
+    ply.push_str("property uchar red\nproperty uchar green\nproperty uchar blue\n");
+    ply.push_str("end_header\n");
+
+    for (i, coord) in coords.iter().enumerate() {
+        let [x, y, z] = to_world(coord);
+        let [r, g, b] = colors.get(i).copied().unwrap_or([255; 3]);
+        ply.push_str(&format!("{} {} {} {} {} {}\n", x, y, z, r, g, b));
+    }
+    ply
+}
+
+/// Settings for the glTF previsualization export.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct GltfParams {
+    /// Radius of the marker mesh drawn for each drone, in meters.
+    pub marker_radius: f32,
+    /// Time in seconds between consecutive formations when animating.
+    pub seconds_per_formation: f32,
+}
+
+impl Default for GltfParams {
+    fn default() -> Self {
+        Self {
+            marker_radius: 0.1,
+            seconds_per_formation: 5.0,
+        }
+    }
+}
+
+/// Builds a binary glTF (`.glb`) with one node per drone, positioned in meters.
+///
+/// Each drone is drawn as a small octahedron with an emissive material of its color.
+/// When more than one formation is given, every drone gets a keyframed translation track
+/// moving it through the formations in order, `seconds_per_formation` apart. Drone `i`
+/// is expected at index `i` of every formation.
+///
+/// Positions are converted from Skybrush world axes to the y-up convention of glTF, so that
+/// the formation lands in the same place as a Skybrush CSV once imported into Blender.
+pub fn gltf_binary(
+    formations: &[Vec<ExportCoordinate>],
+    colors: &[[u8; 3]],
+    params: &GltfParams,
+) -> Result<Vec<u8>, String> {
+    let Some(first) = formations.first() else {
+        return Err("No formations to export".to_string());
+    };
+    let drone_count = first.len();
+    if drone_count == 0 {
+        return Err("No coordinates to export".to_string());
+    }
+    if formations.iter().any(|formation| formation.len() != drone_count) {
+        return Err("Every formation must have the same number of drones".to_string());
+    }
+
+    let gltf_position = |coord: &ExportCoordinate| -> [f32; 3] {
+        let [x, y, z] = to_world(coord);
+        [x as f32, z as f32, -y as f32]
+    };
+
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut buffer_views = Vec::new();
+    let mut accessors = Vec::new();
+
+    // appends raw bytes as a new, 4-byte aligned buffer view, returning its index
+    let mut push_view = |buffer: &mut Vec<u8>, bytes: &[u8]| -> usize {
+        while !buffer.len().is_multiple_of(4) {
+            buffer.push(0);
+        }
+        buffer_views.push(serde_json::json!({
+            "buffer": 0,
+            "byteOffset": buffer.len(),
+            "byteLength": bytes.len(),
+        }));
+        buffer.extend_from_slice(bytes);
+        buffer_views.len() - 1
+    };
+
+    // --- Marker mesh: an octahedron shared by every drone ---
+    let r = params.marker_radius;
+    let vertices: [[f32; 3]; 6] = [
+        [r, 0.0, 0.0], [-r, 0.0, 0.0],
+        [0.0, r, 0.0], [0.0, -r, 0.0],
+        [0.0, 0.0, r], [0.0, 0.0, -r],
+    ];
+    let indices: [u16; 24] = [
+        0, 2, 4, 2, 1, 4, 1, 3, 4, 3, 0, 4,
+        2, 0, 5, 1, 2, 5, 3, 1, 5, 0, 3, 5,
+    ];
+    let vertex_view = push_view(&mut buffer, bytemuck::cast_slice(&vertices));
+    accessors.push(serde_json::json!({
+        "bufferView": vertex_view,
+        "componentType": 5126, // FLOAT
+        "count": vertices.len(),
+        "type": "VEC3",
+        "min": [-r, -r, -r],
+        "max": [r, r, r],
+    }));
+    let index_view = push_view(&mut buffer, bytemuck::cast_slice(&indices));
+    accessors.push(serde_json::json!({
+        "bufferView": index_view,
+        "componentType": 5123, // UNSIGNED_SHORT
+        "count": indices.len(),
+        "type": "SCALAR",
+    }));
+
+    // --- One material and mesh per distinct color ---
+    let mut palette: Vec<[u8; 3]> = Vec::new();
+    let drone_mesh: Vec<usize> = (0..drone_count)
+        .map(|i| {
+            let color = colors.get(i).copied().unwrap_or([255; 3]);
+            match palette.iter().position(|&c| c == color) {
+                Some(index) => index,
+                None => {
+                    palette.push(color);
+                    palette.len() - 1
+                }
+            }
+        })
+        .collect();
+
+    let materials: Vec<serde_json::Value> = palette
+        .iter()
+        .map(|[r, g, b]| {
+            let rgb = [*r as f32 / 255.0, *g as f32 / 255.0, *b as f32 / 255.0];
+            serde_json::json!({
+                "name": format!("LED_{:02X}{:02X}{:02X}", r, g, b),
+                "pbrMetallicRoughness": {
+                    "baseColorFactor": [rgb[0], rgb[1], rgb[2], 1.0],
+                    "metallicFactor": 0.0,
+                },
+                "emissiveFactor": rgb,
+            })
+        })
+        .collect();
+
+    let meshes: Vec<serde_json::Value> = (0..palette.len())
+        .map(|material| serde_json::json!({
+            "primitives": [{
+                "attributes": { "POSITION": 0 },
+                "indices": 1,
+                "material": material,
+            }],
+        }))
+        .collect();
+
+    let nodes: Vec<serde_json::Value> = first
+        .iter()
+        .enumerate()
+        .map(|(i, coord)| serde_json::json!({
+            "name": format!("Drone{}", i + 1),
+            "mesh": drone_mesh[i],
+            "translation": gltf_position(coord),
+        }))
+        .collect();
+
+    let mut root = serde_json::json!({
+        "asset": { "version": "2.0", "generator": "raster_drone" },
+        "scene": 0,
+        "scenes": [{ "nodes": (0..drone_count).collect::<Vec<_>>() }],
+        "nodes": nodes,
+        "meshes": meshes,
+        "materials": materials,
+    });
+
+    // --- Keyframed animation through the formations ---
+    if formations.len() > 1 {
+        let times: Vec<f32> = (0..formations.len())
+            .map(|i| i as f32 * params.seconds_per_formation)
+            .collect();
+        let time_view = push_view(&mut buffer, bytemuck::cast_slice(&times));
+        accessors.push(serde_json::json!({
+            "bufferView": time_view,
+            "componentType": 5126,
+            "count": times.len(),
+            "type": "SCALAR",
+            "min": [times[0]],
+            "max": [times[times.len() - 1]],
+        }));
+        let time_accessor = accessors.len() - 1;
+
+        let mut samplers = Vec::with_capacity(drone_count);
+        let mut channels = Vec::with_capacity(drone_count);
+        for drone in 0..drone_count {
+            let track: Vec<[f32; 3]> = formations
+                .iter()
+                .map(|formation| gltf_position(&formation[drone]))
+                .collect();
+            let track_view = push_view(&mut buffer, bytemuck::cast_slice(&track));
+            accessors.push(serde_json::json!({
+                "bufferView": track_view,
+                "componentType": 5126,
+                "count": track.len(),
+                "type": "VEC3",
+            }));
+            samplers.push(serde_json::json!({
+                "input": time_accessor,
+                "output": accessors.len() - 1,
+                "interpolation": "LINEAR",
+            }));
+            channels.push(serde_json::json!({
+                "sampler": drone,
+                "target": { "node": drone, "path": "translation" },
+            }));
+        }
+        root["animations"] = serde_json::json!([{
+            "name": "Show",
+            "samplers": samplers,
+            "channels": channels,
+        }]);
+    }
+
+    while !buffer.len().is_multiple_of(4) {
+        buffer.push(0);
+    }
+    root["bufferViews"] = serde_json::Value::Array(buffer_views);
+    root["accessors"] = serde_json::Value::Array(accessors);
+    root["buffers"] = serde_json::json!([{ "byteLength": buffer.len() }]);
+
+    let mut json = serde_json::to_vec(&root).map_err(|e| format!("glTF error: {}", e))?;
+    while !json.len().is_multiple_of(4) {
+        json.push(b' ');
+    }
+
+    // --- GLB container: header, JSON chunk, BIN chunk ---
+    let total_length = 12 + 8 + json.len() + 8 + buffer.len();
+    let mut glb = Vec::with_capacity(total_length);
+    glb.extend_from_slice(b"glTF");
+    glb.extend_from_slice(&2u32.to_le_bytes());
+    glb.extend_from_slice(&(total_length as u32).to_le_bytes());
+    glb.extend_from_slice(&(json.len() as u32).to_le_bytes());
+    glb.extend_from_slice(b"JSON");
+    glb.extend_from_slice(&json);
+    glb.extend_from_slice(&(buffer.len() as u32).to_le_bytes());
+    glb.extend_from_slice(b"BIN\0");
+    glb.extend_from_slice(&buffer);
+
+    Ok(glb)
+}
+
+/// Writes `data` to `path`, creating the intermediate directories if necessary.
+pub fn write_output(path: &Path, data: &[u8]) -> Result<(), Error> {
+    create_parent_dirs(path)?;
+    std::fs::write(path, data).map_err(|cause| Error::Io { path: path.to_path_buf(), cause })
+}
+
+/// Saves an image in the format matching the extension of `path`, creating the intermediate
+/// directories if necessary.
+pub fn save_image(path: &Path, img: &DynamicImage) -> Result<(), Error> {
+    create_parent_dirs(path)?;
+    img.save(path).map_err(|e| match e {
+        ImageError::IoError(cause) => Error::Io { path: path.to_path_buf(), cause },
+        e => Error::Export { path: path.to_path_buf(), cause: e.to_string() },
+    })
+}
+
+fn create_parent_dirs(path: &Path) -> Result<(), Error> {
+    match path.parent() {
+        Some(prefix) => std::fs::create_dir_all(prefix)
+            .map_err(|cause| Error::Io { path: prefix.to_path_buf(), cause }),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn formation() -> Vec<ExportCoordinate> {
+        (0..5).map(|i| ExportCoordinate::new_3d(i as f64, 2.0 * i as f64, 0.5)).collect()
+    }
+
+    /// The JSON chunk of a binary glTF.
+    fn gltf_json(glb: &[u8]) -> serde_json::Value {
+        assert_eq!(&glb[..4], b"glTF");
+        assert_eq!(u32::from_le_bytes(glb[8..12].try_into().unwrap()) as usize, glb.len());
+        let json_length = u32::from_le_bytes(glb[12..16].try_into().unwrap()) as usize;
+        assert_eq!(&glb[16..20], b"JSON");
+        serde_json::from_slice(&glb[20..20 + json_length]).unwrap()
+    }
+
+    #[test]
+    fn every_format_keeps_every_drone() {
+        let colors = [[255, 0, 0], [0, 255, 0]];
+
+        let csv = encode_by_extension(Path::new("show.CSV"), formation(), &colors).unwrap();
+        let mut reader = csv::Reader::from_reader(csv.as_slice());
+        let rows: Vec<csv::StringRecord> = reader.records().map(|row| row.unwrap()).collect();
+        assert_eq!(rows.len(), 5);
+        assert_eq!(rows[1].iter().skip(4).collect::<Vec<_>>(), ["0", "255", "0"]);
+        assert_eq!(rows[4].iter().skip(4).collect::<Vec<_>>(), ["255", "255", "255"]);
+
+        let ply = String::from_utf8(encode_by_extension(Path::new("show.ply"), formation(), &colors).unwrap()).unwrap();
+        let (header, body) = ply.split_once("end_header\n").unwrap();
+        assert!(header.contains("element vertex 5\n"));
+        assert_eq!(body.lines().count(), 5);
+        // Skybrush axes: the depth goes first
+        assert_eq!(body.lines().nth(1), Some("0.5 1 2 0 255 0"));
+
+        let glb = encode_by_extension(Path::new("show.glb"), formation(), &colors).unwrap();
+        let json = gltf_json(&glb);
+        assert_eq!(json["nodes"].as_array().unwrap().len(), 5);
+        assert_eq!(json["materials"].as_array().unwrap().len(), 3);
+        assert!(json.get("animations").is_none());
+
+        assert!(encode_by_extension(Path::new("show.txt"), formation(), &colors).is_err());
+    }
+
+    #[test]
+    fn animated_gltf_has_a_keyframe_per_formation() {
+        let formations = vec![formation(); 3];
+        let json = gltf_json(&gltf_binary(&formations, &[], &GltfParams::default()).unwrap());
+        let channels = json["animations"][0]["channels"].as_array().unwrap();
+        assert_eq!(channels.len(), 5);
+        let track = json["animations"][0]["samplers"][0]["output"].as_u64().unwrap() as usize;
+        assert_eq!(json["accessors"][track]["count"], 3);
+
+        let uneven = vec![formation(), formation()[..4].to_vec()];
+        assert!(gltf_binary(&uneven, &[], &GltfParams::default()).is_err());
+    }
+}
@@ -0,0 +1,218 @@
+use image::{imageops, DynamicImage, Rgba, RgbaImage};
+
+/// A rectangle of an image, in pixels.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct CropRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Geometric changes applied to the source image before any thresholding.
+///
+/// The steps run in the order of the fields: crop, rotation, flips, then padding.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct TransformParams {
+    /// Region of the source image to keep. `None` keeps the whole image.
+    pub crop: Option<CropRect>,
+    /// Clockwise rotation in degrees. The canvas grows to fit the rotated image.
+    pub rotation: f32,
+    pub flip_horizontal: bool,
+    pub flip_vertical: bool,
+    /// Empty border added around the image, in pixels.
+    pub padding: u32,
+}
+
+impl Default for TransformParams {
+    fn default() -> Self {
+        Self {
+            crop: None,
+            rotation: 0.0,
+            flip_horizontal: false,
+            flip_vertical: false,
+            padding: 0,
+        }
+    }
+}
+
+impl TransformParams {
+    /// Whether these settings change the image at all.
+    pub fn is_identity(&self) -> bool {
+        self.crop.is_none()
+            && self.rotation % 360.0 == 0.0
+            && !self.flip_horizontal
+            && !self.flip_vertical
+            && self.padding == 0
+    }
+}
+
+/// Applies the crop, rotation, flip and padding steps to an image.
+///
+/// Areas uncovered by the rotation and the padding are fully transparent, so that they
+/// have no brightness and never become candidate pixels, whatever the image type.
+pub fn transform_image(img: &DynamicImage, params: &TransformParams) -> DynamicImage {
+    let mut rgba = img.to_rgba8();
+
+    if let Some(crop) = params.crop {
+        // clamp the rectangle to the image, keeping at least one pixel
+        let x = crop.x.min(rgba.width().saturating_sub(1));
+        let y = crop.y.min(rgba.height().saturating_sub(1));
+        let width = crop.width.clamp(1, rgba.width() - x);
+        let height = crop.height.clamp(1, rgba.height() - y);
+        rgba = imageops::crop_imm(&rgba, x, y, width, height).to_image();
+    }
+
+    if params.rotation % 360.0 != 0.0 {
+        rgba = rotate(&rgba, params.rotation);
+    }
+    if params.flip_horizontal {
+        imageops::flip_horizontal_in_place(&mut rgba);
+    }
+    if params.flip_vertical {
+        imageops::flip_vertical_in_place(&mut rgba);
+    }
+
+    if params.padding > 0 {
+        let mut padded = RgbaImage::new(
+            rgba.width() + 2 * params.padding,
+            rgba.height() + 2 * params.padding,
+        );
+        imageops::replace(&mut padded, &rgba, params.padding as i64, params.padding as i64);
+        rgba = padded;
+    }
+
+    DynamicImage::ImageRgba8(rgba)
+}
+
+/// Rotates an image clockwise by an arbitrary angle with bilinear filtering, growing the
+/// canvas so that no part of the image is cut off.
+fn rotate(img: &RgbaImage, degrees: f32) -> RgbaImage {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let (width, height) = img.dimensions();
+    let (w, h) = (width as f32, height as f32);
+
+    let new_width = (w * cos.abs() + h * sin.abs()).round().max(1.0) as u32;
+    let new_height = (w * sin.abs() + h * cos.abs()).round().max(1.0) as u32;
+    let (cx, cy) = (w / 2.0, h / 2.0);
+    let (new_cx, new_cy) = (new_width as f32 / 2.0, new_height as f32 / 2.0);
+
+    // reads a pixel, treating everything outside the image as transparent
+    let pixel = |x: i64, y: i64| -> [f32; 4] {
+        if x < 0 || y < 0 || x >= width as i64 || y >= height as i64 {
+            [0.0; 4]
+        } else {
+            img.get_pixel(x as u32, y as u32).0.map(|c| c as f32)
+        }
+    };
+
+    RgbaImage::from_fn(new_width, new_height, |x, y| {
+        // map each output pixel center back into the source image
+        let dx = x as f32 + 0.5 - new_cx;
+        let dy = y as f32 + 0.5 - new_cy;
+        let sx = cos * dx + sin * dy + cx - 0.5;
+        let sy = -sin * dx + cos * dy + cy - 0.5;
+
+        let (x0, y0) = (sx.floor(), sy.floor());
+        let (tx, ty) = (sx - x0, sy - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let corners = [
+            (pixel(x0, y0), (1.0 - tx) * (1.0 - ty)),
+            (pixel(x0 + 1, y0), tx * (1.0 - ty)),
+            (pixel(x0, y0 + 1), (1.0 - tx) * ty),
+            (pixel(x0 + 1, y0 + 1), tx * ty),
+        ];
+
+        let mut output = [0.0f32; 4];
+        for (value, weight) in corners {
+            for channel in 0..4 {
+                output[channel] += value[channel] * weight;
+            }
+        }
+        Rgba(output.map(|c| c.round().clamp(0.0, 255.0) as u8))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::{prepare_image, PreprocessingParams};
+
+    /// A 4x2 image whose red channel holds the index of each pixel.
+    fn numbered() -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(4, 2, |x, y| Rgba([(y * 4 + x) as u8, 0, 0, 255])))
+    }
+
+    fn transformed(params: TransformParams) -> RgbaImage {
+        transform_image(&numbered(), &params).to_rgba8()
+    }
+
+    #[test]
+    fn crop_rectangles_are_clamped_to_the_image() {
+        let crop = |x, y, width, height| transformed(TransformParams {
+            crop: Some(CropRect { x, y, width, height }),
+            ..TransformParams::default()
+        });
+
+        let inside = crop(1, 0, 2, 1);
+        assert_eq!(inside.dimensions(), (2, 1));
+        assert_eq!(inside.get_pixel(0, 0)[0], 1);
+
+        // overflowing rectangles stop at the edge, and at least one pixel is kept
+        assert_eq!(crop(2, 1, 10, 10).dimensions(), (2, 1));
+        assert_eq!(crop(9, 9, 10, 10).dimensions(), (1, 1));
+        assert_eq!(crop(0, 0, 0, 0).dimensions(), (1, 1));
+    }
+
+    #[test]
+    fn rotation_grows_the_canvas_to_fit() {
+        let rotated = |rotation| transformed(TransformParams { rotation, ..TransformParams::default() });
+
+        let quarter = rotated(90.0);
+        assert_eq!(quarter.dimensions(), (2, 4));
+        // clockwise, the bottom left corner moves to the top left
+        assert_eq!(quarter.get_pixel(0, 0)[0], 4);
+        assert_eq!(quarter.get_pixel(1, 0)[0], 0);
+
+        // 45 degrees takes (4 + 2) / sqrt(2) pixels each way
+        assert_eq!(rotated(45.0).dimensions(), (4, 4));
+        assert_eq!(transformed(TransformParams { rotation: 360.0, ..TransformParams::default() }), numbered().to_rgba8());
+    }
+
+    #[test]
+    fn flips_mirror_known_pixels() {
+        let horizontal = transformed(TransformParams { flip_horizontal: true, ..TransformParams::default() });
+        assert_eq!(horizontal.get_pixel(0, 0)[0], 3);
+        assert_eq!(horizontal.get_pixel(3, 1)[0], 4);
+
+        let vertical = transformed(TransformParams { flip_vertical: true, ..TransformParams::default() });
+        assert_eq!(vertical.get_pixel(0, 0)[0], 4);
+        assert_eq!(vertical.get_pixel(3, 1)[0], 3);
+    }
+
+    #[test]
+    fn padding_adds_a_transparent_border() {
+        let padded = transformed(TransformParams { padding: 2, ..TransformParams::default() });
+        assert_eq!(padded.dimensions(), (8, 6));
+        assert_eq!(padded.get_pixel(0, 0), &Rgba([0, 0, 0, 0]));
+        assert_eq!(padded.get_pixel(7, 5), &Rgba([0, 0, 0, 0]));
+        assert_eq!(padded.get_pixel(2, 2), &Rgba([0, 0, 0, 255]));
+        assert_eq!(padded.get_pixel(5, 3), &Rgba([7, 0, 0, 255]));
+    }
+
+    #[test]
+    fn rotation_corners_are_masked_out() {
+        let img = DynamicImage::ImageRgba8(RgbaImage::from_pixel(20, 20, Rgba([200, 200, 200, 255])));
+        let params = PreprocessingParams {
+            transform: TransformParams { rotation: 45.0, ..TransformParams::default() },
+            ..PreprocessingParams::default()
+        };
+        let prepared = prepare_image(&params, &img).to_rgba8();
+
+        // the canvas grows to fit, leaving transparent corners that the default mask clears
+        assert_eq!(prepared.dimensions(), (28, 28));
+        assert_eq!(prepared.get_pixel(0, 0), &Rgba([0, 0, 0, 0]));
+        let center = prepared.get_pixel(prepared.width() / 2, prepared.height() / 2);
+        assert_eq!(center[3], 255);
+    }
+}
@@ -6,15 +6,24 @@ use egui_winit::winit::{
     window::Window
 };
 use std::sync::Arc;
+use log::{info, warn};
 use egui_wgpu::Renderer as EguiRenderer;
 use egui_winit::State as EguiState;
 
 use crate::{
+    crazyswarm::CrazyswarmParams,
+    depth::DepthParams,
     gui::{
-        menu::{populate_slider_menu, populate_upload_menu, ExportUnit}, 
-        pipeline::{run_preprocessing_stage, run_sampling_stage, PreprocessingParams, SamplingParams} 
+        crop::ui_crop_tool,
+        menu::{populate_slider_menu, populate_upload_menu, ui_log_panel, ExportFormat, ExportUnit}, 
+        pipeline::{apply_tune_result, AutoTuneJob, JobInput, PipelineJob} 
     }, 
-    utils::{Coordinate, CoordinateOutput}};
+    metrics::FormationMetrics,
+    pipeline::{Candidates, PreprocessingParams, SamplingParams},
+    stats::RunStats,
+    text::TextLayout,
+    tune::TuneResult,
+    utils::Coordinate};
 
 // Shader code is embedded directly into the binary for simplicity.
 const SHADER_CODE: &str = include_str!("lights.wgsl");
@@ -250,8 +259,28 @@ pub struct AppState {
     // --- Data ---
     // The raw image data is now stored in memory after being loaded.
     pub image: Option<image::DynamicImage>,
-    pub intermediate_coords: Option<CoordinateOutput>,
+    pub intermediate_coords: Option<Candidates>,
     pub final_light_coords: Vec<Coordinate>,
+    /// Statistics of the run that produced `final_light_coords`
+    pub run_stats: Option<RunStats>,
+    /// Scores of `final_light_coords` against `intermediate_coords`
+    pub formation_metrics: Option<FormationMetrics>,
+    /// The run of the pipeline stages in progress, if any
+    pub pipeline_job: Option<PipelineJob>,
+    /// The params of the last run cancelled from the progress bar, which isn't started again
+    /// until the params change
+    pub cancelled_params: Option<(PreprocessingParams, SamplingParams)>,
+    /// The search started by the Auto button, if it is still running
+    pub auto_tune_job: Option<AutoTuneJob>,
+    /// The best settings found by the last search, best first
+    pub tune_results: Vec<TuneResult>,
+
+    /// Whether the viewport shows the source image to drag a crop box over it
+    pub crop_tool_active: bool,
+    /// The source image uploaded for the crop tool, dropped whenever a new image is loaded
+    pub crop_texture: Option<egui::TextureHandle>,
+    /// Start and current corners of the crop box being dragged, in source image pixels
+    pub crop_drag: Option<(egui::Pos2, egui::Pos2)>,
 
     /// Toggles the visibility of the export settings panel
     pub show_export_panel: bool,
@@ -259,8 +288,37 @@ pub struct AppState {
     pub export_size_str: String,
     /// Stores the selected unit (Meters or Feet)
     pub export_unit: ExportUnit,
+    /// Stores the selected output file format
+    pub export_format: ExportFormat,
     /// Stores any error message from parsing the export size
     pub export_error_msg: Option<String>,
+    /// Whether the export gives the formation depth from a depth image
+    pub use_depth: bool,
+    /// Depth range and direction used when `use_depth` is set
+    pub depth_params: DepthParams,
+    /// A separately loaded depth image. When `None`, the luminance of the source image is used
+    pub depth_image: Option<image::DynamicImage>,
+
+    /// Toggles the visibility of the Crazyswarm export panel
+    pub show_crazyswarm_panel: bool,
+    /// Indoor scaling and spacing settings for the Crazyswarm export
+    pub crazyswarm_params: CrazyswarmParams,
+    /// Stores any error message from the Crazyswarm export
+    pub crazyswarm_error_msg: Option<String>,
+
+    /// Toggles the visibility of the text source panel
+    pub show_text_panel: bool,
+    /// The string rendered by the text source
+    pub text_input: String,
+    /// The font file used by the text source
+    pub font_path: Option<std::path::PathBuf>,
+    /// Size, alignment and spacing of the rendered text
+    pub text_layout: TextLayout,
+    /// Stores any error message from rendering the text
+    pub text_error_msg: Option<String>,
+
+    /// Whether the status bar is expanded to show the recent log messages
+    pub show_log_panel: bool,
 }
 
 impl AppState {
@@ -274,13 +332,48 @@ impl AppState {
             image: None,
             intermediate_coords: None,
             final_light_coords: Vec::new(),
+            run_stats: None,
+            formation_metrics: None,
+            pipeline_job: None,
+            cancelled_params: None,
+            auto_tune_job: None,
+            tune_results: Vec::new(),
+
+            crop_tool_active: false,
+            crop_texture: None,
+            crop_drag: None,
 
             show_export_panel: false,
             export_size_str: "20.0".to_string(), // Default to a sensible value
             export_unit: ExportUnit::Meters,
+            export_format: ExportFormat::SkybrushCsv,
             export_error_msg: None,
+            use_depth: false,
+            depth_params: DepthParams::default(),
+            depth_image: None,
+
+            show_crazyswarm_panel: false,
+            crazyswarm_params: CrazyswarmParams::default(),
+            crazyswarm_error_msg: None,
+
+            show_text_panel: false,
+            text_input: "HAPPY 2027".to_string(),
+            font_path: None,
+            text_layout: TextLayout::default(),
+            text_error_msg: None,
+
+            show_log_panel: false,
         }
     }
+
+    /// Stops the search of the Auto button and forgets its results, as they belong to the
+    /// image that was searched.
+    pub fn reset_auto_tune(&mut self) {
+        if let Some(job) = self.auto_tune_job.take() {
+            job.progress.cancel();
+        }
+        self.tune_results.clear();
+    }
 }
 
 
@@ -348,12 +441,20 @@ pub async fn run_app() {
                             }
                         });
 
+                        // The status bar goes under the viewport, so that it doesn't cover the lights.
+                        egui::TopBottomPanel::bottom("log_panel").show(&egui_ctx, |ui| {
+                            ui_log_panel(ui, &mut app_state);
+                        });
+
                         // Create the central panel to fill the remaining space.
                         egui::CentralPanel::default()
                             // make it transparent, otherwise it will draw over
                             .frame(egui::Frame::none())
                             .show(&egui_ctx, |ui| {
                             viewport_rect = ui.available_rect_before_wrap();
+                            if app_state.crop_tool_active {
+                                ui_crop_tool(ui, viewport_rect, &mut app_state);
+                            }
                         });
                         
                         let egui_output = egui_ctx.end_frame();
@@ -385,7 +486,7 @@ pub async fn run_app() {
                         // --- Get Surface Texture for Drawing ---
                         let output_frame = match render_state.surface.get_current_texture() {
                             Ok(frame) => frame,
-                            Err(e) => { eprintln!("Dropped frame: {:?}", e); return; }
+                            Err(e) => { warn!("Dropped frame: {:?}", e); return; }
                         };
                         let output_view = output_frame.texture.create_view(
                             &wgpu::TextureViewDescriptor::default()
@@ -403,22 +504,58 @@ pub async fn run_app() {
                         // --- Conditional Rendering Logic ---
                         if app_state.image.is_some() {
                             // --- Update Pipelines if Params Changed ---
-                            let mut force_resample = false;
-                            if app_state.preprocessing_params != app_state.cached_preprocessing_params {
-                                app_state.intermediate_coords = run_preprocessing_stage(
-                                    &app_state.preprocessing_params, 
-                                    &app_state.image
-                                );
-                                app_state.cached_preprocessing_params = app_state.preprocessing_params;
-                                force_resample = true;
+                            // the stages run in the background, and a run whose params changed
+                            // since it started is cancelled, to start again once it has stopped
+                            let current_params = (app_state.preprocessing_params, app_state.sampling_params);
+                            if let Some(job) = &app_state.pipeline_job {
+                                if (job.preprocessing_params, job.sampling_params) != current_params {
+                                    job.progress.cancel();
+                                }
+                            }
+                            if app_state.pipeline_job.as_ref().is_some_and(|job| job.is_finished()) {
+                                let job = app_state.pipeline_job.take().expect("the job was just checked");
+                                let params = (job.preprocessing_params, job.sampling_params);
+                                if let Ok(output) = job.join() {
+                                    app_state.intermediate_coords = output.intermediate_coords;
+                                    app_state.final_light_coords = output.final_light_coords;
+                                    app_state.run_stats = output.stats;
+                                    app_state.formation_metrics = output.metrics;
+                                    (app_state.cached_preprocessing_params, app_state.cached_sampling_params) = params;
+                                }
                             }
 
-                            if force_resample || app_state.sampling_params != app_state.cached_sampling_params {
-                                app_state.final_light_coords = run_sampling_stage(
-                                    &app_state.sampling_params, 
-                                    app_state.intermediate_coords.clone()
-                                );
-                                app_state.cached_sampling_params = app_state.sampling_params;
+                            if app_state.auto_tune_job.as_ref().is_some_and(|job| job.is_finished()) {
+                                let job = app_state.auto_tune_job.take().expect("the job was just checked");
+                                if let Ok(results) = job.join() {
+                                    // the best settings are filled in, and the pipeline reruns with them below
+                                    match results.first() {
+                                        Some(best) => {
+                                            info!("Applied the best settings found, which scored {:.3}", best.score);
+                                            apply_tune_result(best, &mut app_state.preprocessing_params, &mut app_state.sampling_params);
+                                        }
+                                        None => warn!("None of the settings tried kept enough candidate pixels for this many lights"),
+                                    }
+                                    app_state.tune_results = results;
+                                }
+                            }
+
+                            let preprocessing_changed = app_state.preprocessing_params != app_state.cached_preprocessing_params;
+                            let sampling_changed = app_state.sampling_params != app_state.cached_sampling_params;
+                            if app_state.pipeline_job.is_none()
+                                && (preprocessing_changed || sampling_changed)
+                                && app_state.cancelled_params != Some(current_params)
+                            {
+                                let input = if preprocessing_changed {
+                                    JobInput::Image(app_state.image.clone())
+                                } else {
+                                    JobInput::Candidates(app_state.intermediate_coords.clone())
+                                };
+                                app_state.pipeline_job = Some(PipelineJob::spawn(
+                                    app_state.preprocessing_params,
+                                    app_state.sampling_params,
+                                    input,
+                                ));
+                                app_state.cancelled_params = None;
                             }
 
                             // --- Update GPU Buffers for Lights Shader ---
@@ -439,8 +576,8 @@ pub async fn run_app() {
                                 bytemuck::cast_slice(&[uniforms])
                             );
 
-                            let light_data: Vec<[f32; 2]> = if let Some(coords) = &app_state.intermediate_coords {
-                                let (img_w, img_h) = (coords.width() as f32, coords.height() as f32);
+                            let light_data: Vec<[f32; 2]> = if let Some(candidates) = &app_state.intermediate_coords {
+                                let (img_w, img_h) = (candidates.output.width() as f32, candidates.output.height() as f32);
                                 // let (screen_w, screen_h) = (render_state.size.width as f32, render_state.size.height as f32);
 
                                 app_state.final_light_coords.iter()
@@ -0,0 +1,96 @@
+use egui::{Color32, Pos2, Rect, Sense, Stroke};
+
+use crate::{geometry::CropRect, gui::app::AppState};
+
+/// Draws the source image over the viewport and lets the user drag a crop box on it.
+///
+/// The image is stretched over `viewport_rect` like the lights are, and the box is stored
+/// in source image pixels in `preprocessing_params.transform.crop` when the drag ends.
+pub fn ui_crop_tool(ui: &mut egui::Ui, viewport_rect: Rect, app_state: &mut AppState) {
+    let Some(image) = &app_state.image else {
+        return;
+    };
+    let (width, height) = (image.width() as f32, image.height() as f32);
+
+    // the texture is only uploaded once per loaded image
+    let texture = app_state.crop_texture.get_or_insert_with(|| {
+        let rgba = image.to_rgba8();
+        ui.ctx().load_texture(
+            "crop_source",
+            egui::ColorImage::from_rgba_unmultiplied([rgba.width() as usize, rgba.height() as usize], rgba.as_raw()),
+            egui::TextureOptions::LINEAR,
+        )
+    });
+    let painter = ui.painter_at(viewport_rect);
+    painter.image(
+        texture.id(),
+        viewport_rect,
+        Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0)),
+        Color32::WHITE,
+    );
+
+    // conversions between screen positions and source image pixels
+    let to_pixel = |pos: Pos2| Pos2::new(
+        ((pos.x - viewport_rect.min.x) / viewport_rect.width() * width).clamp(0.0, width),
+        ((pos.y - viewport_rect.min.y) / viewport_rect.height() * height).clamp(0.0, height),
+    );
+    let to_screen = |pixel: Pos2| Pos2::new(
+        viewport_rect.min.x + pixel.x / width * viewport_rect.width(),
+        viewport_rect.min.y + pixel.y / height * viewport_rect.height(),
+    );
+
+    let response = ui.interact(viewport_rect, ui.id().with("crop_tool"), Sense::drag());
+    if let Some(pos) = response.interact_pointer_pos() {
+        if response.drag_started() {
+            app_state.crop_drag = Some((to_pixel(pos), to_pixel(pos)));
+        } else if let Some((_, end)) = &mut app_state.crop_drag {
+            *end = to_pixel(pos);
+        }
+    }
+
+    if response.drag_stopped() {
+        if let Some((start, end)) = app_state.crop_drag.take() {
+            let selection = Rect::from_two_pos(start, end);
+            // a simple click doesn't replace the current crop
+            if selection.width() >= 1.0 && selection.height() >= 1.0 {
+                app_state.preprocessing_params.transform.crop = Some(CropRect {
+                    x: selection.min.x.round() as u32,
+                    y: selection.min.y.round() as u32,
+                    width: selection.width().round() as u32,
+                    height: selection.height().round() as u32,
+                });
+            }
+        }
+    }
+
+    // --- Draw the box being dragged, or else the current crop ---
+    let selection = match (app_state.crop_drag, app_state.preprocessing_params.transform.crop) {
+        (Some((start, end)), _) => Some(Rect::from_two_pos(start, end)),
+        (None, Some(crop)) => Some(Rect::from_min_size(
+            Pos2::new(crop.x as f32, crop.y as f32),
+            egui::vec2(crop.width as f32, crop.height as f32),
+        )),
+        (None, None) => None,
+    };
+    if let Some(selection) = selection {
+        let screen_rect = Rect::from_two_pos(to_screen(selection.min), to_screen(selection.max));
+        // darken everything outside of the box
+        let shade = Color32::from_black_alpha(160);
+        let outside = [
+            Rect::from_min_max(viewport_rect.min, Pos2::new(viewport_rect.max.x, screen_rect.min.y)),
+            Rect::from_min_max(Pos2::new(viewport_rect.min.x, screen_rect.max.y), viewport_rect.max),
+            Rect::from_min_max(
+                Pos2::new(viewport_rect.min.x, screen_rect.min.y),
+                Pos2::new(screen_rect.min.x, screen_rect.max.y),
+            ),
+            Rect::from_min_max(
+                Pos2::new(screen_rect.max.x, screen_rect.min.y),
+                Pos2::new(viewport_rect.max.x, screen_rect.max.y),
+            ),
+        ];
+        for rect in outside {
+            painter.rect_filled(rect, 0.0, shade);
+        }
+        painter.rect_stroke(screen_rect, 0.0, Stroke::new(2.0, Color32::YELLOW));
+    }
+}
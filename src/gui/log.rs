@@ -0,0 +1,66 @@
+use std::{collections::VecDeque, sync::Mutex};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+/// How many of the most recent messages are kept for the log panel
+const CAPACITY: usize = 200;
+/// The environment variable that sets the most detailed level logged, e.g. `debug`
+const LEVEL_VAR: &str = "RASTER_DRONE_LOG";
+
+#[derive(Debug, Clone)]
+pub struct LogEntry {
+    pub level: Level,
+    pub message: String,
+}
+
+/// Writes the records of the `log` facade to stderr, and keeps the most recent ones for the
+/// log panel of the GUI.
+///
+/// Records from other crates, such as wgpu, are only kept from the warning level up.
+struct GuiLogger {
+    entries: Mutex<VecDeque<LogEntry>>,
+}
+
+static LOGGER: GuiLogger = GuiLogger {
+    entries: Mutex::new(VecDeque::new()),
+};
+
+impl Log for GuiLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+            && (metadata.target().starts_with("raster_drone") || metadata.level() <= Level::Warn)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let message = record.args().to_string();
+        eprintln!("[{} {}] {}", record.level(), record.target(), message);
+
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if entries.len() == CAPACITY {
+            entries.pop_front();
+        }
+        entries.push_back(LogEntry { level: record.level(), message });
+    }
+
+    fn flush(&self) {}
+}
+
+/// Installs the logger of the GUI, logging up to the level given by `RASTER_DRONE_LOG`, or
+/// up to the info level by default.
+pub fn init_logging() {
+    let level = std::env::var(LEVEL_VAR)
+        .ok()
+        .and_then(|level| level.parse().ok())
+        .unwrap_or(LevelFilter::Info);
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(level);
+    }
+}
+
+/// The most recent messages, oldest first.
+pub fn recent_entries() -> Vec<LogEntry> {
+    LOGGER.entries.lock().unwrap_or_else(|e| e.into_inner()).iter().cloned().collect()
+}
@@ -1,13 +1,70 @@
-use crate::{gui::app::AppState, transformation::ImgType, utils::ExportCoordinate};
+use std::{borrow::Cow, time::Duration};
+
+use image::{imageops::FilterType, DynamicImage};
+use log::{error, info, Level};
+
+use crate::{
+    adjust::{AdjustParams, Equalization},
+    crazyswarm::write_crazyswarm,
+    depth::{apply_depths, sample_depths},
+    export::{gltf_binary, normalize_coordinates, ply_point_cloud, skybrush_csv, GltfParams},
+    geometry::transform_image,
+    gui::{
+        app::AppState,
+        log::{recent_entries, LogEntry},
+        pipeline::{apply_tune_result, AutoTuneJob},
+    },
+    metrics::FormationMetrics,
+    pipeline::PreprocessingParams,
+    progress::Progress,
+    raster::SamplingType,
+    resize::{ResizeFilter, ResizeMode},
+    stats::RunStats,
+    text::{render_text, TextAlign},
+    transformation::ImgType,
+    tune::TuneResult,
+    vector::load_image,
+};
 
 const FEET_TO_METERS: f64 = 0.3048;
+/// How long a run of the pipeline goes on before its progress bar is shown, so that quick
+/// runs don't flash it on every change
+const PROGRESS_DELAY: Duration = Duration::from_millis(150);
+/// Size of the longest side of the formation previews of the auto-tune results, in points
+const TUNE_PREVIEW_SIZE: f32 = 64.0;
 
 #[derive(Debug, PartialEq, Clone, Copy)]
-enum ResizeOption {
-    None,
-    Size256,
-    Size512,
-    Size1024,
+pub enum ExportFormat {
+    SkybrushCsv,
+    Gltf,
+    Ply,
+}
+
+impl ExportFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::SkybrushCsv => "csv",
+            ExportFormat::Gltf => "glb",
+            ExportFormat::Ply => "ply",
+        }
+    }
+    fn default_file_name(&self) -> &'static str {
+        match self {
+            ExportFormat::SkybrushCsv => "skybrush_coords.csv",
+            ExportFormat::Gltf => "formation.glb",
+            ExportFormat::Ply => "formation.ply",
+        }
+    }
+}
+
+impl std::fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExportFormat::SkybrushCsv => write!(f, "Skybrush CSV"),
+            ExportFormat::Gltf => write!(f, "glTF (Blender)"),
+            ExportFormat::Ply => write!(f, "PLY point cloud"),
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -26,12 +83,12 @@ impl std::fmt::Display for ExportUnit {
     }
 }
 
-/// Helper function defining the button that exports the current coordinates as a static CSV
-/// compatible with Skybrush Studio
-/// Saves to disk a CSV with the following structure:
+/// Helper function defining the button that exports the current coordinates, either as a static CSV
+/// compatible with Skybrush Studio, or as a glTF or PLY file for previsualization in Blender
+/// The CSV has the following structure:
 /// time(ms), x_m (x in meters), y_m, z_m, Red, Green, Blue
 pub fn ui_export_coordinates_button(ui: &mut egui::Ui, app_state: &mut AppState) {
-    if ui.button("Export Coordinates to CSV").clicked() {
+    if ui.button("Export Coordinates").clicked() {
         app_state.show_export_panel = !app_state.show_export_panel;
         app_state.export_error_msg = None;
 
@@ -77,6 +134,44 @@ pub fn ui_export_coordinates_button(ui: &mut egui::Ui, app_state: &mut AppState)
                         ExportUnit::Feet.to_string(),
                     );
                 });
+
+            egui::ComboBox::from_label("Format")
+                .selected_text(format!("{}", app_state.export_format))
+                .show_ui(ui, |ui| {
+                    for format in [ExportFormat::SkybrushCsv, ExportFormat::Gltf, ExportFormat::Ply] {
+                        ui.selectable_value(&mut app_state.export_format, format, format.to_string());
+                    }
+                });
+
+            ui.checkbox(&mut app_state.use_depth, "Depth from image");
+            if app_state.use_depth {
+                ui.horizontal(|ui| {
+                    let source = if app_state.depth_image.is_some() { "Depth image" } else { "Source luminance" };
+                    ui.label(format!("Depth source: {}", source));
+                    if ui.button("Load...").clicked() {
+                        if let Some(path) = rfd::FileDialog::new()
+                            .add_filter("Image Files", &["png", "jpg", "jpeg", "svg"])
+                            .pick_file()
+                        {
+                            match load_image(&path, Some((1024, 1024))) {
+                                Ok(img) => app_state.depth_image = Some(img),
+                                Err(e) => app_state.export_error_msg = Some(format!("Failed to open depth image: {}", e)),
+                            }
+                        }
+                    }
+                    if app_state.depth_image.is_some() && ui.button("Clear").clicked() {
+                        app_state.depth_image = None;
+                    }
+                });
+                let params = &mut app_state.depth_params;
+                ui.horizontal(|ui| {
+                    ui.label("Depth range (m):");
+                    ui.add(egui::DragValue::new(&mut params.min_depth).speed(0.1).clamp_range(-50.0..=50.0));
+                    ui.label("to");
+                    ui.add(egui::DragValue::new(&mut params.max_depth).speed(0.1).clamp_range(-50.0..=50.0));
+                });
+                ui.checkbox(&mut params.invert, "Invert depth");
+            }
             
             ui.add_space(10.0);
 
@@ -105,106 +200,78 @@ pub fn ui_export_coordinates_button(ui: &mut egui::Ui, app_state: &mut AppState)
                     
                     // --- B. Run all transformation logic ---
                     // This logic is now *inside* the confirm button
-                    
-                    let coordinates = &app_state.final_light_coords;
-                    if coordinates.is_empty() {
-                         app_state.export_error_msg = Some("No coordinates to export".to_string());
-                         return;
-                    }
-
-                    // --- Fix for fold initialization ---
-                    // Initialize with the first coordinate's values
-                    let first = coordinates[0];
-                    let (min_x, max_x, min_y, max_y) = coordinates.iter().skip(1).fold(
-                        (first.x(), first.x(), first.y(), first.y()),
-                        |mut acc, coord| {
-                            let x = coord.x();
-                            let y = coord.y();
-                            if x < acc.0 { acc.0 = x; }
-                            if x > acc.1 { acc.1 = x; }
-                            if y < acc.2 { acc.2 = y; }
-                            if y > acc.3 { acc.3 = y; }
-                            acc
-                        },
-                    );
-
-                    let x_space = max_x - min_x;
-                    let y_space = max_y - min_y;
-                    let max_range = x_space.max(y_space);
+                    let normalized_coordinates = match normalize_coordinates(
+                        &app_state.final_light_coords,
+                        max_dim_meters,
+                    ) {
+                        Ok(coords) => coords,
+                        Err(e) => {
+                            app_state.export_error_msg = Some(e);
+                            return;
+                        }
+                    };
 
-                    // Handle edge case where all points are identical
-                    if max_range == 0 {
-                        app_state.export_error_msg = Some("All coordinates are identical".to_string());
-                        return;
-                    }
+                    // the depth is read in the pixel space of the processed image
+                    let normalized_coordinates = match (&app_state.intermediate_coords, &app_state.image) {
+                        (Some(intermediate), Some(source)) if app_state.use_depth => {
+                            // the depth image goes through the same crop and rotation as the source
+                            let transform = &app_state.preprocessing_params.transform;
+                            let depth_img: Cow<DynamicImage> = match (&app_state.depth_image, transform.is_identity()) {
+                                (Some(depth), true) => Cow::Borrowed(depth),
+                                (Some(depth), false) => Cow::Owned(transform_image(
+                                    &depth.resize_exact(source.width(), source.height(), FilterType::Triangle),
+                                    transform,
+                                )),
+                                (None, true) => Cow::Borrowed(source),
+                                (None, false) => Cow::Owned(transform_image(source, transform)),
+                            };
+                            let depths = sample_depths(
+                                &depth_img,
+                                &app_state.final_light_coords,
+                                intermediate.output.width(),
+                                intermediate.output.height(),
+                                &app_state.depth_params,
+                            );
+                            apply_depths(normalized_coordinates, &depths)
+                        }
+                        _ => normalized_coordinates,
+                    };
 
-                    let scale_factor = 1.0 / max_range as f64;
-                    let new_width = x_space as f64 * scale_factor;
-                    let new_height = y_space as f64 * scale_factor;
-                    let offset_x = (1.0 - new_width) / 2.0;
-                    let offset_y = (1.0 - new_height) / 2.0;
-
-                    let normalized_coordinates: Vec<ExportCoordinate> = coordinates
-                        .iter()
-                        .map(|coord| {
-                            let normalized_x = (coord.x() as f64 - min_x as f64) * scale_factor + offset_x;
-                            // Flip y-axis (1.0 - ...)
-                            let normalized_y = 1.0 - ((coord.y() as f64 - min_y as f64) * scale_factor + offset_y);
-
-                            // Scale to the final desired dimension
-                            let new_x = normalized_x * max_dim_meters;
-                            let new_y = normalized_y * max_dim_meters;
-
-                            ExportCoordinate::new(new_x, new_y)
-                        })
-                        .collect();
-                    
-                    // --- C. Create the CSV data in memory ---
-                    let mut wtr = csv::Writer::from_writer(vec![]);
+                    // --- C. Create the file data in memory ---
                     // these need to be converted into u8, normalized on 1
-                    let [red, green, blue] = app_state.visual_params.light_color;
-                    let red_u8 = (red * 255f32) as u8;
-                    let green_u8 = (green * 255f32) as u8;
-                    let blue_u8 = (blue * 255f32) as u8;
-
-                    // Write header
-                     wtr.write_record([
-                        "Name", "x_m", "y_m", "z_m", "Red", "Green", "Blue"
-                     ]).unwrap(); // Handle error
-
-                    for (count, coord) in normalized_coordinates.iter().enumerate() {
-                        wtr.write_record(&[
-                            format!("Drone{}", count + 1),
-                            // String::from("1000"), // setting time in ms to 1 second
-                            String::from("0.0"),
-                            coord.x().to_string(),
-                            coord.y().to_string(),
-                            red_u8.to_string(),
-                            green_u8.to_string(),
-                            blue_u8.to_string(),
-                        ])
-                        .unwrap(); // todo: Handle error
-                    }
-
-                    // Get the CSV data as bytes
-                    let csv_data = match wtr.into_inner() {
+                    let colors = vec![
+                        app_state.visual_params.light_color.map(|channel| (channel * 255f32) as u8);
+                        normalized_coordinates.len()
+                    ];
+                    let file_data = match app_state.export_format {
+                        ExportFormat::SkybrushCsv => skybrush_csv(&normalized_coordinates, &colors),
+                        ExportFormat::Gltf => gltf_binary(
+                            &[normalized_coordinates],
+                            &colors,
+                            &GltfParams::default(),
+                        ),
+                        ExportFormat::Ply => Ok(ply_point_cloud(&normalized_coordinates, &colors).into_bytes()),
+                    };
+                    let file_data = match file_data {
                         Ok(data) => data,
                         Err(e) => {
-                            app_state.export_error_msg = Some(format!("CSV error: {}", e));
+                            app_state.export_error_msg = Some(e);
                             return;
                         }
                     };
 
                     // --- D. Open the File Save Dialog ---
+                    let extension = app_state.export_format.extension();
                     let file_path = rfd::FileDialog::new()
-                        .add_filter("CSV", &["csv"])
-                        .set_file_name("skybrush_coords.csv")
+                        .add_filter(app_state.export_format.to_string(), &[extension])
+                        .set_file_name(app_state.export_format.default_file_name())
                         .save_file();
 
                     // --- E. Write the file to disk ---
                     if let Some(path) = file_path {
-                        match std::fs::write(&path, csv_data) {
+                        match std::fs::write(&path, file_data) {
                             Ok(_) => {
+                                info!("Exported {} drones to '{}'", app_state.final_light_coords.len(), path.display());
                                 // Success! Hide the panel and clear errors
                                 app_state.show_export_panel = false;
                                 app_state.export_error_msg = None;
@@ -228,37 +295,569 @@ pub fn ui_export_coordinates_button(ui: &mut egui::Ui, app_state: &mut AppState)
     }
 }
 
+/// Helper function defining the button that exports the current coordinates for indoor
+/// rehearsal on Crazyflies, as a Crazyswarm `crazyflies.yaml` plus per-drone waypoint files
+pub fn ui_export_crazyswarm_button(ui: &mut egui::Ui, app_state: &mut AppState) {
+    if ui.button("Export for Crazyswarm").clicked() {
+        app_state.show_crazyswarm_panel = !app_state.show_crazyswarm_panel;
+        app_state.crazyswarm_error_msg = None;
+    }
+
+    if app_state.show_crazyswarm_panel {
+        ui.add_space(5.0);
+
+        egui::Frame::group(ui.style()).show(ui, |ui| {
+            ui.heading("Indoor Settings");
+            ui.add_space(10.0);
+
+            let params = &mut app_state.crazyswarm_params;
+            ui.horizontal(|ui| {
+                ui.label("Flight cube (m):");
+                ui.add(egui::DragValue::new(&mut params.cube_size).speed(0.1).clamp_range(0.5..=20.0));
+            });
+            ui.horizontal(|ui| {
+                ui.label("Minimum spacing (m):");
+                ui.add(egui::DragValue::new(&mut params.min_spacing).speed(0.01).clamp_range(0.0..=5.0));
+            });
+            ui.horizontal(|ui| {
+                ui.label("Floor margin (m):");
+                ui.add(egui::DragValue::new(&mut params.floor_margin).speed(0.05).clamp_range(0.0..=5.0));
+            });
+            ui.horizontal(|ui| {
+                ui.label("Takeoff grid spacing (m):");
+                ui.add(egui::DragValue::new(&mut params.ground_spacing).speed(0.05).clamp_range(0.1..=5.0));
+            });
+            ui.horizontal(|ui| {
+                ui.label("Transit time (s):");
+                ui.add(egui::DragValue::new(&mut params.duration).speed(0.1).clamp_range(0.5..=60.0));
+            });
+
+            if let Some(err) = &app_state.crazyswarm_error_msg {
+                ui.label(egui::RichText::new(err).color(ui.style().visuals.error_fg_color));
+            }
+
+            ui.add_space(10.0);
+
+            ui.horizontal(|ui| {
+                if ui.button("Choose Folder & Save").clicked() {
+                    if let Some(dir) = rfd::FileDialog::new().pick_folder() {
+                        match write_crazyswarm(
+                            &dir,
+                            &app_state.final_light_coords,
+                            &app_state.crazyswarm_params,
+                        ) {
+                            Ok(_) => {
+                                info!("Exported the Crazyswarm configuration to '{}'", dir.display());
+                                app_state.show_crazyswarm_panel = false;
+                                app_state.crazyswarm_error_msg = None;
+                            }
+                            Err(e) => app_state.crazyswarm_error_msg = Some(e),
+                        }
+                    }
+                }
+
+                if ui.button("Cancel").clicked() {
+                    app_state.show_crazyswarm_panel = false;
+                    app_state.crazyswarm_error_msg = None;
+                }
+            });
+        });
+    }
+}
+
 /// Helper function to encapsulate the file loading logic.
 pub fn ui_load_image_button(ui: &mut egui::Ui, app_state: &mut AppState) {
     if ui.button("Load Image...").clicked() {
         if let Some(path) = rfd::FileDialog::new()
-            .add_filter("Image Files", &["png", "jpg", "jpeg"])
+            .add_filter("Image Files", &["png", "jpg", "jpeg", "svg"])
             .pick_file()
         {
-            match image::open(path) {
+            // SVGs are rasterized at the largest resize preset, so that the presets only shrink them
+            match load_image(&path, Some((1024, 1024))) {
                 Ok(img) => {
+                    info!("Loaded '{}' ({}x{})", path.display(), img.width(), img.height());
                     app_state.image = Some(img);
+                    // the crop and the searched settings of the previous image don't apply to this one
+                    app_state.preprocessing_params.transform.crop = None;
+                    app_state.crop_texture = None;
+                    app_state.reset_auto_tune();
+                    // a previous text source may have changed the threshold to keep every pixel
+                    app_state.preprocessing_params.threshold = PreprocessingParams::default().threshold;
                     // Invalidate the cache to force the expensive pipeline to re-run on the next frame.
                     // This is a simple way to signal that a major data source has changed.
-                    app_state.cached_preprocessing_params.use_bradley = !app_state.preprocessing_params.use_bradley;
+                    app_state.cached_preprocessing_params.bradley = !app_state.preprocessing_params.bradley;
+                }
+                Err(e) => error!("Failed to open image: {}", e),
+            }
+        }
+    }
+}
+
+/// Helper function defining the "Text" source, which renders a string with a font file
+/// and uses the result in place of a loaded image.
+pub fn ui_text_source(ui: &mut egui::Ui, app_state: &mut AppState) {
+    if ui.button("Text...").clicked() {
+        app_state.show_text_panel = !app_state.show_text_panel;
+        app_state.text_error_msg = None;
+    }
+
+    if app_state.show_text_panel {
+        ui.add_space(5.0);
+
+        egui::Frame::group(ui.style()).show(ui, |ui| {
+            ui.heading("Text Source");
+            ui.add_space(10.0);
+
+            ui.add(
+                egui::TextEdit::multiline(&mut app_state.text_input)
+                    .desired_rows(2)
+                    .desired_width(f32::INFINITY),
+            );
+
+            ui.horizontal(|ui| {
+                if ui.button("Choose Font...").clicked() {
+                    if let Some(path) = rfd::FileDialog::new()
+                        .add_filter("Font Files", &["ttf", "otf"])
+                        .pick_file()
+                    {
+                        app_state.font_path = Some(path);
+                    }
+                }
+                let font_name = app_state.font_path
+                    .as_ref()
+                    .and_then(|path| path.file_name())
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| "No font selected".to_string());
+                ui.label(font_name);
+            });
+
+            let layout = &mut app_state.text_layout;
+            ui.add(egui::Slider::new(&mut layout.size, 8.0..=512.0).text("Font Size"));
+            ui.add(egui::Slider::new(&mut layout.line_spacing, 0.5..=3.0).text("Line Spacing"));
+            ui.add(egui::Slider::new(&mut layout.letter_spacing, -20.0..=50.0).text("Letter Spacing"));
+
+            egui::ComboBox::from_label("Alignment")
+                .selected_text(format!("{:?}", layout.align))
+                .show_ui(ui, |ui| {
+                    for align in [TextAlign::Left, TextAlign::Center, TextAlign::Right] {
+                        ui.selectable_value(&mut layout.align, align, format!("{:?}", align));
+                    }
+                });
+
+            if let Some(err) = &app_state.text_error_msg {
+                ui.label(egui::RichText::new(err).color(ui.style().visuals.error_fg_color));
+            }
+
+            ui.add_space(10.0);
+
+            ui.horizontal(|ui| {
+                if ui.button("Render Text").clicked() {
+                    let Some(font_path) = &app_state.font_path else {
+                        app_state.text_error_msg = Some("Please choose a font file".to_string());
+                        return;
+                    };
+                    match render_text(&app_state.text_input, font_path, &app_state.text_layout) {
+                        Ok(img) => {
+                            app_state.image = Some(img);
+                            // the crop and the searched settings of the previous image don't apply to this one
+                            app_state.preprocessing_params.transform.crop = None;
+                            app_state.crop_texture = None;
+                            app_state.reset_auto_tune();
+                            // text is rendered white on black, and every glyph pixel is a candidate
+                            app_state.preprocessing_params.img_type = ImgType::WhiteOnBlack;
+                            app_state.preprocessing_params.threshold = 1.0;
+                            app_state.cached_preprocessing_params.bradley = !app_state.preprocessing_params.bradley;
+                            app_state.show_text_panel = false;
+                            app_state.text_error_msg = None;
+                        }
+                        Err(e) => app_state.text_error_msg = Some(e),
+                    }
+                }
+
+                if ui.button("Cancel").clicked() {
+                    app_state.show_text_panel = false;
+                    app_state.text_error_msg = None;
+                }
+            });
+        });
+    }
+}
+
+/// Helper function defining the background removal settings, which restrict the candidate
+/// pixels to the foreground of the image.
+pub fn ui_background_removal(ui: &mut egui::Ui, app_state: &mut AppState) {
+    let mask = &mut app_state.preprocessing_params.mask;
+
+    ui.heading("Background Removal");
+    ui.add(egui::Slider::new(
+        &mut mask.alpha_threshold,
+        0..=255
+    ).text("Alpha threshold"));
+
+    let mut use_chroma_key = mask.chroma_key.is_some();
+    ui.checkbox(&mut use_chroma_key, "Chroma key");
+    if use_chroma_key {
+        let mut key = mask.chroma_key.unwrap_or([0, 255, 0]);
+        ui.horizontal(|ui| {
+            ui.label("Key color");
+            ui.color_edit_button_srgb(&mut key);
+        });
+        ui.add(egui::Slider::new(
+            &mut mask.chroma_tolerance,
+            0.0..=100.0
+        ).text("Key tolerance"));
+        mask.chroma_key = Some(key);
+    } else {
+        mask.chroma_key = None;
+    }
+
+    ui.checkbox(&mut mask.flood_fill, "Remove border background");
+    if mask.flood_fill {
+        ui.add(egui::Slider::new(
+            &mut mask.flood_tolerance,
+            0.0..=100.0
+        ).text("Border tolerance"));
+    }
+}
+
+/// Helper function defining the tonal and sharpness adjustments applied before thresholding.
+pub fn ui_adjustments(ui: &mut egui::Ui, app_state: &mut AppState) {
+    let adjust = &mut app_state.preprocessing_params.adjust;
+
+    ui.heading("Adjustments");
+    ui.add(egui::Slider::new(
+        &mut adjust.brightness,
+        -1.0..=1.0
+    ).text("Brightness"));
+    ui.add(egui::Slider::new(
+        &mut adjust.contrast,
+        0.0..=3.0
+    ).text("Contrast"));
+    ui.add(egui::Slider::new(
+        &mut adjust.gamma,
+        0.2..=5.0
+    ).logarithmic(true).text("Gamma"));
+    ui.add(egui::Slider::new(
+        &mut adjust.blur_sigma,
+        0.0..=10.0
+    ).text("Blur"));
+    ui.add(egui::Slider::new(
+        &mut adjust.sharpen_amount,
+        0.0..=5.0
+    ).text("Sharpen"));
+    if adjust.sharpen_amount > 0.0 {
+        ui.add(egui::Slider::new(
+            &mut adjust.sharpen_sigma,
+            0.5..=5.0
+        ).text("Sharpen radius"));
+    }
+
+    egui::ComboBox::from_label("Equalization")
+        .selected_text(format!("{:?}", adjust.equalization))
+        .show_ui(ui, |ui| {
+            for equalization in [Equalization::None, Equalization::Global, Equalization::Clahe] {
+                ui.selectable_value(&mut adjust.equalization, equalization, format!("{:?}", equalization));
+            }
+        });
+    if adjust.equalization == Equalization::Clahe {
+        ui.add(egui::Slider::new(
+            &mut adjust.clahe_tiles,
+            1..=16
+        ).text("Tiles"));
+        ui.add(egui::Slider::new(
+            &mut adjust.clahe_clip_limit,
+            1.0..=10.0
+        ).text("Clip limit"));
+    }
+
+    ui.horizontal(|ui| {
+        ui.checkbox(&mut adjust.invert, "Invert");
+        if ui.button("Reset").clicked() {
+            *adjust = AdjustParams::default();
+        }
+    });
+}
+
+/// Helper function defining the crop, rotation, flip and padding settings applied to the
+/// source image before thresholding.
+pub fn ui_crop_and_rotate(ui: &mut egui::Ui, app_state: &mut AppState) {
+    ui.heading("Crop & Rotate");
+    ui.horizontal(|ui| {
+        let label = if app_state.crop_tool_active { "Done Cropping" } else { "Crop Tool" };
+        if ui.button(label).clicked() {
+            app_state.crop_tool_active = !app_state.crop_tool_active;
+            app_state.crop_drag = None;
+        }
+        if app_state.preprocessing_params.transform.crop.is_some() && ui.button("Reset Crop").clicked() {
+            app_state.preprocessing_params.transform.crop = None;
+        }
+    });
+    if app_state.crop_tool_active {
+        ui.label("Drag a box over the image to crop it.");
+    }
+
+    let transform = &mut app_state.preprocessing_params.transform;
+    ui.add(egui::Slider::new(
+        &mut transform.rotation,
+        -180.0..=180.0
+    ).text("Rotation").suffix("°"));
+    ui.horizontal(|ui| {
+        ui.checkbox(&mut transform.flip_horizontal, "Flip horizontal");
+        ui.checkbox(&mut transform.flip_vertical, "Flip vertical");
+    });
+    ui.add(egui::Slider::new(
+        &mut transform.padding,
+        0..=200
+    ).text("Padding"));
+}
+
+/// Helper function defining the size the image is resized to before thresholding, which sets
+/// the density of candidate pixels.
+pub fn ui_resize(ui: &mut egui::Ui, app_state: &mut AppState) {
+    let mut use_resize = app_state.preprocessing_params.resize.is_some();
+    ui.checkbox(&mut use_resize, "Resize Image");
+    if !use_resize {
+        app_state.preprocessing_params.resize = None;
+        return;
+    }
+
+    let params = &mut app_state.preprocessing_params;
+    let (mut width, mut height) = params.resize
+        .or(PreprocessingParams::default().resize)
+        .expect("The default preprocessing resizes the image");
+
+    ui.horizontal(|ui| {
+        ui.add(egui::DragValue::new(&mut width).clamp_range(1..=4096).suffix(" px"));
+        ui.label("x");
+        ui.add(egui::DragValue::new(&mut height).clamp_range(1..=4096).suffix(" px"));
+    });
+    ui.horizontal(|ui| {
+        for size in [256, 512, 1024] {
+            if ui.button(format!("{}", size)).clicked() {
+                width = size;
+                height = size;
+            }
+        }
+    });
+
+    egui::ComboBox::from_label("Resize Mode")
+        .selected_text(format!("{:?}", params.resize_mode))
+        .show_ui(ui, |ui| {
+            for mode in [ResizeMode::Fit, ResizeMode::Upscale, ResizeMode::Fill, ResizeMode::Exact] {
+                ui.selectable_value(&mut params.resize_mode, mode, format!("{:?}", mode));
+            }
+        });
+    egui::ComboBox::from_label("Resize Filter")
+        .selected_text(format!("{:?}", params.resize_filter))
+        .show_ui(ui, |ui| {
+            for filter in [ResizeFilter::Nearest, ResizeFilter::Triangle, ResizeFilter::Lanczos] {
+                ui.selectable_value(&mut params.resize_filter, filter, format!("{:?}", filter));
+            }
+        });
+
+    params.resize = Some((width, height));
+}
+
+/// A progress bar of the current stage of `progress`, with a button to cancel it. Returns
+/// whether the button was clicked.
+fn ui_progress_bar(ui: &mut egui::Ui, progress: &Progress) -> bool {
+    let stage = progress.current_stage().map_or("Starting".to_string(), |stage| stage.to_string());
+    let (done, total) = progress.counts();
+    let progress_bar = if total > 0 {
+        egui::ProgressBar::new(done as f32 / total as f32).text(format!("{}: {}/{}", stage, done, total))
+    } else {
+        egui::ProgressBar::new(0.0).text(stage).animate(true)
+    };
+
+    ui.horizontal(|ui| {
+        ui.add(progress_bar.desired_width(ui.available_width() - 60.0));
+        if progress.is_cancelled() {
+            ui.add_enabled(false, egui::Button::new("Cancel"));
+            false
+        } else {
+            ui.button("Cancel").clicked()
+        }
+    }).inner
+}
+
+fn ui_pipeline_progress(ui: &mut egui::Ui, app_state: &mut AppState) {
+    let Some(job) = &app_state.pipeline_job else {
+        return
+    };
+    if job.started.elapsed() < PROGRESS_DELAY {
+        return
+    }
+
+    ui.separator();
+    if ui_progress_bar(ui, &job.progress) {
+        info!("Cancelled the run of the pipeline");
+        job.progress.cancel();
+        // the previous result stays on screen until the settings change again
+        app_state.cancelled_params = Some((job.preprocessing_params, job.sampling_params));
+    }
+}
+
+/// Helper function for the Auto button, which searches for the settings giving the best
+/// formation of the current number of lights, and lists the best settings found with a preview
+/// of their formation, to switch between them.
+fn ui_auto_tune(ui: &mut egui::Ui, app_state: &mut AppState) {
+    if let Some(job) = &app_state.auto_tune_job {
+        if ui_progress_bar(ui, &job.progress) {
+            info!("Cancelled the search for the best settings");
+            app_state.reset_auto_tune();
+        }
+    } else {
+        let button = ui.add_enabled(app_state.image.is_some(), egui::Button::new("Auto"))
+            .on_hover_text("Try thresholds, Bradley window sizes and resolutions, and keep the settings giving the best formation of this many lights");
+        if button.clicked() {
+            if let Some(image) = &app_state.image {
+                app_state.auto_tune_job = Some(AutoTuneJob::spawn(
+                    app_state.preprocessing_params,
+                    app_state.sampling_params,
+                    image.clone(),
+                ));
+            }
+        }
+    }
+
+    if app_state.tune_results.is_empty() {
+        return
+    }
+    let mut applied = None;
+    egui::CollapsingHeader::new("Best settings found").show(ui, |ui| {
+        egui::Grid::new("tune_results").num_columns(3).striped(true).show(ui, |ui| {
+            for (i, result) in app_state.tune_results.iter().enumerate() {
+                let choice = &result.choice;
+                let bradley = choice.bradley_size.map_or("off".to_string(), |size| size.to_string());
+                ui_tune_preview(ui, result);
+                ui.label(format!(
+                    "{:.3}: {}x{}, threshold {}, Bradley {}, {} sampling",
+                    result.score, choice.resize.0, choice.resize.1, choice.threshold, bradley, choice.sampling.name(),
+                ));
+                if ui.button("Apply").clicked() {
+                    applied = Some(i);
                 }
-                Err(e) => eprintln!("Failed to open image: {}", e),
+                ui.end_row();
             }
+        });
+    });
+    if let Some(i) = applied {
+        apply_tune_result(&app_state.tune_results[i], &mut app_state.preprocessing_params, &mut app_state.sampling_params);
+    }
+}
+
+/// Draws the formation of an auto-tune result as white dots on a small black thumbnail.
+fn ui_tune_preview(ui: &mut egui::Ui, result: &TuneResult) {
+    let output = &result.coordinates;
+    let (width, height) = (output.width().max(1) as f32, output.height().max(1) as f32);
+    let scale = TUNE_PREVIEW_SIZE / width.max(height);
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(width * scale, height * scale), egui::Sense::hover());
+
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 0.0, egui::Color32::BLACK);
+    for coord in output.coords() {
+        let center = rect.min + egui::vec2((coord.x() as f32 + 0.5) * scale, (coord.y() as f32 + 0.5) * scale);
+        painter.circle_filled(center, 1.0, egui::Color32::WHITE);
+    }
+}
+
+/// The text of a log message, colored by its level
+fn log_entry_text(entry: &LogEntry, text: String) -> egui::RichText {
+    let text = egui::RichText::new(text);
+    match entry.level {
+        Level::Error => text.color(egui::Color32::LIGHT_RED),
+        Level::Warn => text.color(egui::Color32::YELLOW),
+        Level::Info | Level::Debug | Level::Trace => text,
+    }
+}
+
+/// A status bar with the latest log message, which expands into the recent messages
+pub fn ui_log_panel(ui: &mut egui::Ui, app_state: &mut AppState) {
+    let entries = recent_entries();
+    ui.horizontal(|ui| {
+        ui.toggle_value(&mut app_state.show_log_panel, "Log");
+        if let Some(entry) = entries.last() {
+            ui.label(log_entry_text(entry, entry.message.clone()));
         }
+    });
+
+    if app_state.show_log_panel {
+        egui::ScrollArea::vertical()
+            .max_height(150.0)
+            .stick_to_bottom(true)
+            .show(ui, |ui| {
+                for entry in &entries {
+                    ui.label(log_entry_text(entry, format!("[{}] {}", entry.level, entry.message)).monospace());
+                }
+            });
     }
 }
 
+/// Helper function showing the statistics of the last run of the pipeline.
+fn ui_run_stats(ui: &mut egui::Ui, stats: Option<&RunStats>) {
+    ui.heading("Statistics");
+    let Some(stats) = stats else {
+        ui.label("No run yet");
+        return
+    };
+    let or_none = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+
+    egui::Grid::new("run_stats").num_columns(2).striped(true).show(ui, |ui| {
+        let mut row = |label: &str, value: String| {
+            ui.label(label);
+            ui.label(value);
+            ui.end_row();
+        };
+        row("Source size", format!("{}x{}", stats.source_size.0, stats.source_size.1));
+        row("Resized size", format!("{}x{}", stats.resized_size.0, stats.resized_size.1));
+        row("Candidates", stats.candidate_count.to_string());
+        row("Threshold", or_none(stats.effective_threshold.map(|t| format!("{:.1}", t))));
+        row("Drones", stats.sample_count.to_string());
+        row("Min spacing", or_none(stats.min_spacing.map(|s| format!("{:.2} px", s))));
+        row("Mean spacing", or_none(stats.mean_spacing.map(|s| format!("{:.2} px", s))));
+        row("Bounding box", or_none(stats.bounding_box.map(|(x0, y0, x1, y1)| format!("({}, {}) to ({}, {})", x0, y0, x1, y1))));
+        row("Coverage", or_none(stats.coverage.map(|c| format!("{:.1}%", c * 100.0))));
+        for (stage, elapsed) in &stats.timings {
+            row(&stage.to_string(), format!("{:.1?}", elapsed));
+        }
+    });
+}
+
+/// Helper function showing how well the last sampled lights represent the candidate pixels.
+fn ui_formation_metrics(ui: &mut egui::Ui, metrics: Option<&FormationMetrics>) {
+    let Some(metrics) = metrics else {
+        return
+    };
+    ui.label(egui::RichText::new("Quality").strong()).on_hover_text(
+        "The lights, rendered at the radius shown, compared to the thresholded image"
+    );
+    egui::Grid::new("formation_metrics").num_columns(2).striped(true).show(ui, |ui| {
+        let mut row = |label: &str, value: String| {
+            ui.label(label);
+            ui.label(value);
+            ui.end_row();
+        };
+        row("SSIM", format!("{:.3}", metrics.ssim));
+        row("Chamfer distance", format!("{:.2} px", metrics.chamfer_distance));
+        row("Hausdorff distance", format!("{:.2} px", metrics.hausdorff_distance));
+        row("Uniformity", format!("{:.3}", metrics.coverage_uniformity));
+        row("Light radius", format!("{:.1} px", metrics.light_radius));
+    });
+}
+
 pub fn populate_slider_menu(app_state: &mut AppState, ui: &mut egui::Ui) {
     ui_load_image_button(ui, app_state);
+    ui_text_source(ui, app_state);
+    ui_pipeline_progress(ui, app_state);
 
     ui.separator();
 
     ui.checkbox(
-        &mut app_state.preprocessing_params.use_bradley,
+        &mut app_state.preprocessing_params.bradley,
         "Use Bradley Thresholding"
     );
 
-    if app_state.preprocessing_params.use_bradley {
+    if app_state.preprocessing_params.bradley {
 
         ui.heading("Bradley Thresholding");
         ui.add(egui::Slider::new(
@@ -275,39 +874,19 @@ pub fn populate_slider_menu(app_state: &mut AppState, ui: &mut egui::Ui) {
 
     ui.separator();
 
-    let mut selected_resize = match app_state.preprocessing_params.resize {
-        None => ResizeOption::None,
-        Some((256, 256)) => ResizeOption::Size256,
-        Some((512, 512)) => ResizeOption::Size512,
-        Some((1024, 1024)) => ResizeOption::Size1024,
-        _ => ResizeOption::Size256, // our default option
-    };
+    ui_background_removal(ui, app_state);
 
-    // Helper to get display text for the selected option.
-    let selected_text = match selected_resize {
-        ResizeOption::None => "None",
-        ResizeOption::Size256 => "256x256",
-        ResizeOption::Size512 => "512x512",
-        ResizeOption::Size1024 => "1024x1024",
-    };
+    ui.separator();
 
-    ui.label("Resize Image");
-    egui::ComboBox::from_id_source("resize_combo")
-        .selected_text(selected_text)
-        .show_ui(ui, |ui| {
-            ui.selectable_value(&mut selected_resize, ResizeOption::None, "None");
-            ui.selectable_value(&mut selected_resize, ResizeOption::Size256, "256x256");
-            ui.selectable_value(&mut selected_resize, ResizeOption::Size512, "512x512");
-            ui.selectable_value(&mut selected_resize, ResizeOption::Size1024, "1024x1024");
-        });
+    ui_crop_and_rotate(ui, app_state);
 
-    // 4. After the UI has been drawn, convert the enum back to the data model.
-    app_state.preprocessing_params.resize = match selected_resize {
-        ResizeOption::None => None,
-        ResizeOption::Size256 => Some((256, 256)),
-        ResizeOption::Size512 => Some((512, 512)),
-        ResizeOption::Size1024 => Some((1024, 1024)),
-    };
+    ui.separator();
+
+    ui_adjustments(ui, app_state);
+
+    ui.separator();
+
+    ui_resize(ui, app_state);
     
     ui.separator();
 
@@ -316,9 +895,30 @@ pub fn populate_slider_menu(app_state: &mut AppState, ui: &mut egui::Ui) {
         &mut app_state.sampling_params.sample_count,
         1..=500
     ).text("Sample Count"));
+    // path and color sampling need the vector source or the colors, which the thresholded
+    // image doesn't have
+    egui::ComboBox::from_label("Sampler")
+        .selected_text(format!("{:?}", app_state.sampling_params.sampling_type))
+        .show_ui(ui, |ui| {
+            for sampling_type in [SamplingType::Farthest, SamplingType::Weighted] {
+                ui.selectable_value(&mut app_state.sampling_params.sampling_type, sampling_type, format!("{:?}", sampling_type));
+            }
+        });
+    if app_state.sampling_params.sampling_type == SamplingType::Weighted {
+        ui.add(egui::Slider::new(
+            &mut app_state.sampling_params.min_spacing,
+            0.0..=20.0
+        ).text("Minimum Spacing"));
+    }
+    ui_auto_tune(ui, app_state);
     
     ui.separator();
 
+    ui_run_stats(ui, app_state.run_stats.as_ref());
+    ui_formation_metrics(ui, app_state.formation_metrics.as_ref());
+
+    ui.separator();
+
     ui.heading("Visuals");
     ui.add(egui::Slider::new(
         &mut app_state.visual_params.light_radius, 
@@ -353,6 +953,7 @@ pub fn populate_slider_menu(app_state: &mut AppState, ui: &mut egui::Ui) {
 
     ui.heading("Export");
     ui_export_coordinates_button(ui, app_state);
+    ui_export_crazyswarm_button(ui, app_state);
 }
 
 pub fn populate_upload_menu(app_state: &mut AppState, ui: &mut egui::Ui) {
@@ -362,6 +963,7 @@ pub fn populate_upload_menu(app_state: &mut AppState, ui: &mut egui::Ui) {
         ui.label("Please load an image to begin.");
         ui.add_space(10.0);
         ui_load_image_button(ui, app_state);
+        ui_text_source(ui, app_state);
     });
 }
 
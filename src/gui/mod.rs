@@ -1,3 +1,5 @@
 pub mod app;
 mod pipeline;
 mod menu;
+mod crop;
+pub mod log;
@@ -1,124 +1,246 @@
-use std::borrow::Cow;
+use std::{
+    sync::Arc,
+    thread::{self, JoinHandle},
+    time::Instant,
+};
 
-use image::{DynamicImage, GenericImageView};
+use image::DynamicImage;
+use log::{debug, error};
 
 use crate::{
-    sampling::{farthest_point_sampling, grid_sampling}, 
-    thresholding::bradley_adaptive_threshold, 
-    transformation::{image_to_coordinates, ImgType},
-    raster::SamplingType,
-    utils::{Coordinate, CoordinateOutput},
+    error::Error,
+    metrics::{evaluate_formation, FormationMetrics},
+    pipeline::{extract_candidates, prepare_image, sample_candidates, Candidates, PreprocessingParams, SamplingParams},
+    progress::{Cancelled, Progress},
+    stats::RunStats,
+    tune::{search, Reference, TuneResult, TuneSpace},
+    resize::Resize,
+    utils::Coordinate,
 };
 
-#[derive(Debug, PartialEq, Clone, Copy)]
-pub struct PreprocessingParams {
-    pub img_type: ImgType,
-    pub resize: Option<(u32, u32)>,
-    pub global_threshold: f32,
-    pub use_bradley: bool,
-    pub bradley_size: u32,
-    pub bradley_threshold: u8,
+/// How many of the best settings found by an `AutoTuneJob` are kept, to choose from
+const TUNE_RESULTS: usize = 5;
+
+/// Takes pre-processing params, loads/processes an image, returns all valid coordinates.
+///
+/// This runs the same steps as the Python `Pipeline`, so both give the same candidates.
+pub fn run_preprocessing_stage(
+    params: &PreprocessingParams,
+    image: &Option<image::DynamicImage>,
+    progress: &Progress,
+) -> Result<Option<Candidates>, Cancelled> {
+    debug!("Rerunning the preprocessing stage");
+    
+    // If no image is loaded, there are no coordinates to return.
+    let Some(source_img) = image else {
+        return Ok(None)
+    };
+
+    let (output, weights) = extract_candidates(
+        prepare_image(params, source_img).into_owned(),
+        params.img_type,
+        params.resize(),
+        params.threshold,
+        params.bradley,
+        params.bradley_threshold,
+        params.bradley_size,
+        progress,
+    )?;
+    Ok(Some(Candidates { output, weights }))
 }
 
-impl Default for PreprocessingParams {
-    fn default() -> Self {
-        Self {
-            img_type: ImgType::BlackOnWhite,
-            resize: Some((256, 256)),
-            global_threshold: 0.01,
-            use_bradley: false,
-            bradley_size: 50,
-            bradley_threshold: 15,
+
+/// Takes sampling params and the full coordinate set, with the intensity weight of each
+/// coordinate, returns the final sample.
+///
+/// A sampler that can't run on the candidates, such as grid sampling with cells of size 0,
+/// logs the error and leaves the formation empty.
+pub fn run_sampling_stage(
+    params: &SamplingParams,
+    initial_coords: &[Coordinate],
+    weights: &[f32],
+    progress: &Progress,
+) -> Result<Vec<Coordinate>, Cancelled> {
+    debug!("Rerunning the sampling stage");
+
+    // without candidates there is nothing to sample, which the window shows as is
+    if initial_coords.is_empty() {
+        return Ok(Vec::new())
+    }
+    match sample_candidates(initial_coords, weights, params.sample_count, params.sampling_type, params.min_spacing, progress) {
+        Ok(coords) => Ok(coords),
+        Err(Error::Cancelled) => Err(Cancelled),
+        Err(e) => {
+            error!("{}", e);
+            Ok(Vec::new())
         }
     }
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
-pub struct SamplingParams {
-    pub sample_count: u32,
-    sampling_type: SamplingType,
+/// The coordinates produced by a `PipelineJob`.
+pub struct PipelineOutput {
+    pub intermediate_coords: Option<Candidates>,
+    pub final_light_coords: Vec<Coordinate>,
+    /// Statistics of the whole run, including the preprocessing stage when it was reused
+    pub stats: Option<RunStats>,
+    /// Scores of the sampled lights against the candidate pixels
+    pub metrics: Option<FormationMetrics>,
 }
 
-impl Default for SamplingParams {
-    fn default() -> Self {
-        Self {
-            sample_count: 30,
-            sampling_type: SamplingType::Farthest,
-        }
-    }
+/// Where a `PipelineJob` starts from.
+pub enum JobInput {
+    /// Runs the preprocessing stage on the source image before sampling
+    Image(Option<DynamicImage>),
+    /// Samples the coordinates of an earlier preprocessing stage again
+    Candidates(Option<Candidates>),
 }
 
+/// A run of the pipeline stages on a background thread, so that the window keeps
+/// redrawing, shows the progress of the run, and can cancel it.
+pub struct PipelineJob {
+    pub preprocessing_params: PreprocessingParams,
+    pub sampling_params: SamplingParams,
+    pub progress: Arc<Progress>,
+    pub started: Instant,
+    handle: JoinHandle<Result<PipelineOutput, Cancelled>>,
+}
 
+impl PipelineJob {
+    /// Starts a run of the stages that `input` hasn't been through yet.
+    pub fn spawn(
+        preprocessing_params: PreprocessingParams,
+        sampling_params: SamplingParams,
+        input: JobInput,
+    ) -> Self {
+        let progress = Arc::new(Progress::new());
+        let handle = thread::spawn({
+            let progress = Arc::clone(&progress);
+            move || {
+                let intermediate_coords = match input {
+                    JobInput::Image(image) => run_preprocessing_stage(&preprocessing_params, &image, &progress)?,
+                    JobInput::Candidates(intermediate_coords) => intermediate_coords,
+                };
+                let final_light_coords = match &intermediate_coords {
+                    Some(candidates) => run_sampling_stage(&sampling_params, &candidates.output.coords(), &candidates.weights, &progress)?,
+                    None => vec![],
+                };
+                let stats = intermediate_coords.as_ref().and_then(|candidates| {
+                    let candidates = &candidates.output;
+                    let stats = candidates.stats()?.sampled(&candidates.coords(), &final_light_coords);
+                    Some(stats.record_timings(&progress))
+                });
+                let metrics = intermediate_coords.as_ref().and_then(|candidates| {
+                    let candidates = &candidates.output;
+                    evaluate_formation(&candidates.coords(), &final_light_coords, candidates.width(), candidates.height(), None)
+                });
+                Ok(PipelineOutput { intermediate_coords, final_light_coords, stats, metrics })
+            }
+        });
 
-/// Takes pre-processing params, loads/processes an image, returns all valid coordinates.
-pub fn run_preprocessing_stage<'a>(
-    params: &PreprocessingParams,
-    image: &'a Option<image::DynamicImage>,
-) -> Option<CoordinateOutput> {
-    // println!("Rerunning EXPENSIVE pre-processing stage...");
-    
-    // If no image is loaded, there are no coordinates to return.
-    let Some(source_img) = image else {
-        return None
-    };
-
-    // using a CoW pointer to avoid cloning unless necessary down the line
-    let mut img_cow: Cow<'a, DynamicImage> = Cow::Borrowed(source_img);
+        Self {
+            preprocessing_params,
+            sampling_params,
+            progress,
+            started: Instant::now(),
+            handle,
+        }
+    }
 
-    if params.use_bradley {
-        img_cow = Cow::Owned(DynamicImage::ImageLuma8(bradley_adaptive_threshold(
-            &img_cow.to_luma8(),
-            params.bradley_size,
-            params.bradley_threshold,
-        )));
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
     }
-    
-    if let Some((width, height)) = params.resize {
-        // .thumbnail() takes a reference, so we pass our Cow's content.
-        img_cow = Cow::Owned(img_cow.thumbnail(width, height));
+
+    /// Waits for the run to finish, returning `Err(Cancelled)` if it was cancelled first.
+    pub fn join(self) -> Result<PipelineOutput, Cancelled> {
+        self.handle.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic))
     }
+}
 
-    let (image_width, image_height) = img_cow.dimensions();
+/// A search for the best settings of the current image and drone count, on a background
+/// thread like a `PipelineJob`.
+///
+/// The search keeps the crop, background removal and adjustments of the current settings, and
+/// tries the thresholds, Bradley window sizes, resolutions and samplers of `TuneSpace::default()`.
+pub struct AutoTuneJob {
+    pub progress: Arc<Progress>,
+    handle: JoinHandle<Result<Vec<TuneResult>, Cancelled>>,
+}
 
-    let initial_coords = image_to_coordinates(&img_cow, params.global_threshold, params.img_type);
+impl AutoTuneJob {
+    pub fn spawn(
+        preprocessing_params: PreprocessingParams,
+        sampling_params: SamplingParams,
+        image: DynamicImage,
+    ) -> Self {
+        let progress = Arc::new(Progress::new());
+        let handle = thread::spawn({
+            let progress = Arc::clone(&progress);
+            move || {
+                let space = TuneSpace::default();
+                let largest = space.largest_resolution().expect("the default space has resolutions");
+                let with_choice = |threshold, bradley_size: Option<u32>, size| {
+                    let mut params = preprocessing_params;
+                    apply_preprocessing_choice(&mut params, threshold, bradley_size, size);
+                    params
+                };
+
+                let reference = Reference::new(
+                    prepare_image(&preprocessing_params, &image).into_owned(),
+                    &Resize::new(largest, preprocessing_params.resize_mode, preprocessing_params.resize_filter),
+                    preprocessing_params.img_type,
+                );
+                let image = Some(image);
+
+                search(
+                    &space,
+                    sampling_params.sample_count,
+                    &reference,
+                    TUNE_RESULTS,
+                    &progress,
+                    |threshold, bradley_size, size, progress| {
+                        let params = with_choice(threshold, bradley_size, size);
+                        let candidates = run_preprocessing_stage(&params, &image, progress)?
+                            .expect("the search always has an image");
+                        Ok((candidates.output, candidates.weights))
+                    },
+                    |candidates, weights, sampling_type, progress| {
+                        let params = SamplingParams { sampling_type, ..sampling_params };
+                        run_sampling_stage(&params, &candidates.coords(), weights, progress)
+                    },
+                )
+            }
+        });
 
-    Some(
-        CoordinateOutput::new(
-            initial_coords,
-            image_width,
-            image_height,
-        )
-    )
-}
+        Self {
+            progress,
+            handle,
+        }
+    }
 
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
 
-/// Takes sampling params and the full coordinate set, returns the final sample.
-pub fn run_sampling_stage(
-    params: &SamplingParams,
-    intermediate_coords: Option<CoordinateOutput>,
-) -> Vec<Coordinate> {
-    // println!("Rerunning CHEAP sampling stage...");
-    // This is where you would apply your grid, farthest-point, etc., sampling
-    // algorithm to the `intermediate_coords`.
-
-    let initial_coords = if let Some(coords) = intermediate_coords {
-        coords.coords()
-    } else {
-        vec![]
-    };
+    /// Waits for the search to finish, returning `Err(Cancelled)` if it was cancelled first.
+    pub fn join(self) -> Result<Vec<TuneResult>, Cancelled> {
+        self.handle.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    }
+}
 
-    // if the initial coordinates set is less than the supplied number of points,
-    // don't sample and just return the whole thing
-    if initial_coords.len() <= params.sample_count.try_into().unwrap() {
-        initial_coords
-    } else {
-        match params.sampling_type {
-            SamplingType::Farthest => {
-                farthest_point_sampling(&initial_coords, params.sample_count)
-            },
-            SamplingType::Grid => {
-                grid_sampling(&initial_coords, params.sample_count)
-            }
-        }
+/// Sets the threshold, Bradley window size and resolution of a choice of the search, keeping the
+/// resize mode and filter of `params`.
+fn apply_preprocessing_choice(params: &mut PreprocessingParams, threshold: f32, bradley_size: Option<u32>, size: (u32, u32)) {
+    params.threshold = threshold;
+    params.bradley = bradley_size.is_some();
+    if let Some(bradley_size) = bradley_size {
+        params.bradley_size = bradley_size;
     }
+    params.resize = Some(size);
+}
+
+/// Fills in the settings that produced `result`.
+pub fn apply_tune_result(result: &TuneResult, preprocessing_params: &mut PreprocessingParams, sampling_params: &mut SamplingParams) {
+    let choice = &result.choice;
+    apply_preprocessing_choice(preprocessing_params, choice.threshold, choice.bradley_size, choice.resize);
+    sampling_params.sampling_type = choice.sampling;
 }
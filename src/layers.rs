@@ -0,0 +1,161 @@
+use pyo3::prelude::*;
+
+use crate::{
+    error::Error,
+    export::normalize_frames,
+    progress::{Cancelled, Progress},
+    sampling::{farthest_point_sampling_from_with_progress, farthest_point_sampling_with_progress},
+    utils::{Coordinate, ExportCoordinate},
+};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum LayerMode {
+    /// Every layer holds the same sampled points
+    Replicate,
+    /// Each layer is resampled to fill the gaps left by the layers in front of it
+    Interleave,
+}
+
+impl FromPyObject<'_> for LayerMode {
+    fn extract_bound(ob: &Bound<'_, PyAny>) -> PyResult<Self> {
+        if let Ok(s) = ob.extract::<&str>() {
+            match s.to_lowercase().as_str() {
+                "replicate" => Ok(Self::Replicate),
+                "interleave" => Ok(Self::Interleave),
+                _ => Err(Error::invalid_parameter("The valid values for 'mode' are 'replicate' and 'interleave'.").into())
+            }
+        } else {
+            Ok(Self::Replicate)
+        }
+    }
+}
+
+/// Settings for stacking a flat formation into several parallel layers.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct LayerParams {
+    /// Number of layers, including the front one.
+    pub count: u32,
+    /// Distance between consecutive layers along the depth axis, in meters.
+    pub spacing: f64,
+    /// Shift of each layer relative to the one in front of it, along the formation's
+    /// x (right) and y (up) axes, in meters.
+    pub offset: (f64, f64),
+    pub mode: LayerMode,
+}
+
+impl Default for LayerParams {
+    fn default() -> Self {
+        Self {
+            count: 3,
+            spacing: 4.0,
+            offset: (0.0, 0.0),
+            mode: LayerMode::Replicate,
+        }
+    }
+}
+
+/// Samples the points of each layer from the same set of candidate pixels.
+///
+/// With `LayerMode::Replicate`, the front layer is sampled once and copied into every layer.
+/// With `LayerMode::Interleave`, each layer continues the farthest point sampling of all
+/// the layers in front of it, so that back layers fill the gaps between the front drones
+/// when the formation is seen head-on.
+///
+/// # Arguments
+/// * `candidates` - The candidate pixels, as returned by `image_to_coordinates`.
+/// * `n` - The number of drones in each layer.
+/// * `params` - The number of layers and how they are filled.
+///
+/// * `progress` - Reports the points selected for each layer, and stops the sampling once it is cancelled.
+///
+/// # Returns
+/// The sampled coordinates of each layer, from front to back.
+pub fn sample_layers_with_progress(
+    candidates: &[Coordinate],
+    n: u32,
+    params: &LayerParams,
+    progress: &Progress,
+) -> Result<Vec<Vec<Coordinate>>, Cancelled> {
+    let front = farthest_point_sampling_with_progress(candidates, n, progress)?;
+    match params.mode {
+        LayerMode::Replicate => Ok(vec![front; params.count as usize]),
+        LayerMode::Interleave => {
+            let mut selected = front.clone();
+            let mut layers = vec![front];
+            for _ in 1..params.count {
+                let layer = farthest_point_sampling_from_with_progress(candidates, &selected, n, progress)?;
+                selected.extend_from_slice(&layer);
+                layers.push(layer);
+            }
+            Ok(layers)
+        }
+    }
+}
+
+/// Combines the layers into a single 3D formation.
+///
+/// All layers are normalized with the same bounding box, so that they line up. Layer `i`
+/// is then pushed back by `i * spacing` along the depth axis and shifted by `i * offset`.
+///
+/// # Returns
+/// The drones of every layer, from front to back, or an error message if there are
+/// no coordinates to place.
+pub fn stack_layers(
+    layers: &[Vec<Coordinate>],
+    max_dim: f64,
+    params: &LayerParams,
+) -> Result<Vec<ExportCoordinate>, String> {
+    let normalized = normalize_frames(layers, max_dim)?;
+    Ok(normalized
+        .into_iter()
+        .enumerate()
+        .flat_map(|(i, layer)| {
+            let i = i as f64;
+            layer.into_iter().map(move |coord| ExportCoordinate::new_3d(
+                coord.x() + i * params.offset.0,
+                coord.y() + i * params.offset.1,
+                i * params.spacing,
+            ))
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layers_are_pushed_back_and_shifted_by_their_index() {
+        let layer = vec![Coordinate::new(0, 0), Coordinate::new(10, 10)];
+        let params = LayerParams { count: 3, spacing: 2.0, offset: (1.0, -0.5), mode: LayerMode::Replicate };
+        let formation = stack_layers(&vec![layer; 3], 10.0, &params).unwrap();
+
+        assert_eq!(formation.len(), 6);
+        let front = &formation[..2];
+        for (i, layer) in formation.chunks(2).enumerate() {
+            let i = i as f64;
+            for (coord, front) in layer.iter().zip(front) {
+                assert_eq!(coord.x(), front.x() + i);
+                assert_eq!(coord.y(), front.y() - i * 0.5);
+                assert_eq!(coord.z(), i * 2.0);
+            }
+        }
+    }
+
+    #[test]
+    fn interleaved_layers_fill_the_gaps_of_the_front_ones() {
+        let candidates: Vec<Coordinate> = (0..20).map(|x| Coordinate::new(x, 0)).collect();
+        let params = LayerParams { count: 3, mode: LayerMode::Interleave, ..LayerParams::default() };
+        let layers = sample_layers_with_progress(&candidates, 4, &params, &Progress::new()).unwrap();
+
+        assert_eq!(layers.len(), 3);
+        let mut all: Vec<Coordinate> = layers.concat();
+        all.sort_by_key(|coord| coord.x());
+        all.dedup();
+        assert_eq!(all.len(), 12);
+
+        let params = LayerParams { mode: LayerMode::Replicate, ..params };
+        let layers = sample_layers_with_progress(&candidates, 4, &params, &Progress::new()).unwrap();
+        assert!(layers.iter().all(|layer| *layer == layers[0]));
+    }
+}
@@ -1,41 +1,108 @@
-mod raster;
-mod transformation;
-mod utils;
-mod sampling;
-mod thresholding;
+pub mod raster;
+pub mod transformation;
+pub mod utils;
+pub mod sampling;
+pub mod thresholding;
+pub mod export;
+pub mod vector;
+pub mod text;
+mod animation;
+mod mesh;
+pub mod depth;
+mod layers;
+mod color;
+mod palette;
+pub mod mask;
+pub mod resize;
+mod source;
+pub mod error;
+pub mod pipeline;
+pub mod progress;
+mod logging;
+pub mod stats;
+pub mod metrics;
+pub mod tune;
+pub mod crazyswarm;
+pub mod geometry;
+pub mod adjust;
 
-use pyo3::{exceptions::PyValueError, prelude::*};
+use pyo3::{exceptions::PyRuntimeError, prelude::*};
 use image::DynamicImage;
+use log::{debug, info};
+use rayon::prelude::*;
 
 use crate::{
+    animation::match_frames,
+    depth::{apply_depths, sample_depths, DepthParams},
+    error::{register_exceptions, Error},
+    export::{encode_by_extension, normalize_coordinates, save_image, write_output},
+    layers::{sample_layers_with_progress, stack_layers, LayerMode, LayerParams},
+    logging::init_python_logging,
+    mask::{filter_color_coordinates, foreground_mask, remove_background, MaskParams},
+    mesh::{load_mesh, mesh_surface_sampling, UpAxis},
+    metrics::{evaluate_formation, render_lights, FormationMetrics},
+    palette::{quantize_color_coordinates, LedCalibration, Palette, PaletteMethod},
+    pipeline::{extract_candidates, sample_candidates, Pipeline},
+    progress::{run_with_progress, Progress, Stage},
     raster::{coordinates_to_color_image, coordinates_to_image, BackgroundColor, SamplingType}, 
-    sampling::{color_albedo_sampling, farthest_point_sampling, grid_sampling}, 
+    resize::{resize_image, Resize, ResizeFilter, ResizeMode},
+    source::{check_readable, ColorInput, CoordinateInput, FramesSource, ImageSource},
+    stats::{effective_threshold, RunStats},
+    sampling::{
+        color_albedo_sampling_with_progress, color_farthest_point_sampling_with_progress,
+        color_weighted_sampling_with_progress, farthest_point_sampling_with_progress, grid_sampling,
+    },
     thresholding::bradley_adaptive_threshold, 
-    transformation::{color_image_to_coordinates, image_to_coordinates, ImgType}, 
-    utils::{ColorCoordinateOutput, CoordinateOutput}
+    transformation::{color_image_to_coordinates, image_to_coordinates, image_to_weighted_coordinates, ColorCoordinate, ImgType}, 
+    tune::{search, Reference, TuneResult, TuneSpace},
+    utils::{
+        colors_array, coordinates_array, image_array, ColorCoordinateOutput, Coordinate, Coordinate3DOutput,
+        CoordinateOutput, FrameSequenceOutput,
+    },
+    text::{render_text, TextAlign, TextLayout, MAX_TEXT_SIZE},
+    vector::svg_path_sampling,
 };
 
-#[pyfunction(signature=(input_path, n, sample=SamplingType::Farthest, img_type=ImgType::BlackOnWhite, resize=Some((256, 256)), threshold=0.01, bradley=false, bradley_threshold=15, bradley_size=16, output_path="output/coordinates.png"))]
+#[pyfunction(signature=(input_path, n, sample=SamplingType::Farthest, img_type=ImgType::BlackOnWhite, resize=Some((256, 256)), threshold=0.01, bradley=false, bradley_threshold=15, bradley_size=16, output_path="output/coordinates.png", min_spacing=0.0, alpha_threshold=128, chroma_key=None, chroma_tolerance=20.0, remove_border_background=false, border_tolerance=10.0, resize_mode=ResizeMode::Fit, resize_filter=ResizeFilter::Triangle, progress=None))]
 /// Processes a black and white image into a sample of coordinate pixels
 ///
 /// Arguments:
-///     input_path: str 
-///         path to source image
+///     input_path: str | bytes | numpy.ndarray | PIL.Image
+///         path to source image, the bytes of an image file, or the image itself as a PIL image or a uint8 (or float in [0, 1]) array of shape (H, W), (H, W, 3) or (H, W, 4). SVG files are rasterized at the `resize` resolution
 ///     n: u32
 ///         number of pixels to select
 ///     sample: str
-///         selecting type of sampling, either 'grid' or 'farthest'. Defaults to 'farthest'
+///         selecting type of sampling, either 'grid', 'farthest', 'weighted' or 'path'. Defaults to 'farthest'. 'weighted' places more pixels where the drawing is more intense (darker for 'black_on_white', brighter for 'white_on_black'). 'path' is only valid for SVG files, and places the pixels at equal spacing along the SVG's paths without rasterizing it
 ///     img_type: str 
 ///         selecting type of image, either 'black_on_white' or 'white_on_black'. Defaults to 'black_on_white'
 ///     resize: (width: u32, height: u32)
-///         maximum dimensions by which to resize the image. Will not be resized to exactly those dimensions, but instead to fit within them. Defaults to width = 256, height = 256. Set to None to prevent resizing
+///         dimensions by which to resize the image, following `resize_mode`. With the default 'fit' mode, the image is not resized to exactly those dimensions, but instead to fit within them. Defaults to width = 256, height = 256. Set to None to prevent resizing
 ///     threshold: f64 
 ///         brightness threshold that gets counted as a 'white' pixel. Defaults to 0.01
 ///     output_path: str
 ///         path where the output coordinates image will be saved. Note that, if the intermediate directories do not exist, they will be created. Defaults to 'output/coordinates.png'
+///     min_spacing: f64
+///         minimum distance between two selected pixels when using 'weighted' sampling. Defaults to 0.0
+///     alpha_threshold: u8
+///         pixels with an alpha below this value are treated as background. Defaults to 128. Set to 0 to keep every pixel
+///     chroma_key: (int, int, int)
+///         a background color to remove, such as the green of a green screen. Defaults to None
+///     chroma_tolerance: f32
+///         how far a color may be from `chroma_key` and still be removed, as a CIELAB color difference. Defaults to 20.0
+///     remove_border_background: bool
+///         removes the regions of uniform color connected to the edges of the image, such as a sky behind the subject. Defaults to False
+///     border_tolerance: f32
+///         how far a color may be from the edge color it was reached from and still be removed, as a CIELAB color difference. Defaults to 10.0
+///     resize_mode: str
+///         how the image is brought to the `resize` dimensions, either 'fit' (fit within them, only ever shrinking), 'upscale' (fit within them, enlarging smaller images), 'fill' (cover them and crop the overflow around the center) or 'exact' (stretch to them, ignoring aspect ratio). Defaults to 'fit'
+///     resize_filter: str
+///         resampling filter used when resizing, either 'nearest', 'triangle' or 'lanczos'. 'nearest' keeps hard edges when enlarging small logos. Defaults to 'triangle'
+///     progress: callable
+///         called with the (done, total) counts of the sampling step while it runs, such as to update a progress bar. Raising an exception from it stops the run. Defaults to None
 #[allow(clippy::too_many_arguments)]
 pub fn process_image(
-    input_path: String, 
+    py: Python<'_>,
+    input_path: ImageSource, 
     n: u32, 
     sample: SamplingType, 
     img_type: ImgType,
@@ -45,9 +112,19 @@ pub fn process_image(
     bradley_threshold: u8,
     bradley_size: u32,
     output_path: &str,
+    min_spacing: f64,
+    alpha_threshold: u8,
+    chroma_key: Option<(u8, u8, u8)>,
+    chroma_tolerance: f32,
+    remove_border_background: bool,
+    border_tolerance: f32,
+    resize_mode: ResizeMode,
+    resize_filter: ResizeFilter,
+    progress: Option<Bound<'_, PyAny>>,
 ) -> PyResult<()> {
 
     let coords_output = process_image_to_coordinates(
+        py,
         input_path, 
         n, 
         sample, 
@@ -56,51 +133,72 @@ pub fn process_image(
         threshold, 
         bradley, 
         bradley_threshold,
-        bradley_size
+        bradley_size,
+        min_spacing,
+        alpha_threshold,
+        chroma_key,
+        chroma_tolerance,
+        remove_border_background,
+        border_tolerance,
+        resize_mode,
+        resize_filter,
+        progress,
     )?;
 
-    // 4. Turn the sampled coordinates back into an image
-    let output_img = coordinates_to_image(
-        coords_output.width(),
-        coords_output.height(),
-        &coords_output.borrow_coords(),
-    );
-
-    // creating intermediate directories if necessary
-    let path = std::path::Path::new(output_path);
-    if let Some(prefix) = path.parent() {
-        std::fs::create_dir_all(prefix).unwrap();
-    }
+    py.allow_threads(|| {
+        // 4. Turn the sampled coordinates back into an image
+        let output_img = coordinates_to_image(
+            coords_output.width(),
+            coords_output.height(),
+            &coords_output.borrow_coords(),
+        );
 
-    match output_img.save(output_path) {
-        Ok(_) => Ok(()),
-        Err(e) => Err(PyValueError::new_err(format!("Unable to create file in path 'output/img.png': {}", e)))
-    }
+        Ok(save_image(std::path::Path::new(output_path), &output_img.into())?)
+    })
 }
 
-#[pyfunction(signature=(input_path, n, sample=SamplingType::Farthest, img_type=ImgType::BlackOnWhite, resize=Some((256, 256)), threshold=0.01, bradley=false, bradley_threshold=15, bradley_size=16))]
+#[pyfunction(signature=(input_path, n, sample=SamplingType::Farthest, img_type=ImgType::BlackOnWhite, resize=Some((256, 256)), threshold=0.01, bradley=false, bradley_threshold=15, bradley_size=16, min_spacing=0.0, alpha_threshold=128, chroma_key=None, chroma_tolerance=20.0, remove_border_background=false, border_tolerance=10.0, resize_mode=ResizeMode::Fit, resize_filter=ResizeFilter::Triangle, progress=None))]
 /// Processes an input image into a vector of (x, y) coordinates
 ///
 /// Arguments:
-///     input_path: str 
-///         path to source image
+///     input_path: str | bytes | numpy.ndarray | PIL.Image
+///         path to source image, the bytes of an image file, or the image itself as a PIL image or a uint8 (or float in [0, 1]) array of shape (H, W), (H, W, 3) or (H, W, 4). SVG files are rasterized at the `resize` resolution
 ///     n: u32
 ///         number of pixels to select
 ///     sample: str
-///         selecting type of sampling, either 'grid' or 'farthest'. Defaults to 'farthest'
+///         selecting type of sampling, either 'grid', 'farthest', 'weighted' or 'path'. Defaults to 'farthest'. 'weighted' places more pixels where the drawing is more intense (darker for 'black_on_white', brighter for 'white_on_black'). 'path' is only valid for SVG files, and places the pixels at equal spacing along the SVG's paths without rasterizing it
 ///     img_type: str 
 ///         selecting type of image, either 'black_on_white' or 'white_on_black'. Defaults to 'black_on_white'
 ///     resize: (width: u32, height: u32)
-///         maximum dimensions by which to resize the image. Will not be resized to exactly those dimensions, but instead to fit within them. Defaults to width = 256, height = 256. Set to None to prevent resizing
+///         dimensions by which to resize the image, following `resize_mode`. With the default 'fit' mode, the image is not resized to exactly those dimensions, but instead to fit within them. Defaults to width = 256, height = 256. Set to None to prevent resizing
 ///     threshold: f64 
 ///         brightness threshold that gets counted as a 'white' pixel. Defaults to 0.01
+///     min_spacing: f64
+///         minimum distance between two selected pixels when using 'weighted' sampling. Defaults to 0.0
+///     alpha_threshold: u8
+///         pixels with an alpha below this value are treated as background. Defaults to 128. Set to 0 to keep every pixel
+///     chroma_key: (int, int, int)
+///         a background color to remove, such as the green of a green screen. Defaults to None
+///     chroma_tolerance: f32
+///         how far a color may be from `chroma_key` and still be removed, as a CIELAB color difference. Defaults to 20.0
+///     remove_border_background: bool
+///         removes the regions of uniform color connected to the edges of the image, such as a sky behind the subject. Defaults to False
+///     border_tolerance: f32
+///         how far a color may be from the edge color it was reached from and still be removed, as a CIELAB color difference. Defaults to 10.0
+///     resize_mode: str
+///         how the image is brought to the `resize` dimensions, either 'fit' (fit within them, only ever shrinking), 'upscale' (fit within them, enlarging smaller images), 'fill' (cover them and crop the overflow around the center) or 'exact' (stretch to them, ignoring aspect ratio). Defaults to 'fit'
+///     resize_filter: str
+///         resampling filter used when resizing, either 'nearest', 'triangle' or 'lanczos'. 'nearest' keeps hard edges when enlarging small logos. Defaults to 'triangle'
+///     progress: callable
+///         called with the (done, total) counts of the sampling step while it runs, such as to update a progress bar. Raising an exception from it stops the run. Defaults to None
 ///
 /// Returns:
-///     coordinates: [(int, int)]
-///         the coordinates of each sampled pixel
+///     coordinates: CoordinateOutput
+///         the coordinates of each sampled pixel, as an (N, 2) uint32 array from `coords_array()` or a list of (x, y) tuples from `coords()`, along with the `width()` and `height()` of the processed image and the `stats()` of the run
 #[allow(clippy::too_many_arguments)]
 pub fn process_image_to_coordinates(
-    input_path: String, 
+    py: Python<'_>,
+    input_path: ImageSource, 
     n: u32, 
     sample: SamplingType, 
     img_type: ImgType,
@@ -109,178 +207,1476 @@ pub fn process_image_to_coordinates(
     bradley: bool,
     bradley_threshold: u8,
     bradley_size: u32,
-
+    min_spacing: f64,
+    alpha_threshold: u8,
+    chroma_key: Option<(u8, u8, u8)>,
+    chroma_tolerance: f32,
+    remove_border_background: bool,
+    border_tolerance: f32,
+    resize_mode: ResizeMode,
+    resize_filter: ResizeFilter,
+    progress: Option<Bound<'_, PyAny>>,
 ) -> PyResult<CoordinateOutput> {
+    let mask = MaskParams::new(alpha_threshold, chroma_key, chroma_tolerance, remove_border_background, border_tolerance);
+    run_with_progress(py, progress.as_ref(), |progress| sample_image(
+        input_path,
+        n,
+        sample,
+        img_type,
+        resize,
+        threshold,
+        bradley,
+        bradley_threshold,
+        bradley_size,
+        min_spacing,
+        resize_mode,
+        resize_filter,
+        &mask,
+        progress,
+    ))
+}
 
-    let source_img = match image::open(input_path) {
-        Ok(img) => img,
-        Err(e) => {
-            return Err(PyValueError::new_err(format!("Error loading image: {:?}", e)))
-        }
-    };
+/// Runs every step of `process_image_to_coordinates`, from loading the image to sampling it
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn sample_image(
+    input_path: ImageSource, 
+    n: u32, 
+    sample: SamplingType, 
+    img_type: ImgType,
+    resize: Option<(u32, u32)>,
+    threshold: f32, 
+    bradley: bool,
+    bradley_threshold: u8,
+    bradley_size: u32,
+    min_spacing: f64,
+    resize_mode: ResizeMode,
+    resize_filter: ResizeFilter,
+    mask: &MaskParams,
+    progress: &Progress,
+) -> Result<CoordinateOutput, Error> {
+    // path sampling works on the vector geometry directly, skipping the raster pipeline
+    if sample == SamplingType::Path {
+        let Some(path) = input_path.path() else {
+            return Err(Error::invalid_parameter("'path' sampling is only available for SVG files."))
+        };
+        check_readable(path)?;
+        progress.stage(Stage::Sampling)?;
+        let (sampled_coords, width, height) = svg_path_sampling(path, n, resize)
+            .map_err(|cause| Error::Decode { path: Some(path.to_path_buf()), cause })?;
+        info!("Sampled {} coordinates along the SVG paths", sampled_coords.len());
+        return Ok(CoordinateOutput::new(sampled_coords, width, height));
+    }
 
-    // adding a bradley thresholding step 
-    // do we want to apply this before or after resizing the image?
-    // let's say after
-    let img = if bradley {
-        DynamicImage::ImageLuma8(bradley_adaptive_threshold(&source_img.to_luma8(), bradley_size, bradley_threshold))
+    progress.stage(Stage::Loading)?;
+    let source_img = input_path.load(resize)?;
+    // the background is removed while the image still has its colors and alpha
+    let source_img = if mask.is_active(&source_img) {
+        remove_background(&source_img, mask)
     } else { source_img };
 
-    let img = if let Some((width, height)) = resize {
-        img.thumbnail(width, height)
-    } else { img };
-
-    let width = img.width();
-    let height = img.height();
-
-
-    println!("Image loaded successfully with dimensions: {}x{}", width, height);
+    process_loaded_image(
+        source_img,
+        n,
+        sample,
+        img_type,
+        resize.map(|size| Resize::new(size, resize_mode, resize_filter)),
+        threshold,
+        bradley,
+        bradley_threshold,
+        bradley_size,
+        min_spacing,
+        progress,
+    )
+}
 
-    // 2. Convert the brightest pixels to coordinates
-    // Let's get all pixels with any brightness for this example.
-    let initial_coords = image_to_coordinates(&img, threshold, img_type);
-    println!("Extracted {} initial coordinates.", initial_coords.len());
+/// Runs the thresholding and sampling steps of `process_image_to_coordinates` on an
+/// image that has already been loaded
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn process_loaded_image(
+    source_img: DynamicImage,
+    n: u32,
+    sample: SamplingType,
+    img_type: ImgType,
+    resize: Option<Resize>,
+    threshold: f32,
+    bradley: bool,
+    bradley_threshold: u8,
+    bradley_size: u32,
+    min_spacing: f64,
+    progress: &Progress,
+) -> Result<CoordinateOutput, Error> {
+    let (candidates, weights) = extract_candidates(
+        source_img,
+        img_type,
+        resize,
+        threshold,
+        bradley,
+        bradley_threshold,
+        bradley_size,
+        progress,
+    )?;
+    let (width, height) = (candidates.width(), candidates.height());
+    let stats = candidates.stats().unwrap_or_default();
+    let initial_coords = candidates.borrow_coords();
 
     // 3. Run a sampling algorithm on the coordinates
-    let sampled_coords = match sample {
-        SamplingType::Grid => {
-            grid_sampling(&initial_coords, n)
-        },
-        SamplingType::Farthest => {
-            farthest_point_sampling(&initial_coords, n)
-        }
-    };
+    let sampled_coords = sample_candidates(&initial_coords, &weights, n, sample, min_spacing, progress)?;
 
-    println!("Sampled down to {} coordinates.", sampled_coords.len());
+    info!("Sampled {} of {} candidate pixels", sampled_coords.len(), initial_coords.len());
+    let stats = stats.sampled(&initial_coords, &sampled_coords).record_timings(progress);
 
     Ok(
         CoordinateOutput::new(
             sampled_coords,
             width,
             height,
-        )
+        ).with_stats(stats)
     )
 }
 
-#[pyfunction(signature=(input_path, n, resize=Some((256, 256)), background_color="black", output_path="output/coordinates.png"))]
-/// Processes a color image into a sample of coordinate pixels
+#[pyfunction(signature=(input_paths, n, sample=SamplingType::Farthest, img_type=ImgType::BlackOnWhite, resize=Some((256, 256)), threshold=0.01, bradley=false, bradley_threshold=15, bradley_size=16, min_spacing=0.0, alpha_threshold=128, chroma_key=None, chroma_tolerance=20.0, remove_border_background=false, border_tolerance=10.0, resize_mode=ResizeMode::Fit, resize_filter=ResizeFilter::Triangle, workers=None, progress=None))]
+/// Processes many images into vectors of (x, y) coordinates at once, on a pool of threads
+///
+/// Each image goes through the same steps as `process_image_to_coordinates`, with the same settings.
+/// An image that fails doesn't stop the others: its error takes its place in the results instead.
+///
+/// Arguments:
+///     input_paths: [str | bytes | numpy.ndarray | PIL.Image]
+///         the images to process, in any of the forms accepted by `process_image_to_coordinates`
+///     workers: u32
+///         number of threads processing the images. Defaults to None, which uses one thread per CPU core
+///     progress: callable
+///         called with the number of images done and the total number of images while the batch runs. Raising an exception from it stops the batch. Defaults to None
+///
+/// The other arguments are those of `process_image_to_coordinates`.
+///
+/// Returns:
+///     results: [CoordinateOutput | Exception]
+///         the coordinates of each image, or the error raised while processing it, in the order of `input_paths`
+#[allow(clippy::too_many_arguments)]
+pub fn process_batch(
+    py: Python<'_>,
+    input_paths: Vec<Bound<'_, PyAny>>,
+    n: u32,
+    sample: SamplingType,
+    img_type: ImgType,
+    resize: Option<(u32, u32)>,
+    threshold: f32,
+    bradley: bool,
+    bradley_threshold: u8,
+    bradley_size: u32,
+    min_spacing: f64,
+    alpha_threshold: u8,
+    chroma_key: Option<(u8, u8, u8)>,
+    chroma_tolerance: f32,
+    remove_border_background: bool,
+    border_tolerance: f32,
+    resize_mode: ResizeMode,
+    resize_filter: ResizeFilter,
+    workers: Option<usize>,
+    progress: Option<Bound<'_, PyAny>>,
+) -> PyResult<Vec<PyObject>> {
+    if workers == Some(0) {
+        return Err(Error::invalid_parameter("'workers' must be at least 1.").into())
+    }
+    // a pool of 0 threads picks the number of threads from the number of CPU cores
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(workers.unwrap_or(0))
+        .build()
+        .map_err(|e| PyRuntimeError::new_err(format!("Unable to start the worker threads: {}", e)))?;
+
+    let mask = MaskParams::new(alpha_threshold, chroma_key, chroma_tolerance, remove_border_background, border_tolerance);
+    // the images are converted while holding the GIL, and an image that can't be is one more error
+    let input_paths: Vec<PyResult<ImageSource>> = input_paths.iter().map(|input_path| input_path.extract()).collect();
+
+    let results: Vec<PyResult<Result<CoordinateOutput, Error>>> = run_with_progress(py, progress.as_ref(), |progress| {
+        progress.report(0, input_paths.len() as u64)?;
+        let results: Vec<_> = pool.install(|| {
+            input_paths
+                .into_par_iter()
+                .map(|input_path| {
+                    // each image keeps its own stages, while the batch counts the images done
+                    let result = input_path.map(|input_path| sample_image(
+                        input_path,
+                        n,
+                        sample,
+                        img_type,
+                        resize,
+                        threshold,
+                        bradley,
+                        bradley_threshold,
+                        bradley_size,
+                        min_spacing,
+                        resize_mode,
+                        resize_filter,
+                        &mask,
+                        &progress.subtask(),
+                    ));
+                    let _ = progress.advance();
+                    result
+                })
+                .collect()
+        });
+        // a cancelled batch stops as a whole, rather than with an error in place of each image left
+        if progress.is_cancelled() {
+            return Err(Error::Cancelled)
+        }
+        Ok(results)
+    })?;
+
+    results.into_iter().map(|result| match result.and_then(|output| Ok(output?)) {
+        Ok(output) => Ok(Bound::new(py, output)?.into_any().unbind()),
+        Err(e) => Ok(e.into_value(py).into_any()),
+    }).collect()
+}
+
+#[pyfunction(signature=(input_path, n, img_type=ImgType::BlackOnWhite, thresholds=None, bradley_sizes=None, resolutions=None, samplers=None, bradley_threshold=15, top=3, progress=None))]
+/// Searches for the settings that give the best formation of `n` drones
+///
+/// Every combination of the given thresholds, Bradley window sizes, resolutions and sampling
+/// types is tried. Each formation is rendered as lights and compared with SSIM to the source
+/// image, at the largest of the resolutions, so that settings keeping more of the drawing score
+/// higher than those that lose parts of it. Settings that keep fewer than `n` candidate pixels
+/// are skipped.
+///
+/// Arguments:
+///     input_path: str | bytes | numpy.ndarray | PIL.Image
+///         the source image, in any form accepted by `process_image_to_coordinates`
+///     n: u32
+///         number of drones in the formation
+///     img_type: str
+///         describes the composition of the image, either 'black_on_white' or 'white_on_black'. Defaults to 'black_on_white'
+///     thresholds: [float]
+///         the global thresholds to try. Defaults to None, which tries 0.01, 0.05, 0.1 and 0.25
+///     bradley_sizes: [u32 | None]
+///         the Bradley window sizes to try, where None tries the global threshold alone. Defaults to None, which tries None, 8, 16 and 32
+///     resolutions: [(width: u32, height: u32)]
+///         the sizes to resize the image to fit within. Defaults to None, which tries (128, 128) and (256, 256)
+///     samplers: [str]
+///         the sampling types to try, among 'farthest' and 'weighted'. Defaults to None, which tries both
+///     bradley_threshold: u8
+///         brightness threshold of the Bradley thresholding. Defaults to 15
+///     top: usize
+///         number of formations to return. Defaults to 3
+///     progress: callable
+///         called with the number of combinations tried and the total number of combinations while the search runs. Raising an exception from it stops the search. Defaults to None
+///
+/// Returns:
+///     results: [TuneResult]
+///         the best formations, best first, with the settings that produced them
+#[allow(clippy::too_many_arguments)]
+pub fn auto_tune(
+    py: Python<'_>,
+    input_path: ImageSource,
+    n: u32,
+    img_type: ImgType,
+    thresholds: Option<Vec<f32>>,
+    bradley_sizes: Option<Vec<Option<u32>>>,
+    resolutions: Option<Vec<(u32, u32)>>,
+    samplers: Option<Vec<SamplingType>>,
+    bradley_threshold: u8,
+    top: usize,
+    progress: Option<Bound<'_, PyAny>>,
+) -> PyResult<Vec<TuneResult>> {
+    let defaults = TuneSpace::default();
+    let space = TuneSpace {
+        thresholds: thresholds.unwrap_or(defaults.thresholds),
+        bradley_sizes: bradley_sizes.unwrap_or(defaults.bradley_sizes),
+        resolutions: resolutions.unwrap_or(defaults.resolutions),
+        samplers: samplers.unwrap_or(defaults.samplers),
+    };
+    if n == 0 || top == 0 {
+        return Err(Error::invalid_parameter("'n' and 'top' must be at least 1.").into())
+    }
+    if space.thresholds.iter().any(|&threshold| !(threshold > 0.0 && threshold <= 1.0)) {
+        return Err(Error::invalid_parameter("The 'thresholds' must be greater than 0.0 and at most 1.0.").into())
+    }
+    if space.bradley_sizes.contains(&Some(0)) || space.resolutions.iter().any(|&(width, height)| width == 0 || height == 0) {
+        return Err(Error::invalid_parameter("The 'bradley_sizes' and 'resolutions' must be at least 1.").into())
+    }
+    // grid sampling takes a cell size rather than a number of drones, so it can't meet the budget
+    if space.samplers.iter().any(|sampling| !matches!(sampling, SamplingType::Farthest | SamplingType::Weighted)) {
+        return Err(Error::invalid_parameter("The valid values for 'samplers' are 'farthest' and 'weighted'.").into())
+    }
+    let Some(largest) = space.largest_resolution() else {
+        return Err(Error::invalid_parameter("'resolutions' must hold at least one size.").into())
+    };
+    if space.thresholds.is_empty() || space.bradley_sizes.is_empty() || space.samplers.is_empty() {
+        return Err(Error::invalid_parameter("'thresholds', 'bradley_sizes' and 'samplers' must each hold at least one value.").into())
+    }
+
+    run_with_progress(py, progress.as_ref(), |progress| {
+        progress.stage(Stage::Loading)?;
+        let source_img = input_path.load(Some(largest))?;
+        let resize = |size| Resize::new(size, ResizeMode::Fit, ResizeFilter::Triangle);
+        let reference = Reference::new(source_img.clone(), &resize(largest), img_type);
+
+        let results = search(
+            &space,
+            n,
+            &reference,
+            top,
+            progress,
+            |threshold, bradley_size, size, progress| Ok(extract_candidates(
+                source_img.clone(),
+                img_type,
+                Some(resize(size)),
+                threshold,
+                bradley_size.is_some(),
+                bradley_threshold,
+                bradley_size.unwrap_or(1),
+                progress,
+            )?),
+            |candidates, weights, sampling, progress| {
+                sample_candidates(&candidates.coords(), weights, n, sampling, 0.0, progress)
+            },
+        )?;
+        if results.is_empty() {
+            return Err(Error::EmptyResult(format!("None of the settings kept at least {} candidate pixels.", n)))
+        }
+        Ok(results)
+    })
+}
+
+#[pyfunction(signature=(input_path, n, sample=SamplingType::Farthest, img_type=ImgType::BlackOnWhite, resize=Some((256, 256)), threshold=0.01, bradley=false, bradley_threshold=15, bradley_size=16, min_spacing=0.0, resize_mode=ResizeMode::Fit, resize_filter=ResizeFilter::Triangle, progress=None))]
+/// Processes an animation into per-frame coordinates, keeping each drone's identity across frames
+///
+/// Each frame goes through the same steps as `process_image_to_coordinates`. The drones of each
+/// frame are then matched to the drones of the frame before so that the total distance they move
+/// is minimal, rather than reshuffling every drone between frames.
+///
+/// Every frame has `n` drones, or as many as the fullest frame with 'grid' sampling. Frames with
+/// fewer sampled points, including blank frames, leave the unmatched drones holding their
+/// previous position, and the drones spread out again when a later frame gains points.
+///
+/// Arguments:
+///     input_path: str | list
+///         path to an animated GIF, or to a directory holding a numbered image sequence (e.g. frame_001.png, frame_002.png, ...). A list of images, in any of the forms accepted by `process_image`, is also accepted
+///     n: u32
+///         number of pixels to select in each frame
+///     sample: str
+///         selecting type of sampling, either 'grid', 'farthest' or 'weighted'. Defaults to 'farthest'
+///     img_type: str 
+///         selecting type of image, either 'black_on_white' or 'white_on_black'. Defaults to 'black_on_white'
+///     resize: (width: u32, height: u32)
+///         maximum dimensions by which to resize each frame. Defaults to width = 256, height = 256. Set to None to prevent resizing
+///     threshold: f64 
+///         brightness threshold that gets counted as a 'white' pixel. Defaults to 0.01
+///     min_spacing: f64
+///         minimum distance between two selected pixels when using 'weighted' sampling. Defaults to 0.0
+///     resize_mode: str
+///         how the image is brought to the `resize` dimensions, either 'fit' (fit within them, only ever shrinking), 'upscale' (fit within them, enlarging smaller images), 'fill' (cover them and crop the overflow around the center) or 'exact' (stretch to them, ignoring aspect ratio). Defaults to 'fit'
+///     resize_filter: str
+///         resampling filter used when resizing, either 'nearest', 'triangle' or 'lanczos'. 'nearest' keeps hard edges when enlarging small logos. Defaults to 'triangle'
+///     progress: callable
+///         called with the number of frames done and the total number of frames while they are processed. Raising an exception from it stops the run. Defaults to None
+///
+/// Returns:
+///     frames: FrameSequenceOutput
+///         the coordinates of each frame, which can be indexed and iterated over as `CoordinateOutput`s, or taken as an (F, N, 2) uint32 array from `frames_array()` when every frame has the same number of drones
+#[allow(clippy::too_many_arguments)]
+pub fn process_animation(
+    py: Python<'_>,
+    input_path: FramesSource,
+    n: u32,
+    sample: SamplingType,
+    img_type: ImgType,
+    resize: Option<(u32, u32)>,
+    threshold: f32,
+    bradley: bool,
+    bradley_threshold: u8,
+    bradley_size: u32,
+    min_spacing: f64,
+    resize_mode: ResizeMode,
+    resize_filter: ResizeFilter,
+    progress: Option<Bound<'_, PyAny>>,
+) -> PyResult<FrameSequenceOutput> {
+    run_with_progress(py, progress.as_ref(), |progress| {
+        progress.stage(Stage::Loading)?;
+        let source_frames = input_path.load(resize)?;
+        progress.stage(Stage::Sampling)?;
+        progress.report(0, source_frames.len() as u64)?;
+
+        let resize = resize.map(|size| Resize::new(size, resize_mode, resize_filter));
+        let mut frames: Vec<Vec<_>> = Vec::with_capacity(source_frames.len());
+        let (mut width, mut height) = (0, 0);
+
+        for source_img in source_frames {
+            let output = match process_loaded_image(
+                source_img,
+                n,
+                sample,
+                img_type,
+                resize,
+                threshold,
+                bradley,
+                bradley_threshold,
+                bradley_size,
+                min_spacing,
+                &progress.subtask(),
+            ) {
+                Ok(output) => output,
+                // a blank frame, such as a fade to black, holds the drones where they are
+                Err(Error::EmptyResult(_)) => {
+                    progress.advance()?;
+                    frames.push(Vec::new());
+                    continue
+                },
+                Err(e) => return Err(e),
+            };
+            progress.advance()?;
+            width = width.max(output.width());
+            height = height.max(output.height());
+            frames.push(output.borrow_coords());
+        }
+
+        // 'grid' sampling has no drone count, so it takes as many drones as its fullest frame
+        let drones = match sample {
+            SamplingType::Grid => frames.iter().map(Vec::len).max().unwrap_or(0),
+            _ => n as usize,
+        };
+        let frames = match_frames(&frames, drones).ok_or_else(|| Error::EmptyResult(
+            "No candidate pixels were found in any frame. Try a different 'threshold' or 'img_type'.".to_string()
+        ))?;
+
+        Ok(FrameSequenceOutput::new(frames, width, height))
+    })
+}
+
+#[pyfunction(signature=(input_path, n, up_axis=UpAxis::Y, progress=None))]
+/// Samples points evenly over the surface of a 3D mesh, for volumetric formations
 ///
 /// Arguments:
 ///     input_path: str 
-///         path to source image
+///         path to an OBJ, STL or PLY mesh
+///     n: u32
+///         number of points to select
+///     up_axis: str
+///         the axis pointing up in the mesh file, either 'y' or 'z'. Defaults to 'y'
+///     progress: callable
+///         called with the (done, total) counts of the sampling step while it runs, such as to update a progress bar. Raising an exception from it stops the run. Defaults to None
+///
+/// Returns:
+///     coordinates: Coordinate3DOutput
+///         the (x, y, z) coordinates of each sampled point, in the units of the mesh, with y up, as an (N, 3) float64 array from `coords_array()`
+pub fn process_mesh(
+    py: Python<'_>,
+    input_path: String,
+    n: u32,
+    up_axis: UpAxis,
+    progress: Option<Bound<'_, PyAny>>,
+) -> PyResult<Coordinate3DOutput> {
+    run_with_progress(py, progress.as_ref(), |progress| {
+        progress.stage(Stage::Loading)?;
+        let path = std::path::Path::new(&input_path);
+        check_readable(path)?;
+        let mesh = load_mesh(path, up_axis)
+            .map_err(|cause| Error::Decode { path: Some(path.to_path_buf()), cause })?;
+
+        progress.stage(Stage::Sampling)?;
+        Ok(Coordinate3DOutput::new(mesh_surface_sampling(&mesh, n, progress)?))
+    })
+}
+
+#[pyfunction(signature=(text, font_path, n, size=128.0, align=TextAlign::Center, line_spacing=1.0, letter_spacing=0.0, sample=SamplingType::Farthest, threshold=1.0, progress=None))]
+/// Renders a string with a font and processes it into a vector of (x, y) coordinates
+///
+/// The text is rendered in white on black, so the same thresholding and sampling steps
+/// as `process_image_to_coordinates` apply, with the glyph interiors ranked first.
+///
+/// Arguments:
+///     text: str 
+///         the text to render. Use '\n' to separate lines
+///     font_path: str
+///         path to a TTF or OTF font file
+///     n: u32
+///         number of pixels to select
+///     size: f32
+///         height of the font in pixels, at most 2048. Defaults to 128.0
+///     align: str
+///         alignment of the lines, either 'left', 'center' or 'right'. Defaults to 'center'
+///     line_spacing: f32
+///         multiplier on the font's natural line height, greater than 0. Defaults to 1.0
+///     letter_spacing: f32
+///         extra space between letters in pixels, which may be negative. Defaults to 0.0
+///     sample: str
+///         selecting type of sampling, either 'grid', 'farthest' or 'weighted'. Defaults to 'farthest'
+///     threshold: f64 
+///         fraction of the brightest glyph pixels that are kept as candidates. Defaults to 1.0
+///     progress: callable
+///         called with the (done, total) counts of the sampling step while it runs, such as to update a progress bar. Raising an exception from it stops the run. Defaults to None
+///
+/// Returns:
+///     coordinates: CoordinateOutput
+///         the coordinates of each sampled pixel, as an (N, 2) uint32 array from `coords_array()` or a list of (x, y) tuples from `coords()`, along with the `width()` and `height()` of the processed image and the `stats()` of the run
+#[allow(clippy::too_many_arguments)]
+pub fn process_text(
+    py: Python<'_>,
+    text: &str,
+    font_path: String,
+    n: u32,
+    size: f32,
+    align: TextAlign,
+    line_spacing: f32,
+    letter_spacing: f32,
+    sample: SamplingType,
+    threshold: f32,
+    progress: Option<Bound<'_, PyAny>>,
+) -> PyResult<CoordinateOutput> {
+    if !(size.is_finite() && size > 0.0 && size <= MAX_TEXT_SIZE) {
+        return Err(Error::invalid_parameter(format!("'size' must be greater than 0 and at most {}.", MAX_TEXT_SIZE)).into())
+    }
+    if !(line_spacing.is_finite() && line_spacing > 0.0) {
+        return Err(Error::invalid_parameter("'line_spacing' must be a finite number greater than 0.").into())
+    }
+    if !letter_spacing.is_finite() {
+        return Err(Error::invalid_parameter("'letter_spacing' must be finite.").into())
+    }
+    run_with_progress(py, progress.as_ref(), |progress| {
+        progress.stage(Stage::Loading)?;
+        let layout = TextLayout {
+            size,
+            align,
+            line_spacing,
+            letter_spacing,
+            ..TextLayout::default()
+        };
+
+        let path = std::path::Path::new(&font_path);
+        check_readable(path)?;
+        let img = render_text(text, path, &layout)
+            .map_err(|cause| Error::Decode { path: Some(path.to_path_buf()), cause })?;
+
+        progress.stage(Stage::Extracting)?;
+        let (initial_coords, weights) = image_to_weighted_coordinates(&img, threshold, ImgType::WhiteOnBlack);
+        if initial_coords.is_empty() {
+            return Err(Error::EmptyResult("The text rendered no pixels.".to_string()))
+        }
+
+        let sampled_coords = sample_candidates(&initial_coords, &weights, n, sample, 0.0, progress)?;
+
+        let size = (img.width(), img.height());
+        let stats = RunStats::preprocessed(size, size, initial_coords.len(), effective_threshold(&weights, ImgType::WhiteOnBlack))
+            .sampled(&initial_coords, &sampled_coords)
+            .record_timings(progress);
+        Ok(CoordinateOutput::new(sampled_coords, size.0, size.1).with_stats(stats))
+    })
+}
+
+#[pyfunction(signature=(input_path, n, resize=Some((256, 256)), background_color="black", output_path="output/coordinates.png", sample=SamplingType::Farthest, min_spacing=0.0, color_weight=1.0, palette=None, palette_method=PaletteMethod::KMeans, dither=false, alpha_threshold=128, chroma_key=None, chroma_tolerance=20.0, remove_border_background=false, border_tolerance=10.0, resize_mode=ResizeMode::Fit, resize_filter=ResizeFilter::Triangle, progress=None))]
+/// Processes a color image into a sample of coordinate pixels
+///
+/// Arguments:
+///     input_path: str | bytes | numpy.ndarray | PIL.Image
+///         path to source image, the bytes of an image file, or the image itself as a PIL image or a uint8 (or float in [0, 1]) array of shape (H, W), (H, W, 3) or (H, W, 4). SVG files are rasterized at the `resize` resolution
 ///     n: u32
 ///         number of pixels to select
 ///     resize: (width: u32, height: u32)
-///         maximum dimensions by which to resize the image. Will not be resized to exactly those dimensions, but instead to fit within them. Defaults to width = 256, height = 256. Set to None to prevent resizing
+///         dimensions by which to resize the image, following `resize_mode`. With the default 'fit' mode, the image is not resized to exactly those dimensions, but instead to fit within them. Defaults to width = 256, height = 256. Set to None to prevent resizing
 ///     background_color: str
 ///         color of the background pixels not sampled. Options are 'black' or 'white'. Defaults to 'black'
 ///     output_path: str
 ///         path where the output coordinates image will be saved. Note that, if the intermediate directories do not exist, they will be created. Defaults to 'output/coordinates.png'
+///     sample: str
+///         selecting type of sampling, either 'farthest', 'weighted' or 'color'. Defaults to 'farthest'. 'weighted' places more pixels in brighter regions of the image, while 'color' spreads the pixels over both position and color, so that small regions of distinct color are represented
+///     min_spacing: f64
+///         minimum distance between two selected pixels when using 'weighted' sampling. Defaults to 0.0
+///     color_weight: f64
+///         how many pixels of distance one unit of CIELAB color difference is worth when using 'color' sampling. Defaults to 1.0
+///     palette: int | [(int, int, int)]
+///         restricts the colors to a palette, either given as a list of (r, g, b) colors or as a number of colors computed from the sampled colors, which must be at least 1. Defaults to None, which keeps the original colors
+///     palette_method: str
+///         how a palette given as a number of colors is computed, either 'kmeans' or 'median_cut'. Defaults to 'kmeans'
+///     dither: bool
+///         diffuses the color error of each drone onto its neighbours, so that colors between two palette colors come out as a mix of both. Defaults to False
+///     alpha_threshold: u8
+///         pixels with an alpha below this value are treated as background. Defaults to 128. Set to 0 to keep every pixel
+///     chroma_key: (int, int, int)
+///         a background color to remove, such as the green of a green screen. Defaults to None
+///     chroma_tolerance: f32
+///         how far a color may be from `chroma_key` and still be removed, as a CIELAB color difference. Defaults to 20.0
+///     remove_border_background: bool
+///         removes the regions of uniform color connected to the edges of the image, such as a sky behind the subject. Defaults to False
+///     border_tolerance: f32
+///         how far a color may be from the edge color it was reached from and still be removed, as a CIELAB color difference. Defaults to 10.0
+///     resize_mode: str
+///         how the image is brought to the `resize` dimensions, either 'fit' (fit within them, only ever shrinking), 'upscale' (fit within them, enlarging smaller images), 'fill' (cover them and crop the overflow around the center) or 'exact' (stretch to them, ignoring aspect ratio). Defaults to 'fit'
+///     resize_filter: str
+///         resampling filter used when resizing, either 'nearest', 'triangle' or 'lanczos'. 'nearest' keeps hard edges when enlarging small logos. Defaults to 'triangle'
+///     progress: callable
+///         called with the (done, total) counts of the sampling step while it runs, such as to update a progress bar. Raising an exception from it stops the run. Defaults to None
+#[allow(clippy::too_many_arguments)]
 pub fn process_color_image(
-    input_path: String, 
+    py: Python<'_>,
+    input_path: ImageSource, 
     n: u32, 
     resize: Option<(u32, u32)>,
     background_color: &str,
     output_path: &str,
+    sample: SamplingType,
+    min_spacing: f64,
+    color_weight: f64,
+    palette: Option<Palette>,
+    palette_method: PaletteMethod,
+    dither: bool,
+    alpha_threshold: u8,
+    chroma_key: Option<(u8, u8, u8)>,
+    chroma_tolerance: f32,
+    remove_border_background: bool,
+    border_tolerance: f32,
+    resize_mode: ResizeMode,
+    resize_filter: ResizeFilter,
+    progress: Option<Bound<'_, PyAny>>,
 ) -> PyResult<()> {
+    run_with_progress(py, progress.as_ref(), |progress| {
+        let mask = MaskParams::new(alpha_threshold, chroma_key, chroma_tolerance, remove_border_background, border_tolerance);
 
-    let coords_output = process_color_image_to_coordinates(
-        input_path, 
-        n, 
-        resize, 
-    )?;
+        let coords_output = sample_color_image(
+            input_path, 
+            n, 
+            resize.map(|size| Resize::new(size, resize_mode, resize_filter)), 
+            sample,
+            min_spacing,
+            color_weight,
+            &mask,
+            progress,
+        )?;
+        let (width, height) = (coords_output.width(), coords_output.height());
+        let mut color_coords = coords_output.coords();
+        if let Some(palette) = &palette {
+            color_coords = quantize_color_coordinates(&color_coords, palette, palette_method, dither);
+        }
 
-    let background_color = match background_color {
-        "black" => BackgroundColor::Black,
-        "white" => BackgroundColor::White,
-        _ => BackgroundColor::Black,
-    };
+        let background_color = match background_color {
+            "black" => BackgroundColor::Black,
+            "white" => BackgroundColor::White,
+            _ => BackgroundColor::Black,
+        };
 
-    // 4. Turn the sampled coordinates back into an image
-    let output_img = coordinates_to_color_image(
-        coords_output.width(),
-        coords_output.height(),
-        &coords_output.coords(),
-        background_color,
-    );
-
-    // creating intermediate directories if necessary
-    let path = std::path::Path::new(output_path);
-    if let Some(prefix) = path.parent() {
-        std::fs::create_dir_all(prefix).unwrap();
-    }
+        // 4. Turn the sampled coordinates back into an image
+        let output_img = coordinates_to_color_image(
+            width,
+            height,
+            &color_coords,
+            background_color,
+        );
 
-    match output_img.save(output_path) {
-        Ok(_) => Ok(()),
-        Err(e) => Err(PyValueError::new_err(format!("Unable to create file in path 'output/img.png': {}", e)))
-    }
+        save_image(std::path::Path::new(output_path), &output_img.into())
+    })
 }
 
+#[pyfunction(signature=(input_path, n, resize=Some((256, 256)), sample=SamplingType::Farthest, min_spacing=0.0, color_weight=1.0, palette=None, palette_method=PaletteMethod::KMeans, dither=false, alpha_threshold=128, chroma_key=None, chroma_tolerance=20.0, remove_border_background=false, border_tolerance=10.0, resize_mode=ResizeMode::Fit, resize_filter=ResizeFilter::Triangle, progress=None))]
+/// Processes a color image into a vector of (x, y) coordinates, along with the color of each
+///
+/// Takes the same arguments as `process_color_image`, without the output image settings.
+///
+/// Returns:
+///     coordinates: ColorCoordinateOutput
+///         the coordinates and colors of each sampled pixel, as an (N, 2) uint32 array of (x, y) rows from `coords_array()` and an (N, 4) uint8 array of (r, g, b, a) rows from `colors_array()`, along with the `width()` and `height()` of the processed image and the `stats()` of the run
+#[allow(clippy::too_many_arguments)]
 pub fn process_color_image_to_coordinates(
-    input_path: String, 
-    n: u32, 
+    py: Python<'_>,
+    input_path: ImageSource,
+    n: u32,
     resize: Option<(u32, u32)>,
+    sample: SamplingType,
+    min_spacing: f64,
+    color_weight: f64,
+    palette: Option<Palette>,
+    palette_method: PaletteMethod,
+    dither: bool,
+    alpha_threshold: u8,
+    chroma_key: Option<(u8, u8, u8)>,
+    chroma_tolerance: f32,
+    remove_border_background: bool,
+    border_tolerance: f32,
+    resize_mode: ResizeMode,
+    resize_filter: ResizeFilter,
+    progress: Option<Bound<'_, PyAny>>,
 ) -> PyResult<ColorCoordinateOutput> {
-    let source_img = match image::open(input_path) {
-        Ok(img) => img,
-        Err(e) => {
-            return Err(PyValueError::new_err(format!("Error loading image: {:?}", e)))
+    run_with_progress(py, progress.as_ref(), |progress| {
+        let mask = MaskParams::new(alpha_threshold, chroma_key, chroma_tolerance, remove_border_background, border_tolerance);
+        let resize = resize.map(|size| Resize::new(size, resize_mode, resize_filter));
+        let coords_output = sample_color_image(input_path, n, resize, sample, min_spacing, color_weight, &mask, progress)?;
+
+        match &palette {
+            Some(palette) => {
+                let (width, height) = (coords_output.width(), coords_output.height());
+                // the quantization only changes the colors, so the stats of the sampling still hold
+                let stats = coords_output.stats();
+                let color_coords = quantize_color_coordinates(&coords_output.coords(), palette, palette_method, dither);
+                let output = ColorCoordinateOutput::new(color_coords, width, height);
+                Ok(match stats {
+                    Some(stats) => output.with_stats(stats),
+                    None => output,
+                })
+            }
+            None => Ok(coords_output),
         }
-    };
+    })
+}
 
-    let img = if let Some((width, height)) = resize {
-        source_img.thumbnail(width, height)
+/// Rejects color weights that don't give a meaningful joint position and color distance.
+fn check_color_weight(color_weight: f64) -> Result<(), Error> {
+    if !(color_weight.is_finite() && color_weight >= 0.0) {
+        return Err(Error::invalid_parameter("'color_weight' must be a finite number of at least 0."))
+    }
+    Ok(())
+}
+
+/// Runs the loading, background removal and sampling steps of the color pipeline
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn sample_color_image(
+    input_path: ImageSource, 
+    n: u32, 
+    resize: Option<Resize>,
+    sample: SamplingType,
+    min_spacing: f64,
+    color_weight: f64,
+    mask: &MaskParams,
+    progress: &Progress,
+) -> Result<ColorCoordinateOutput, Error> {
+    check_color_weight(color_weight)?;
+    progress.stage(Stage::Loading)?;
+    let source_img = input_path.load(resize.map(|r| r.size()))?;
+
+    progress.stage(Stage::Preprocessing)?;
+    let source_size = (source_img.width(), source_img.height());
+    let img = if let Some(resize) = &resize {
+        resize_image(source_img, resize)
     } else { source_img };
 
     let width = img.width();
     let height = img.height();
+    debug!("Preprocessed the image to {}x{}", width, height);
 
+    progress.stage(Stage::Extracting)?;
     let initial_coords = color_image_to_coordinates(&img);
 
+    // only the foreground pixels are eligible for sampling
+    let initial_coords = if mask.is_active(&img) {
+        filter_color_coordinates(initial_coords, &foreground_mask(&img, mask))
+    } else { initial_coords };
+    if initial_coords.is_empty() {
+        return Err(Error::EmptyResult("No visible pixels were left in the image after removing its background.".to_string()))
+    }
+    debug!("Extracted {} candidate pixels", initial_coords.len());
+
     // sample colors
-    let sampled_coords = color_albedo_sampling(&initial_coords, n);
+    progress.stage(Stage::Sampling)?;
+    let sampled_coords = match sample {
+        SamplingType::Farthest => color_albedo_sampling_with_progress(&initial_coords, n, progress)?,
+        SamplingType::Weighted => color_weighted_sampling_with_progress(&initial_coords, n, min_spacing, progress)?,
+        SamplingType::Color => color_farthest_point_sampling_with_progress(&initial_coords, n, color_weight, progress)?,
+        SamplingType::Grid | SamplingType::Path => {
+            return Err(Error::invalid_parameter("The valid values for `sampling` with color images are 'farthest', 'weighted' and 'color'."))
+        }
+    };
+    info!("Sampled {} of {} candidate pixels", sampled_coords.len(), initial_coords.len());
+
+    let candidate_positions: Vec<Coordinate> = initial_coords.iter().map(|c| c.coord()).collect();
+    let sampled_positions: Vec<Coordinate> = sampled_coords.iter().map(|c| c.coord()).collect();
+    let stats = RunStats::preprocessed(source_size, (width, height), initial_coords.len(), None)
+        .sampled(&candidate_positions, &sampled_positions)
+        .record_timings(progress);
 
     Ok(ColorCoordinateOutput::new(
         sampled_coords,
         width,
         height,
-    ))
+    ).with_stats(stats))
 }
 
+#[pyfunction(signature=(input_path, n, output_path, max_dim=20.0, resize=Some((256, 256)), sample=SamplingType::Farthest, min_spacing=0.0, color_weight=1.0, palette=None, palette_method=PaletteMethod::KMeans, dither=false, led_gamma=1.0, led_gain=(1.0, 1.0, 1.0), alpha_threshold=128, chroma_key=None, chroma_tolerance=20.0, remove_border_background=false, border_tolerance=10.0, resize_mode=ResizeMode::Fit, resize_filter=ResizeFilter::Triangle, progress=None))]
+/// Processes a color image into a drone formation and exports it for use outside of Python
+///
+/// The file format is chosen from the extension of `output_path`:
+///     '.csv': a static formation for Skybrush Studio
+///     '.glb': a binary glTF with one emissive node per drone, for previsualization in Blender
+///     '.ply': a colored point cloud
+///
+/// Arguments:
+///     input_path: str | bytes | numpy.ndarray | PIL.Image
+///         path to source image, the bytes of an image file, or the image itself as a PIL image or a uint8 (or float in [0, 1]) array of shape (H, W), (H, W, 3) or (H, W, 4).
+///     n: u32
+///         number of drones in the formation
+///     output_path: str
+///         path where the formation will be saved. Note that, if the intermediate directories do not exist, they will be created
+///     max_dim: float
+///         size of the largest dimension of the formation, in meters. Defaults to 20.0
+///     resize: (width: u32, height: u32)
+///         dimensions by which to resize the image, following `resize_mode`. With the default 'fit' mode, the image is not resized to exactly those dimensions, but instead to fit within them. Defaults to width = 256, height = 256. Set to None to prevent resizing
+///     sample: str
+///         selecting type of sampling, either 'farthest', 'weighted' or 'color'. Defaults to 'farthest'. 'weighted' places more drones in brighter regions of the image, while 'color' spreads the drones over both position and color, so that small regions of distinct color are represented
+///     min_spacing: f64
+///         minimum distance between two selected pixels when using 'weighted' sampling. Defaults to 0.0
+///     color_weight: f64
+///         how many pixels of distance one unit of CIELAB color difference is worth when using 'color' sampling. Defaults to 1.0
+///     palette: int | [(int, int, int)]
+///         restricts the colors to a palette, either given as a list of (r, g, b) colors or as a number of colors computed from the sampled colors, which must be at least 1. Defaults to None, which keeps the original colors
+///     palette_method: str
+///         how a palette given as a number of colors is computed, either 'kmeans' or 'median_cut'. Defaults to 'kmeans'
+///     dither: bool
+///         diffuses the color error of each drone onto its neighbours, so that colors between two palette colors come out as a mix of both. Defaults to False
+///     led_gamma: f32
+///         exponent of the LED calibration curve, applied to each normalized channel after the palette. Must be finite and above 0. Defaults to 1.0
+///     led_gain: (r: f32, g: f32, b: f32)
+///         multiplier applied to each channel after the gamma curve, to balance the LEDs' channels. Each gain must be finite and at least 0. Defaults to (1.0, 1.0, 1.0)
+///     alpha_threshold: u8
+///         pixels with an alpha below this value are treated as background. Defaults to 128. Set to 0 to keep every pixel
+///     chroma_key: (int, int, int)
+///         a background color to remove, such as the green of a green screen. Defaults to None
+///     chroma_tolerance: f32
+///         how far a color may be from `chroma_key` and still be removed, as a CIELAB color difference. Defaults to 20.0
+///     remove_border_background: bool
+///         removes the regions of uniform color connected to the edges of the image, such as a sky behind the subject. Defaults to False
+///     border_tolerance: f32
+///         how far a color may be from the edge color it was reached from and still be removed, as a CIELAB color difference. Defaults to 10.0
+///     resize_mode: str
+///         how the image is brought to the `resize` dimensions, either 'fit' (fit within them, only ever shrinking), 'upscale' (fit within them, enlarging smaller images), 'fill' (cover them and crop the overflow around the center) or 'exact' (stretch to them, ignoring aspect ratio). Defaults to 'fit'
+///     resize_filter: str
+///         resampling filter used when resizing, either 'nearest', 'triangle' or 'lanczos'. 'nearest' keeps hard edges when enlarging small logos. Defaults to 'triangle'
+///     progress: callable
+///         called with the (done, total) counts of the sampling step while it runs, such as to update a progress bar. Raising an exception from it stops the run. Defaults to None
+#[allow(clippy::too_many_arguments)]
+pub fn export_color_formation(
+    py: Python<'_>,
+    input_path: ImageSource,
+    n: u32,
+    output_path: &str,
+    max_dim: f64,
+    resize: Option<(u32, u32)>,
+    sample: SamplingType,
+    min_spacing: f64,
+    color_weight: f64,
+    palette: Option<Palette>,
+    palette_method: PaletteMethod,
+    dither: bool,
+    led_gamma: f32,
+    led_gain: (f32, f32, f32),
+    alpha_threshold: u8,
+    chroma_key: Option<(u8, u8, u8)>,
+    chroma_tolerance: f32,
+    remove_border_background: bool,
+    border_tolerance: f32,
+    resize_mode: ResizeMode,
+    resize_filter: ResizeFilter,
+    progress: Option<Bound<'_, PyAny>>,
+) -> PyResult<()> {
+    let calibration = LedCalibration {
+        gamma: led_gamma,
+        gain: [led_gain.0, led_gain.1, led_gain.2],
+    };
+    if !(calibration.gamma.is_finite() && calibration.gamma > 0.0) {
+        return Err(Error::invalid_parameter("'led_gamma' must be a finite number above 0.").into());
+    }
+    if !calibration.gain.iter().all(|gain| gain.is_finite() && *gain >= 0.0) {
+        return Err(Error::invalid_parameter("Each 'led_gain' must be a finite number of at least 0.").into());
+    }
+
+    run_with_progress(py, progress.as_ref(), |progress| {
+        let mask = MaskParams::new(alpha_threshold, chroma_key, chroma_tolerance, remove_border_background, border_tolerance);
+        let resize = resize.map(|size| Resize::new(size, resize_mode, resize_filter));
+        let coords_output = sample_color_image(input_path, n, resize, sample, min_spacing, color_weight, &mask, progress)?;
+        let mut color_coords = coords_output.coords();
+        if let Some(palette) = &palette {
+            color_coords = quantize_color_coordinates(&color_coords, palette, palette_method, dither);
+        }
 
-#[pyfunction(signature=(input_path, size, bradley_threshold=15, output_path="output/bradley.png"))]
-fn test_bradley(
-    input_path: String, 
-    size: u32,
+        // the LED curve is applied last, so that it changes what the drones display
+        // rather than which palette color each drone is given
+        let coords: Vec<_> = color_coords.iter().map(|c| c.coord()).collect();
+        let colors: Vec<[u8; 3]> = color_coords.iter().map(|c| {
+            let [r, g, b, _] = c.color().0;
+            calibration.apply([r, g, b])
+        }).collect();
+
+        let path = std::path::Path::new(output_path);
+
+        let normalized = normalize_coordinates(&coords, max_dim).map_err(|cause| Error::export(path, cause))?;
+        let file_data = encode_by_extension(path, normalized, &colors).map_err(|cause| Error::export(path, cause))?;
+
+        write_output(path, &file_data)
+    })
+}
+
+#[pyfunction(signature=(input_path, n, output_path, depth_path=None, depth_range=(0.0, 4.0), invert_depth=false, max_dim=20.0, sample=SamplingType::Farthest, img_type=ImgType::BlackOnWhite, resize=Some((256, 256)), threshold=0.01, resize_mode=ResizeMode::Fit, resize_filter=ResizeFilter::Triangle, bradley=false, bradley_threshold=15, bradley_size=16, min_spacing=0.0, progress=None))]
+/// Processes an image into a relief formation, where a depth image sets how far forward or back each drone sits
+///
+/// The drones are sampled exactly like in `process_image_to_coordinates`. The luminance of the depth image at
+/// each drone's position is then scaled into `depth_range`, which becomes the depth axis of the export (the x
+/// axis of the Skybrush CSV).
+///
+/// The file format is chosen from the extension of `output_path`: '.csv' for Skybrush Studio, '.glb' for a
+/// binary glTF, or '.ply' for a point cloud
+///
+/// Arguments:
+///     input_path: str | bytes | numpy.ndarray | PIL.Image
+///         path to source image, the bytes of an image file, or the image itself as a PIL image or a uint8 (or float in [0, 1]) array of shape (H, W), (H, W, 3) or (H, W, 4).
+///     n: u32
+///         number of drones in the formation
+///     output_path: str
+///         path where the formation will be saved. Note that, if the intermediate directories do not exist, they will be created
+///     depth_path: str | bytes | numpy.ndarray | PIL.Image
+///         grayscale depth image, in any of the forms accepted by `input_path`, with the same aspect ratio as the source image. Defaults to None, which uses the luminance of the source image itself
+///     depth_range: (min: float, max: float)
+///         depths in meters given to black and white pixels of the depth image. Defaults to (0.0, 4.0)
+///     invert_depth: bool
+///         swaps the depth range, so that white pixels get the minimum depth. Defaults to False
+///     max_dim: float
+///         size of the largest dimension of the formation's plane, in meters. Defaults to 20.0
+///     sample: str
+///         selecting type of sampling, either 'grid', 'farthest', 'weighted' or 'path'. Defaults to 'farthest'. 'path' is only valid for SVG files with the 'fit' resize mode, since it samples the SVG's paths rather than the resized image the depths are read from
+///     img_type: str 
+///         selecting type of image, either 'black_on_white' or 'white_on_black'. Defaults to 'black_on_white'
+///     resize: (width: u32, height: u32)
+///         maximum dimensions by which to resize the image. Defaults to width = 256, height = 256. Set to None to prevent resizing
+///     threshold: f64 
+///         brightness threshold that gets counted as a 'white' pixel. Defaults to 0.01
+///     resize_mode: str
+///         how the image is brought to the `resize` dimensions, either 'fit' (fit within them, only ever shrinking), 'upscale' (fit within them, enlarging smaller images), 'fill' (cover them and crop the overflow around the center) or 'exact' (stretch to them, ignoring aspect ratio). Defaults to 'fit'
+///     resize_filter: str
+///         resampling filter used when resizing, either 'nearest', 'triangle' or 'lanczos'. 'nearest' keeps hard edges when enlarging small logos. Defaults to 'triangle'
+///     bradley: bool
+///         also applies Bradley adaptive thresholding, which keeps lines under uneven lighting. Defaults to False
+///     bradley_threshold: u8
+///         brightness threshold of the Bradley thresholding. Defaults to 15
+///     bradley_size: u32
+///         size of the window of the Bradley thresholding, in pixels. Defaults to 16
+///     min_spacing: f64
+///         minimum distance between two selected pixels when using 'weighted' sampling. Defaults to 0.0
+///     progress: callable
+///         called with the (done, total) counts of the sampling step while it runs, such as to update a progress bar. Raising an exception from it stops the run. Defaults to None
+#[allow(clippy::too_many_arguments)]
+pub fn export_depth_formation(
+    py: Python<'_>,
+    input_path: ImageSource,
+    n: u32,
+    output_path: &str,
+    depth_path: Option<ImageSource>,
+    depth_range: (f64, f64),
+    invert_depth: bool,
+    max_dim: f64,
+    sample: SamplingType,
+    img_type: ImgType,
+    resize: Option<(u32, u32)>,
+    threshold: f32,
+    resize_mode: ResizeMode,
+    resize_filter: ResizeFilter,
+    bradley: bool,
     bradley_threshold: u8,
+    bradley_size: u32,
+    min_spacing: f64,
+    progress: Option<Bound<'_, PyAny>>,
+) -> PyResult<()> {
+    run_with_progress(py, progress.as_ref(), |progress| {
+        if !depth_range.0.is_finite() || !depth_range.1.is_finite() {
+            return Err(Error::invalid_parameter("'depth_range' must be finite."));
+        }
+        // the path sampler works on the SVG's own geometry, which only matches the resized depth image when fitting
+        if sample == SamplingType::Path && resize_mode != ResizeMode::Fit {
+            return Err(Error::invalid_parameter("'path' sampling only supports the 'fit' resize mode."));
+        }
+        let depth_img = depth_path.as_ref().unwrap_or(&input_path).load(resize)?;
+        // without a depth map, the source image was just loaded for its depth, so it is sampled
+        // from memory rather than decoded again. Path sampling needs the SVG itself
+        let input_path = match depth_path {
+            None if sample != SamplingType::Path => ImageSource::Image(depth_img.clone()),
+            _ => input_path,
+        };
+        // the depth image goes through the same resize as the source, so that a 'fill' crop lines up
+        let depth_img = match resize {
+            Some(size) => resize_image(depth_img, &Resize::new(size, resize_mode, resize_filter)),
+            None => depth_img,
+        };
+
+        let coords_output = sample_image(
+            input_path,
+            n,
+            sample,
+            img_type,
+            resize,
+            threshold,
+            bradley,
+            bradley_threshold,
+            bradley_size,
+            min_spacing,
+            resize_mode,
+            resize_filter,
+            &MaskParams::default(),
+            progress,
+        )?;
+        let (width, height) = (coords_output.width(), coords_output.height());
+        let coords = coords_output.borrow_coords();
+
+        let params = DepthParams {
+            min_depth: depth_range.0,
+            max_depth: depth_range.1,
+            invert: invert_depth,
+        };
+        let depths = sample_depths(&depth_img, &coords, width, height, &params);
+
+        let path = std::path::Path::new(output_path);
+
+        let normalized = normalize_coordinates(&coords, max_dim).map_err(|cause| Error::export(path, cause))?;
+        let normalized = apply_depths(normalized, &depths);
+        let file_data = encode_by_extension(path, normalized, &[]).map_err(|cause| Error::export(path, cause))?;
+
+        write_output(path, &file_data)
+    })
+}
+
+#[pyfunction(signature=(input_path, n, output_path, layers=3, layer_spacing=4.0, layer_offset=(0.0, 0.0), mode=LayerMode::Replicate, max_dim=20.0, img_type=ImgType::BlackOnWhite, resize=Some((256, 256)), threshold=0.01, resize_mode=ResizeMode::Fit, resize_filter=ResizeFilter::Triangle, bradley=false, bradley_threshold=15, bradley_size=16, progress=None))]
+/// Processes an image into several parallel layers of drones, exported as one 3D formation
+///
+/// Stacking layers behind each other increases the perceived density of the formation. With the
+/// 'interleave' mode, each layer is resampled so that its drones fill the gaps left by the layers
+/// in front of it, rather than hiding behind them.
+///
+/// The file format is chosen from the extension of `output_path`: '.csv' for Skybrush Studio, '.glb' for a
+/// binary glTF, or '.ply' for a point cloud
+///
+/// Arguments:
+///     input_path: str | bytes | numpy.ndarray | PIL.Image
+///         path to source image, the bytes of an image file, or the image itself as a PIL image or a uint8 (or float in [0, 1]) array of shape (H, W), (H, W, 3) or (H, W, 4).
+///     n: u32
+///         number of drones in each layer
+///     output_path: str
+///         path where the formation will be saved. Note that, if the intermediate directories do not exist, they will be created
+///     layers: u32
+///         number of layers. Defaults to 3
+///     layer_spacing: float
+///         distance between consecutive layers along the depth axis, in meters. Defaults to 4.0
+///     layer_offset: (x: float, y: float)
+///         shift of each layer relative to the one in front of it, in meters. Defaults to (0.0, 0.0)
+///     mode: str
+///         how the layers are filled, either 'replicate' (the same points in every layer) or 'interleave' (each layer is resampled to fill the gaps of the layers in front). Defaults to 'replicate'
+///     max_dim: float
+///         size of the largest dimension of each layer, in meters. Defaults to 20.0
+///     img_type: str 
+///         selecting type of image, either 'black_on_white' or 'white_on_black'. Defaults to 'black_on_white'
+///     resize: (width: u32, height: u32)
+///         maximum dimensions by which to resize the image. Defaults to width = 256, height = 256. Set to None to prevent resizing
+///     threshold: f64 
+///         brightness threshold that gets counted as a 'white' pixel. Defaults to 0.01
+///     resize_mode: str
+///         how the image is brought to the `resize` dimensions, either 'fit' (fit within them, only ever shrinking), 'upscale' (fit within them, enlarging smaller images), 'fill' (cover them and crop the overflow around the center) or 'exact' (stretch to them, ignoring aspect ratio). Defaults to 'fit'
+///     resize_filter: str
+///         resampling filter used when resizing, either 'nearest', 'triangle' or 'lanczos'. 'nearest' keeps hard edges when enlarging small logos. Defaults to 'triangle'
+///     bradley: bool
+///         also applies Bradley adaptive thresholding, which keeps lines under uneven lighting. Defaults to False
+///     bradley_threshold: u8
+///         brightness threshold of the Bradley thresholding. Defaults to 15
+///     bradley_size: u32
+///         size of the window of the Bradley thresholding, in pixels. Defaults to 16
+///     progress: callable
+///         called with the (done, total) counts of the sampling of each layer while it runs, such as to update a progress bar. Raising an exception from it stops the run. Defaults to None
+#[allow(clippy::too_many_arguments)]
+pub fn export_layered_formation(
+    py: Python<'_>,
+    input_path: ImageSource,
+    n: u32,
     output_path: &str,
+    layers: u32,
+    layer_spacing: f64,
+    layer_offset: (f64, f64),
+    mode: LayerMode,
+    max_dim: f64,
+    img_type: ImgType,
+    resize: Option<(u32, u32)>,
+    threshold: f32,
+    resize_mode: ResizeMode,
+    resize_filter: ResizeFilter,
+    bradley: bool,
+    bradley_threshold: u8,
+    bradley_size: u32,
+    progress: Option<Bound<'_, PyAny>>,
 ) -> PyResult<()> {
-    let source_img = match image::open(input_path) {
-        Ok(img) => img,
-        Err(e) => {
-            return Err(PyValueError::new_err(format!("Error loading image: {:?}", e)))
+    run_with_progress(py, progress.as_ref(), |progress| {
+        if layers == 0 {
+            return Err(Error::invalid_parameter("'layers' must be at least 1."))
         }
+
+        let source_img = input_path.load(resize)?;
+        let resize = resize.map(|size| Resize::new(size, resize_mode, resize_filter));
+        let (candidates, _weights) = extract_candidates(
+            source_img,
+            img_type,
+            resize,
+            threshold,
+            bradley,
+            bradley_threshold,
+            bradley_size,
+            progress,
+        )?;
+        let candidates = candidates.borrow_coords();
+
+        let params = LayerParams {
+            count: layers,
+            spacing: layer_spacing,
+            offset: layer_offset,
+            mode,
+        };
+        progress.stage(Stage::Sampling)?;
+        let layer_coords = sample_layers_with_progress(&candidates, n, &params, progress)?;
+
+        let path = std::path::Path::new(output_path);
+        let formation = stack_layers(&layer_coords, max_dim, &params).map_err(|cause| Error::export(path, cause))?;
+        let file_data = encode_by_extension(path, formation, &[]).map_err(|cause| Error::export(path, cause))?;
+
+        write_output(path, &file_data)
+    })
+}
+
+// --- Individual pipeline stages, for composing custom pipelines from Python ---
+
+/// Loads an image given to one of the stage functions, without resizing it
+fn load_stage_image(image: &ImageSource) -> Result<DynamicImage, Error> {
+    image.load(None)
+}
+
+/// Pairs coordinates with their colors, computing the brightness of each like
+/// `color_image_to_coordinates` does
+fn zip_colors(coords: Vec<Coordinate>, colors: Vec<[u8; 4]>) -> Result<Vec<ColorCoordinate>, Error> {
+    if coords.len() != colors.len() {
+        return Err(Error::invalid_parameter(format!(
+            "There are {} coordinates but {} colors.", coords.len(), colors.len()
+        )))
+    }
+    Ok(coords.into_iter().zip(colors).map(|(coord, [r, g, b, a])| {
+        let brightness = (0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32) * (a as f32 / 255.0);
+        ColorCoordinate::new(coord.x(), coord.y(), image::Rgba([r, g, b, a]), brightness)
+    }).collect())
+}
+
+#[pyfunction(name="bradley_adaptive_threshold", signature=(image, size=16, threshold=15))]
+/// Applies Bradley adaptive thresholding to an image
+///
+/// Arguments:
+///     image: str | bytes | numpy.ndarray | PIL.Image
+///         the image to threshold, in any form accepted by `process_image`
+///     size: u32
+///         size in pixels of the window each pixel is compared to. Defaults to 16
+///     threshold: u8
+///         percentage below the window's mean brightness under which a pixel turns black. Defaults to 15
+///
+/// Returns:
+///     image: numpy.ndarray
+///         the thresholded image, as an (H, W) uint8 array of 0 and 255
+fn py_bradley_adaptive_threshold<'py>(
+    py: Python<'py>,
+    image: ImageSource,
+    size: u32,
+    threshold: u8,
+) -> PyResult<Bound<'py, PyAny>> {
+    let output_img = py.allow_threads(|| {
+        let img = load_stage_image(&image)?;
+        PyResult::Ok(bradley_adaptive_threshold(&img.to_luma8(), size, threshold))
+    })?;
+    image_array(py, &DynamicImage::ImageLuma8(output_img))
+}
+
+#[pyfunction(name="image_to_coordinates", signature=(image, threshold=0.01, img_type=ImgType::BlackOnWhite))]
+/// Extracts the candidate pixels of a black and white image
+///
+/// Arguments:
+///     image: str | bytes | numpy.ndarray | PIL.Image
+///         the image to extract pixels from, in any form accepted by `process_image`
+///     threshold: f32
+///         fraction of the most intense pixels to keep. Defaults to 0.01
+///     img_type: str
+///         selecting type of image, either 'black_on_white' or 'white_on_black'. Defaults to 'black_on_white'
+///
+/// Returns:
+///     coordinates: numpy.ndarray
+///         the (x, y) coordinates of the candidate pixels, as an (N, 2) uint32 array
+fn py_image_to_coordinates<'py>(
+    py: Python<'py>,
+    image: ImageSource,
+    threshold: f32,
+    img_type: ImgType,
+) -> PyResult<Bound<'py, PyAny>> {
+    let coords = py.allow_threads(|| {
+        let img = load_stage_image(&image)?;
+        PyResult::Ok(image_to_coordinates(&img, threshold, img_type))
+    })?;
+    coordinates_array(py, &coords)
+}
+
+#[pyfunction(name="farthest_point_sampling", signature=(coordinates, n, progress=None))]
+/// Selects `n` coordinates that are spread as far from each other as possible
+///
+/// Arguments:
+///     coordinates: numpy.ndarray | list[(u32, u32)] | CoordinateOutput
+///         the candidate (x, y) coordinates, such as an (N, 2) array
+///     n: u32
+///         number of coordinates to select
+///     progress: callable
+///         called with the (done, total) counts of the sampling while it runs, such as to update a progress bar. Raising an exception from it stops the run. Defaults to None
+///
+/// Returns:
+///     coordinates: numpy.ndarray
+///         the selected coordinates, as an (n, 2) uint32 array
+fn py_farthest_point_sampling<'py>(
+    py: Python<'py>,
+    coordinates: CoordinateInput,
+    n: u32,
+    progress: Option<Bound<'py, PyAny>>,
+) -> PyResult<Bound<'py, PyAny>> {
+    let sampled_coords = run_with_progress(py, progress.as_ref(), |progress| {
+        Ok(farthest_point_sampling_with_progress(&coordinates.0, n, progress)?)
+    })?;
+    coordinates_array(py, &sampled_coords)
+}
+
+#[pyfunction(name="grid_sampling", signature=(coordinates, cell_size))]
+/// Keeps one coordinate in each cell of a square grid
+///
+/// Arguments:
+///     coordinates: numpy.ndarray | list[(u32, u32)] | CoordinateOutput
+///         the candidate (x, y) coordinates, such as an (N, 2) array
+///     cell_size: u32
+///         size in pixels of the grid cells
+///
+/// Returns:
+///     coordinates: numpy.ndarray
+///         the selected coordinates, as an (N, 2) uint32 array
+fn py_grid_sampling<'py>(
+    py: Python<'py>,
+    coordinates: CoordinateInput,
+    cell_size: u32,
+) -> PyResult<Bound<'py, PyAny>> {
+    if cell_size == 0 {
+        return Err(Error::invalid_parameter("'cell_size' must be at least 1.").into())
+    }
+    let sampled_coords = py.allow_threads(|| grid_sampling(&coordinates.0, cell_size));
+    coordinates_array(py, &sampled_coords)
+}
+
+#[pyfunction(name="color_image_to_coordinates", signature=(image))]
+/// Extracts the candidate pixels of a color image, which are all its pixels with some brightness
+///
+/// Arguments:
+///     image: str | bytes | numpy.ndarray | PIL.Image
+///         the image to extract pixels from, in any form accepted by `process_image`
+///
+/// Returns:
+///     coordinates: numpy.ndarray
+///         the (x, y) coordinates of the candidate pixels, as an (N, 2) uint32 array
+///     colors: numpy.ndarray
+///         the (r, g, b, a) color of each candidate pixel, as an (N, 4) uint8 array
+fn py_color_image_to_coordinates<'py>(
+    py: Python<'py>,
+    image: ImageSource,
+) -> PyResult<(Bound<'py, PyAny>, Bound<'py, PyAny>)> {
+    let color_coords = py.allow_threads(|| {
+        let img = load_stage_image(&image)?;
+        PyResult::Ok(color_image_to_coordinates(&img))
+    })?;
+    let coords: Vec<Coordinate> = color_coords.iter().map(|c| c.coord()).collect();
+    Ok((coordinates_array(py, &coords)?, colors_array(py, &color_coords)?))
+}
+
+#[pyfunction(name="color_albedo_sampling", signature=(coordinates, colors, n, color_weight=0.0, progress=None))]
+/// Selects `n` colored coordinates spread out over the image, like the 'farthest' sampling of `process_color_image`
+///
+/// With a positive `color_weight`, this is the 'color' sampling of `process_color_image` instead, which defaults to a `color_weight` of 1.0
+///
+/// Arguments:
+///     coordinates: numpy.ndarray | list[(u32, u32)] | CoordinateOutput
+///         the candidate (x, y) coordinates, such as an (N, 2) array
+///     colors: numpy.ndarray | list[(u8, u8, u8, u8)]
+///         the color of each candidate, as an (N, 3) or (N, 4) array of uint8 (or float in [0, 1])
+///     n: u32
+///         number of coordinates to select
+///     color_weight: f64
+///         how many pixels of distance one unit of CIELAB color difference is worth, so that small regions of distinct color are represented. Defaults to 0.0, which only spreads the coordinates over the image
+///     progress: callable
+///         called with the (done, total) counts of the sampling while it runs, such as to update a progress bar. Raising an exception from it stops the run. Defaults to None
+///
+/// Returns:
+///     coordinates: numpy.ndarray
+///         the selected coordinates, as an (n, 2) uint32 array
+///     colors: numpy.ndarray
+///         the color of each selected coordinate, as an (n, 4) uint8 array
+fn py_color_albedo_sampling<'py>(
+    py: Python<'py>,
+    coordinates: CoordinateInput,
+    colors: ColorInput,
+    n: u32,
+    color_weight: f64,
+    progress: Option<Bound<'py, PyAny>>,
+) -> PyResult<(Bound<'py, PyAny>, Bound<'py, PyAny>)> {
+    check_color_weight(color_weight)?;
+    let color_coords = zip_colors(coordinates.0, colors.0)?;
+    let color_coords = run_with_progress(py, progress.as_ref(), |progress| {
+        Ok(color_farthest_point_sampling_with_progress(&color_coords, n, color_weight, progress)?)
+    })?;
+    let coords: Vec<Coordinate> = color_coords.iter().map(|c| c.coord()).collect();
+    Ok((coordinates_array(py, &coords)?, colors_array(py, &color_coords)?))
+}
+
+#[pyfunction(name="coordinates_to_image", signature=(coordinates, width, height, colors=None, background_color="black"))]
+/// Draws coordinates as single pixels on a blank image
+///
+/// Arguments:
+///     coordinates: numpy.ndarray | list[(u32, u32)] | CoordinateOutput
+///         the (x, y) coordinates to draw, such as an (N, 2) array. Coordinates outside of the image are skipped
+///     width: u32
+///         width of the image
+///     height: u32
+///         height of the image
+///     colors: numpy.ndarray | list[(u8, u8, u8, u8)] | None
+///         the color of each coordinate, as an (N, 3) or (N, 4) array of uint8 (or float in [0, 1]). Defaults to None, which draws white pixels on black
+///     background_color: str
+///         background of a colored image, either 'black' or 'white'. Defaults to 'black'
+///
+/// Returns:
+///     image: numpy.ndarray
+///         the drawn image, as an (H, W) uint8 array without `colors`, or an (H, W, 4) uint8 array with them
+fn py_coordinates_to_image<'py>(
+    py: Python<'py>,
+    coordinates: CoordinateInput,
+    width: u32,
+    height: u32,
+    colors: Option<ColorInput>,
+    background_color: &str,
+) -> PyResult<Bound<'py, PyAny>> {
+    let background_color = match background_color.to_lowercase().as_str() {
+        "black" => BackgroundColor::Black,
+        "white" => BackgroundColor::White,
+        _ => return Err(Error::invalid_parameter("The valid values for 'background_color' are 'black' and 'white'.").into()),
     };
-    let output_img = bradley_adaptive_threshold(&source_img.to_luma8(), size, bradley_threshold);
+    let output_img = py.allow_threads(|| match colors {
+        Some(colors) => {
+            let color_coords = zip_colors(coordinates.0, colors.0)?;
+            PyResult::Ok(DynamicImage::ImageRgba8(coordinates_to_color_image(width, height, &color_coords, background_color)))
+        }
+        None => Ok(DynamicImage::ImageLuma8(coordinates_to_image(width, height, &coordinates.0))),
+    })?;
+    image_array(py, &output_img)
+}
 
-    // creating intermediate directories if necessary
-    let path = std::path::Path::new(output_path);
-    if let Some(prefix) = path.parent() {
-        std::fs::create_dir_all(prefix).unwrap();
+#[pyfunction(name="render_lights", signature=(coordinates, width, height, light_radius=3.0))]
+/// Draws coordinates as glowing lights, like the preview of the GUI
+///
+/// Each light is a Gaussian splat whose intensity drops to 1% at `light_radius`, and the
+/// lights add up where they overlap.
+///
+/// Arguments:
+///     coordinates: numpy.ndarray | list[(u32, u32)] | CoordinateOutput
+///         the (x, y) coordinates of the lights, such as an (N, 2) array
+///     width: u32
+///         width of the image
+///     height: u32
+///         height of the image
+///     light_radius: f32
+///         radius of each light, in pixels. Defaults to 3.0
+///
+/// Returns:
+///     image: numpy.ndarray
+///         the rendered lights, as an (H, W) uint8 array
+fn py_render_lights<'py>(
+    py: Python<'py>,
+    coordinates: CoordinateInput,
+    width: u32,
+    height: u32,
+    light_radius: f32,
+) -> PyResult<Bound<'py, PyAny>> {
+    if light_radius <= 0.0 {
+        return Err(Error::invalid_parameter("'light_radius' must be greater than 0.").into())
     }
+    let output_img = py.allow_threads(|| {
+        let pixels = render_lights(&coordinates.0, width, height, light_radius);
+        let pixels = pixels.into_iter().map(|p| (p * 255.0).round() as u8).collect();
+        image::GrayImage::from_raw(width, height, pixels).expect("one pixel was rendered per position")
+    });
+    image_array(py, &DynamicImage::ImageLuma8(output_img))
+}
 
-    match output_img.save(output_path) {
-        Ok(_) => Ok(()),
-        Err(e) => Err(PyValueError::new_err(format!("Unable to create file in path 'output/img.png': {}", e)))
+#[pyfunction(name="evaluate_formation", signature=(candidates, coordinates, width, height, light_radius=None))]
+/// Scores how well a formation represents the candidate pixels it was sampled from
+///
+/// The formation is rendered as lights and compared to the thresholded image with SSIM, the
+/// distances between the drones and the candidate pixels are measured both ways, and the
+/// number of candidate pixels each drone stands for gives the uniformity of the coverage.
+///
+/// Arguments:
+///     candidates: numpy.ndarray | list[(u32, u32)] | CoordinateOutput
+///         the (x, y) coordinates of the candidate pixels, such as returned by `image_to_coordinates`
+///     coordinates: numpy.ndarray | list[(u32, u32)] | CoordinateOutput
+///         the (x, y) coordinates of the drones
+///     width: u32
+///         width of the thresholded image
+///     height: u32
+///         height of the thresholded image
+///     light_radius: f32
+///         radius of the rendered lights, in pixels. Defaults to None, which uses the spacing of the drones if they were spread evenly over the candidates
+///
+/// Returns:
+///     metrics: FormationMetrics
+///         the scores of the formation
+fn py_evaluate_formation(
+    py: Python<'_>,
+    candidates: CoordinateInput,
+    coordinates: CoordinateInput,
+    width: u32,
+    height: u32,
+    light_radius: Option<f32>,
+) -> Result<FormationMetrics, Error> {
+    if light_radius.is_some_and(|radius| radius <= 0.0) {
+        return Err(Error::invalid_parameter("'light_radius' must be greater than 0."))
     }
+    py.allow_threads(|| evaluate_formation(&candidates.0, &coordinates.0, width, height, light_radius))
+        .ok_or_else(|| Error::EmptyResult("Both the candidates and the coordinates are needed to evaluate a formation.".to_string()))
+}
+
+#[pyfunction(signature=(input_path, size, bradley_threshold=15, output_path="output/bradley.png"))]
+fn test_bradley(
+    py: Python<'_>,
+    input_path: ImageSource, 
+    size: u32,
+    bradley_threshold: u8,
+    output_path: &str,
+) -> Result<(), Error> {
+    py.allow_threads(|| {
+        let source_img = input_path.load(None)?;
+        let output_img = bradley_adaptive_threshold(&source_img.to_luma8(), size, bradley_threshold);
+
+        save_image(std::path::Path::new(output_path), &output_img.into())
+    })
 }
 
 /// A Python module implemented in Rust.
 #[pymodule]
 fn raster_drone(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    init_python_logging(m.py());
+    register_exceptions(m)?;
+    m.add_class::<Pipeline>()?;
+    m.add_class::<RunStats>()?;
+    m.add_class::<FormationMetrics>()?;
+    m.add_class::<TuneResult>()?;
     m.add_function(wrap_pyfunction!(process_image, m)?)?;
     m.add_function(wrap_pyfunction!(process_color_image, m)?)?;
     m.add_function(wrap_pyfunction!(process_image_to_coordinates, m)?)?;
+    m.add_function(wrap_pyfunction!(process_color_image_to_coordinates, m)?)?;
+    m.add_function(wrap_pyfunction!(process_batch, m)?)?;
+    m.add_function(wrap_pyfunction!(auto_tune, m)?)?;
+    m.add_function(wrap_pyfunction!(process_animation, m)?)?;
+    m.add_function(wrap_pyfunction!(process_mesh, m)?)?;
+    m.add_function(wrap_pyfunction!(process_text, m)?)?;
+    m.add_function(wrap_pyfunction!(export_color_formation, m)?)?;
+    m.add_function(wrap_pyfunction!(export_depth_formation, m)?)?;
+    m.add_function(wrap_pyfunction!(export_layered_formation, m)?)?;
     m.add_function(wrap_pyfunction!(test_bradley, m)?)?;
+    m.add_function(wrap_pyfunction!(py_bradley_adaptive_threshold, m)?)?;
+    m.add_function(wrap_pyfunction!(py_image_to_coordinates, m)?)?;
+    m.add_function(wrap_pyfunction!(py_farthest_point_sampling, m)?)?;
+    m.add_function(wrap_pyfunction!(py_grid_sampling, m)?)?;
+    m.add_function(wrap_pyfunction!(py_color_image_to_coordinates, m)?)?;
+    m.add_function(wrap_pyfunction!(py_color_albedo_sampling, m)?)?;
+    m.add_function(wrap_pyfunction!(py_coordinates_to_image, m)?)?;
+    m.add_function(wrap_pyfunction!(py_render_lights, m)?)?;
+    m.add_function(wrap_pyfunction!(py_evaluate_formation, m)?)?;
     Ok(())
 }
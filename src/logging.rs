@@ -0,0 +1,95 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use pyo3::prelude::*;
+
+/// Forwards the records of the `log` facade to Python's `logging` module.
+///
+/// Each record goes to the Python logger named after the Rust module it comes from, such
+/// as `raster_drone.sampling`, so the messages of the library can be shown, silenced or
+/// redirected with the usual `logging` configuration of the `raster_drone` logger.
+///
+/// The effective level of the `raster_drone` logger is cached as the max level of the `log`
+/// facade, so that records Python doesn't want are dropped without taking the GIL.
+struct PythonLogger;
+
+static PYTHON_LOGGER: PythonLogger = PythonLogger;
+
+/// Whether `PYTHON_LOGGER` is the logger of the `log` facade, rather than the logger of the GUI.
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+impl Log for PythonLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let name = record.target().replace("::", ".");
+        let level = python_level(record.level());
+        Python::with_gil(|py| {
+            let result = (|| -> PyResult<()> {
+                let logger = py.import("logging")?.call_method1("getLogger", (name,))?;
+                if logger.call_method1("isEnabledFor", (level,))?.is_truthy()? {
+                    logger.call_method1("log", (level, record.args().to_string()))?;
+                }
+                Ok(())
+            })();
+            // logging is best effort, and must never fail the call being logged
+            drop(result);
+        });
+    }
+
+    fn flush(&self) {}
+}
+
+/// The Python `logging` level of a `log` level, with trace records just below `DEBUG`.
+fn python_level(level: Level) -> u8 {
+    match level {
+        Level::Error => 40,
+        Level::Warn => 30,
+        Level::Info => 20,
+        Level::Debug => 10,
+        Level::Trace => 5,
+    }
+}
+
+/// The `log` level filter letting through the records at or above a Python `logging` level.
+fn level_filter(python_level: u32) -> LevelFilter {
+    match python_level {
+        0..=5 => LevelFilter::Trace,
+        6..=10 => LevelFilter::Debug,
+        11..=20 => LevelFilter::Info,
+        21..=30 => LevelFilter::Warn,
+        31..=40 => LevelFilter::Error,
+        _ => LevelFilter::Off,
+    }
+}
+
+/// Caches the effective level of the `raster_drone` Python logger, so that only the records
+/// it will emit take the GIL. This runs at import and at the start of every
+/// `run_with_progress`, so a `logging` configuration made after importing the module applies
+/// to the next run.
+pub fn refresh_log_level(py: Python<'_>) {
+    if !INSTALLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let level = py
+        .import("logging")
+        .and_then(|logging| logging.call_method1("getLogger", ("raster_drone",)))
+        .and_then(|logger| logger.call_method0("getEffectiveLevel"))
+        .and_then(|level| level.extract::<u32>());
+    if let Ok(level) = level {
+        log::set_max_level(level_filter(level));
+    }
+}
+
+/// Installs the bridge to Python's `logging` module as the logger of the library.
+pub fn init_python_logging(py: Python<'_>) {
+    if log::set_logger(&PYTHON_LOGGER).is_ok() {
+        INSTALLED.store(true, Ordering::Relaxed);
+        refresh_log_level(py);
+    }
+}
@@ -1,13 +1,14 @@
-mod raster;
-mod transformation;
-mod utils;
-mod sampling;
-mod thresholding;
 mod gui;
 
-use gui::app::run_app;
+// the GUI works on the same modules as the Python library
+use raster_drone::{
+    adjust, crazyswarm, depth, error, export, geometry, metrics, pipeline, progress, raster, resize,
+    stats, text, transformation, tune, utils, vector,
+};
+
+use gui::{app::run_app, log::init_logging};
 
 fn main() {
-    // You might want to add logging initialization here, e.g., `env_logger::init();`
+    init_logging();
     pollster::block_on(run_app());
 }
@@ -0,0 +1,256 @@
+use std::collections::{HashMap, VecDeque};
+
+use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+
+use crate::{
+    color::{lab_distance_squared, rgb_to_lab},
+    transformation::ColorCoordinate,
+};
+
+/// Settings for separating the foreground of an image from its background.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct MaskParams {
+    /// Pixels with an alpha below this value are background. 0 keeps every pixel.
+    pub alpha_threshold: u8,
+    /// A background color to remove, such as the green of a green screen.
+    pub chroma_key: Option<[u8; 3]>,
+    /// How far a color may be from `chroma_key` and still be removed, as a CIELAB difference.
+    pub chroma_tolerance: f32,
+    /// Removes the regions of uniform color connected to the edges of the image.
+    pub flood_fill: bool,
+    /// How far a color may be from the most common edge color and still be removed, as a
+    /// CIELAB difference.
+    pub flood_tolerance: f32,
+}
+
+impl Default for MaskParams {
+    fn default() -> Self {
+        Self {
+            alpha_threshold: 128,
+            chroma_key: None,
+            chroma_tolerance: 20.0,
+            flood_fill: false,
+            flood_tolerance: 10.0,
+        }
+    }
+}
+
+impl MaskParams {
+    /// The settings from the keyword arguments of the Python API, which take the chroma key as
+    /// an (r, g, b) tuple and call the flood fill the removal of the border background.
+    pub fn new(
+        alpha_threshold: u8,
+        chroma_key: Option<(u8, u8, u8)>,
+        chroma_tolerance: f32,
+        remove_border_background: bool,
+        border_tolerance: f32,
+    ) -> Self {
+        Self {
+            alpha_threshold,
+            chroma_key: chroma_key.map(|(r, g, b)| [r, g, b]),
+            chroma_tolerance,
+            flood_fill: remove_border_background,
+            flood_tolerance: border_tolerance,
+        }
+    }
+
+    /// Whether these settings remove anything from `img`. The alpha rule alone leaves a fully
+    /// opaque image untouched, so such images skip the mask entirely.
+    pub fn is_active(&self, img: &DynamicImage) -> bool {
+        let transparent = self.alpha_threshold > 0
+            && img.color().has_alpha()
+            && img.pixels().any(|(_, _, pixel)| pixel[3] < self.alpha_threshold);
+        transparent || self.chroma_key.is_some() || self.flood_fill
+    }
+}
+
+/// Marks which pixels of an image belong to the foreground.
+#[derive(Debug, Clone)]
+pub struct ForegroundMask {
+    width: u32,
+    height: u32,
+    foreground: Vec<bool>,
+}
+
+impl ForegroundMask {
+    pub fn contains(&self, x: u32, y: u32) -> bool {
+        x < self.width && y < self.height && self.foreground[(y * self.width + x) as usize]
+    }
+}
+
+/// Computes the foreground mask of an image.
+///
+/// A pixel is background if any of the enabled rules removes it: its alpha is below
+/// `alpha_threshold`, its color is within `chroma_tolerance` of the chroma key, or it can be
+/// reached from the edges of the image through pixels within `flood_tolerance` of the
+/// background color. The background color is the most common color along the edges, so a
+/// subject touching the edge keeps its own pixels. Pixels removed by the other rules are
+/// always passable, so a transparent or keyed border doesn't stop the fill from reaching
+/// further inwards.
+pub fn foreground_mask(img: &DynamicImage, params: &MaskParams) -> ForegroundMask {
+    let (width, height) = img.dimensions();
+    let rgba = img.to_rgba8();
+    let index = |x: u32, y: u32| (y * width + x) as usize;
+
+    let key_lab = params.chroma_key.map(rgb_to_lab);
+    let chroma_tolerance_sq = (params.chroma_tolerance as f64).powi(2);
+    // only the chroma key and the flood fill compare colors
+    let labs: Vec<[f32; 3]> = if params.chroma_key.is_some() || params.flood_fill {
+        rgba.pixels().map(|p| rgb_to_lab([p[0], p[1], p[2]])).collect()
+    } else {
+        Vec::new()
+    };
+
+    // `labs` is empty unless a color rule is set, so it is only indexed under `key_lab`
+    let mut foreground: Vec<bool> = rgba
+        .pixels()
+        .enumerate()
+        .map(|(i, pixel)| {
+            let opaque = pixel[3] >= params.alpha_threshold;
+            let keyed = key_lab.is_some_and(|key| lab_distance_squared(&labs[i], &key) <= chroma_tolerance_sq);
+            opaque && !keyed
+        })
+        .collect();
+
+    if params.flood_fill && width > 0 && height > 0 {
+        let mut border: Vec<(u32, u32)> = Vec::new();
+        border.extend((0..width).flat_map(|x| [(x, 0), (x, height - 1)]));
+        border.extend((0..height).flat_map(|y| [(0, y), (width - 1, y)]));
+
+        if let Some(background) = border_color(&rgba, &border, &foreground) {
+            let background = rgb_to_lab(background);
+            let flood_tolerance_sq = (params.flood_tolerance as f64).powi(2);
+            // background removed by the other rules is always passable
+            let passable = |i: usize, foreground: &[bool]| {
+                !foreground[i] || lab_distance_squared(&labs[i], &background) <= flood_tolerance_sq
+            };
+
+            let mut visited = vec![false; foreground.len()];
+            let mut queue: VecDeque<(u32, u32)> = VecDeque::new();
+            for (x, y) in border {
+                let i = index(x, y);
+                if !visited[i] && passable(i, &foreground) {
+                    visited[i] = true;
+                    foreground[i] = false;
+                    queue.push_back((x, y));
+                }
+            }
+
+            while let Some((x, y)) = queue.pop_front() {
+                let neighbours = [
+                    (x.wrapping_sub(1), y),
+                    (x + 1, y),
+                    (x, y.wrapping_sub(1)),
+                    (x, y + 1),
+                ];
+                for (nx, ny) in neighbours {
+                    if nx >= width || ny >= height {
+                        continue;
+                    }
+                    let i = index(nx, ny);
+                    if !visited[i] && passable(i, &foreground) {
+                        visited[i] = true;
+                        foreground[i] = false;
+                        queue.push_back((nx, ny));
+                    }
+                }
+            }
+        }
+    }
+
+    ForegroundMask {
+        width,
+        height,
+        foreground,
+    }
+}
+
+/// The most common color among the `border` pixels still in the foreground, or `None` if the
+/// other rules already removed the whole border.
+fn border_color(rgba: &RgbaImage, border: &[(u32, u32)], foreground: &[bool]) -> Option<[u8; 3]> {
+    let mut counts: HashMap<[u8; 3], usize> = HashMap::new();
+    for &(x, y) in border {
+        if foreground[(y * rgba.width() + x) as usize] {
+            let pixel = rgba.get_pixel(x, y);
+            *counts.entry([pixel[0], pixel[1], pixel[2]]).or_default() += 1;
+        }
+    }
+    // ties go to the smallest color, so the result doesn't depend on the hash order
+    counts
+        .into_iter()
+        .max_by_key(|&(color, count)| (count, std::cmp::Reverse(color)))
+        .map(|(color, _)| color)
+}
+
+/// Keeps only the color pixels that belong to the foreground.
+pub fn filter_color_coordinates(coords: Vec<ColorCoordinate>, mask: &ForegroundMask) -> Vec<ColorCoordinate> {
+    coords
+        .into_iter()
+        .filter(|coord| mask.contains(coord.x(), coord.y()))
+        .collect()
+}
+
+/// Makes the background of an image fully transparent.
+///
+/// Transparent pixels have no brightness, so they are never picked as candidates by
+/// `image_to_coordinates` or `color_image_to_coordinates`, whatever the image type.
+pub fn remove_background(img: &DynamicImage, params: &MaskParams) -> DynamicImage {
+    let mask = foreground_mask(img, params);
+    let mut rgba: RgbaImage = img.to_rgba8();
+    for (x, y, pixel) in rgba.enumerate_pixels_mut() {
+        if !mask.contains(x, y) {
+            *pixel = Rgba([0, 0, 0, 0]);
+        }
+    }
+    DynamicImage::ImageRgba8(rgba)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flood_fill_keeps_a_subject_touching_the_edge() {
+        // a white background with a black bar running off the left edge
+        let img = RgbaImage::from_fn(10, 10, |x, y| {
+            if x < 5 && (4..6).contains(&y) { Rgba([0, 0, 0, 255]) } else { Rgba([255, 255, 255, 255]) }
+        });
+        let params = MaskParams { flood_fill: true, ..MaskParams::default() };
+        let mask = foreground_mask(&DynamicImage::ImageRgba8(img), &params);
+
+        assert!(mask.contains(0, 4));
+        assert!(mask.contains(4, 5));
+        assert!(!mask.contains(0, 0));
+        assert!(!mask.contains(9, 5));
+        assert!(!mask.contains(5, 4));
+    }
+
+    #[test]
+    fn opaque_images_skip_the_alpha_mask() {
+        let img = DynamicImage::ImageRgba8(RgbaImage::from_pixel(4, 4, Rgba([10, 20, 30, 255])));
+        assert!(!MaskParams::default().is_active(&img));
+
+        let mut img = img.to_rgba8();
+        img.put_pixel(0, 0, Rgba([10, 20, 30, 0]));
+        assert!(MaskParams::default().is_active(&DynamicImage::ImageRgba8(img)));
+    }
+
+    #[test]
+    fn alpha_only_mask_removes_transparent_pixels() {
+        // a transparent image with an opaque square in the middle
+        let img = RgbaImage::from_fn(6, 6, |x, y| {
+            if (2..4).contains(&x) && (2..4).contains(&y) { Rgba([0, 0, 0, 255]) } else { Rgba([0, 0, 0, 0]) }
+        });
+        let img = DynamicImage::ImageRgba8(img);
+        let mask = foreground_mask(&img, &MaskParams::default());
+
+        assert!(mask.contains(2, 2));
+        assert!(mask.contains(3, 3));
+        assert!(!mask.contains(0, 0));
+        assert!(!mask.contains(5, 2));
+
+        let removed = remove_background(&img, &MaskParams::default()).to_rgba8();
+        assert_eq!(removed.get_pixel(0, 0), &Rgba([0, 0, 0, 0]));
+        assert_eq!(removed.get_pixel(2, 3), &Rgba([0, 0, 0, 255]));
+    }
+}
@@ -0,0 +1,394 @@
+use std::{fs::File, io::{BufRead, BufReader, Read}, path::Path};
+
+use pyo3::prelude::*;
+
+use crate::{
+    error::Error,
+    progress::{Cancelled, Progress},
+    sampling::farthest_point_sampling_3d_with_progress,
+    utils::Coordinate3D,
+};
+
+/// A triangle mesh, reduced to the vertex positions and triangle indices needed for sampling.
+pub struct TriangleMesh {
+    pub vertices: Vec<[f64; 3]>,
+    pub triangles: Vec<[usize; 3]>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum UpAxis {
+    Y,
+    Z,
+}
+
+impl FromPyObject<'_> for UpAxis {
+    fn extract_bound(ob: &Bound<'_, PyAny>) -> PyResult<Self> {
+        if let Ok(s) = ob.extract::<&str>() {
+            match s.to_lowercase().as_str() {
+                "y" => Ok(Self::Y),
+                "z" => Ok(Self::Z),
+                _ => Err(Error::invalid_parameter("The valid values for 'up_axis' are 'y' and 'z'.").into())
+            }
+        } else {
+            Ok(Self::Y)
+        }
+    }
+}
+
+/// Loads an OBJ, STL or PLY mesh from disk, based on the file extension.
+///
+/// Positions are converted so that y points up: meshes authored with z up (common for
+/// STL files from CAD tools) should be loaded with `UpAxis::Z`.
+pub fn load_mesh(path: &Path, up_axis: UpAxis) -> Result<TriangleMesh, String> {
+    let extension = path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase());
+    let mut mesh = match extension.as_deref() {
+        Some("obj") => load_obj(path)?,
+        Some("stl") => load_stl(path)?,
+        Some("ply") => load_ply(path)?,
+        _ => return Err("The valid mesh formats are '.obj', '.stl' and '.ply'".to_string()),
+    };
+
+    if up_axis == UpAxis::Z {
+        for vertex in mesh.vertices.iter_mut() {
+            *vertex = [vertex[0], vertex[2], -vertex[1]];
+        }
+    }
+    if mesh.triangles.is_empty() {
+        return Err("The mesh has no faces".to_string());
+    }
+    Ok(mesh)
+}
+
+fn load_obj(path: &Path) -> Result<TriangleMesh, String> {
+    let options = tobj::LoadOptions {
+        triangulate: true,
+        ..Default::default()
+    };
+    let (models, _materials) = tobj::load_obj(path, &options).map_err(|e| format!("{:?}", e))?;
+
+    let mut mesh = TriangleMesh { vertices: Vec::new(), triangles: Vec::new() };
+    for model in models {
+        let offset = mesh.vertices.len();
+        mesh.vertices.extend(
+            model.mesh.positions
+                .chunks_exact(3)
+                .map(|p| [p[0] as f64, p[1] as f64, p[2] as f64]),
+        );
+        mesh.triangles.extend(
+            model.mesh.indices
+                .chunks_exact(3)
+                .map(|t| [offset + t[0] as usize, offset + t[1] as usize, offset + t[2] as usize]),
+        );
+    }
+    Ok(mesh)
+}
+
+fn load_stl(path: &Path) -> Result<TriangleMesh, String> {
+    let mut file = File::open(path).map_err(|e| format!("{:?}", e))?;
+    let stl = stl_io::read_stl(&mut file).map_err(|e| format!("{:?}", e))?;
+    Ok(TriangleMesh {
+        vertices: stl.vertices
+            .iter()
+            .map(|v| [v.0[0] as f64, v.0[1] as f64, v.0[2] as f64])
+            .collect(),
+        triangles: stl.faces.iter().map(|face| face.vertices).collect(),
+    })
+}
+
+/// A property declared in a PLY header. List properties also carry the type of their length prefix.
+struct PlyProperty {
+    name: String,
+    kind: String,
+    list_count_kind: Option<String>,
+}
+
+/// The longest list a PLY element may hold. Face lists are usually counted with a `uchar`, so
+/// any longer list is a corrupt file rather than a real polygon.
+const MAX_PLY_LIST_LEN: usize = 1 << 16;
+
+/// An element declared in a PLY header, such as `vertex` or `face`.
+struct PlyElement {
+    name: String,
+    count: usize,
+    properties: Vec<PlyProperty>,
+}
+
+/// Reads a PLY mesh, in either ASCII or binary little-endian format.
+///
+/// Only the `x`, `y` and `z` vertex properties and the face index lists are used;
+/// polygons with more than three corners are split into a triangle fan.
+fn load_ply(path: &Path) -> Result<TriangleMesh, String> {
+    let file = File::open(path).map_err(|e| format!("{:?}", e))?;
+    let mut reader = BufReader::new(file);
+
+    // --- Parse the header ---
+    let mut format = String::new();
+    let mut elements: Vec<PlyElement> = Vec::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).map_err(|e| format!("{:?}", e))? == 0 {
+            return Err("Unexpected end of PLY header".to_string());
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["format", f, ..] => format = f.to_string(),
+            ["element", name, count] => elements.push(PlyElement {
+                name: name.to_string(),
+                count: count.parse().map_err(|_| format!("Invalid PLY element count '{}'", count))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count_kind, kind, name] => {
+                if let Some(element) = elements.last_mut() {
+                    element.properties.push(PlyProperty {
+                        name: name.to_string(),
+                        kind: kind.to_string(),
+                        list_count_kind: Some(count_kind.to_string()),
+                    });
+                }
+            }
+            ["property", kind, name] => {
+                if let Some(element) = elements.last_mut() {
+                    element.properties.push(PlyProperty {
+                        name: name.to_string(),
+                        kind: kind.to_string(),
+                        list_count_kind: None,
+                    });
+                }
+            }
+            ["end_header"] => break,
+            _ => {}
+        }
+    }
+
+    let mut mesh = TriangleMesh { vertices: Vec::new(), triangles: Vec::new() };
+    let mut ascii_tokens: Vec<String> = Vec::new();
+    let mut ascii_cursor = 0;
+    match format.as_str() {
+        "ascii" => {
+            let mut body = String::new();
+            reader.read_to_string(&mut body).map_err(|e| format!("{:?}", e))?;
+            ascii_tokens = body.split_whitespace().map(str::to_string).collect();
+        }
+        "binary_little_endian" => {}
+        _ => return Err(format!("Unsupported PLY format '{}'", format)),
+    }
+
+    // reads one scalar value of the given PLY type, from whichever encoding the file uses
+    let mut read_value = |kind: &str, reader: &mut BufReader<File>| -> Result<f64, String> {
+        if format == "ascii" {
+            let token = ascii_tokens.get(ascii_cursor).ok_or("Unexpected end of PLY data")?;
+            ascii_cursor += 1;
+            return token.parse().map_err(|_| format!("Invalid PLY value '{}'", token));
+        }
+        let size = match kind {
+            "char" | "uchar" | "int8" | "uint8" => 1,
+            "short" | "ushort" | "int16" | "uint16" => 2,
+            "int" | "uint" | "float" | "int32" | "uint32" | "float32" => 4,
+            "double" | "float64" => 8,
+            _ => return Err(format!("Unsupported PLY property type '{}'", kind)),
+        };
+        let mut bytes = [0u8; 8];
+        reader.read_exact(&mut bytes[..size]).map_err(|e| format!("{:?}", e))?;
+        Ok(match kind {
+            "char" | "int8" => bytes[0] as i8 as f64,
+            "uchar" | "uint8" => bytes[0] as f64,
+            "short" | "int16" => i16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+            "ushort" | "uint16" => u16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+            "int" | "int32" => i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+            "uint" | "uint32" => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+            "float" | "float32" => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+            _ => f64::from_le_bytes(bytes),
+        })
+    };
+
+    // --- Read the element data ---
+    for element in &elements {
+        for _ in 0..element.count {
+            let mut position = [0.0; 3];
+            for property in &element.properties {
+                match &property.list_count_kind {
+                    Some(count_kind) => {
+                        let len = ply_index(read_value(count_kind, &mut reader)?)?;
+                        if len > MAX_PLY_LIST_LEN {
+                            return Err(format!("Invalid PLY list length {}", len));
+                        }
+                        let mut indices = Vec::new();
+                        for _ in 0..len {
+                            indices.push(ply_index(read_value(&property.kind, &mut reader)?)?);
+                        }
+                        if element.name == "face" && matches!(property.name.as_str(), "vertex_indices" | "vertex_index") {
+                            for i in 1..indices.len().saturating_sub(1) {
+                                mesh.triangles.push([indices[0], indices[i], indices[i + 1]]);
+                            }
+                        }
+                    }
+                    None => {
+                        let value = read_value(&property.kind, &mut reader)?;
+                        match property.name.as_str() {
+                            "x" => position[0] = value,
+                            "y" => position[1] = value,
+                            "z" => position[2] = value,
+                            _ => {}
+                        }
+                    }
+                }
+            }
+            if element.name == "vertex" {
+                mesh.vertices.push(position);
+            }
+        }
+    }
+
+    if mesh.triangles.iter().flatten().any(|&i| i >= mesh.vertices.len()) {
+        return Err("The PLY faces refer to vertices that don't exist".to_string());
+    }
+    Ok(mesh)
+}
+
+/// Converts a PLY list length or vertex index to `usize`, rejecting negative and fractional values.
+fn ply_index(value: f64) -> Result<usize, String> {
+    if value < 0.0 || value.fract() != 0.0 || value > usize::MAX as f64 {
+        return Err(format!("Invalid PLY index or list length {}", value));
+    }
+    Ok(value as usize)
+}
+
+/// Places `n` points evenly over the surface of a mesh.
+///
+/// A dense set of candidate points is first scattered over the triangles, with each
+/// triangle receiving candidates in proportion to its area. Farthest point sampling then
+/// picks `n` of those candidates, so that the final points are spread evenly in 3D.
+///
+/// The candidates come from a fixed-seed generator, so the same mesh always gives the
+/// same formation. The farthest point sampling reports each selected point to `progress`,
+/// and stops early once it is cancelled.
+pub fn mesh_surface_sampling(mesh: &TriangleMesh, n: u32, progress: &Progress) -> Result<Vec<Coordinate3D>, Cancelled> {
+    // how many candidates to scatter for each point that is eventually selected
+    const CANDIDATES_PER_POINT: usize = 40;
+    const MIN_CANDIDATES: usize = 10_000;
+
+    let corners = |t: &[usize; 3]| (mesh.vertices[t[0]], mesh.vertices[t[1]], mesh.vertices[t[2]]);
+    let sub = |a: [f64; 3], b: [f64; 3]| [a[0] - b[0], a[1] - b[1], a[2] - b[2]];
+
+    // --- Cumulative triangle areas, for area-weighted selection ---
+    let mut cumulative_areas = Vec::with_capacity(mesh.triangles.len());
+    let mut total_area = 0.0;
+    for triangle in &mesh.triangles {
+        let (a, b, c) = corners(triangle);
+        let (ab, ac) = (sub(b, a), sub(c, a));
+        let cross = [
+            ab[1] * ac[2] - ab[2] * ac[1],
+            ab[2] * ac[0] - ab[0] * ac[2],
+            ab[0] * ac[1] - ab[1] * ac[0],
+        ];
+        total_area += (cross[0] * cross[0] + cross[1] * cross[1] + cross[2] * cross[2]).sqrt() / 2.0;
+        cumulative_areas.push(total_area);
+    }
+    if n == 0 || total_area <= 0.0 {
+        return Ok(Vec::new());
+    }
+
+    // xorshift64*, which is plenty for scattering candidates
+    let mut state: u64 = 0x9E37_79B9_7F4A_7C15;
+    let mut random = || {
+        state ^= state >> 12;
+        state ^= state << 25;
+        state ^= state >> 27;
+        (state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 11) as f64 / (1u64 << 53) as f64
+    };
+
+    let candidate_count = (n as usize * CANDIDATES_PER_POINT).max(MIN_CANDIDATES);
+    let candidates: Vec<Coordinate3D> = (0..candidate_count)
+        .map(|_| {
+            let target = random() * total_area;
+            let index = cumulative_areas
+                .partition_point(|&area| area < target)
+                .min(mesh.triangles.len() - 1);
+            let (a, b, c) = corners(&mesh.triangles[index]);
+
+            // uniform point in the triangle, using the square root trick
+            let r1 = random().sqrt();
+            let r2 = random();
+            let (wa, wb, wc) = (1.0 - r1, r1 * (1.0 - r2), r1 * r2);
+            Coordinate3D::new(
+                wa * a[0] + wb * b[0] + wc * c[0],
+                wa * a[1] + wb * b[1] + wc * c[1],
+                wa * a[2] + wb * b[2] + wc * c[2],
+            )
+        })
+        .collect();
+
+    farthest_point_sampling_3d_with_progress(&candidates, n, progress)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes `data` to a temporary PLY file, loads it and removes the file again.
+    fn load_ply_bytes(name: &str, data: &[u8]) -> Result<TriangleMesh, String> {
+        let path = std::env::temp_dir().join(format!("raster_drone_{}.ply", name));
+        std::fs::write(&path, data).unwrap();
+        let mesh = load_ply(&path);
+        std::fs::remove_file(&path).unwrap();
+        mesh
+    }
+
+    fn ascii_ply(face: &str) -> String {
+        format!(
+            "ply\nformat ascii 1.0\nelement vertex 4\nproperty float x\nproperty float y\nproperty float z\n\
+             element face 1\nproperty list uchar int vertex_indices\nend_header\n\
+             0 0 0\n1 0 0\n1 1 0\n0 1 0\n{}\n",
+            face,
+        )
+    }
+
+    fn binary_ply(count_kind: &str, count: u32, indices: &[i32]) -> Vec<u8> {
+        let mut data = format!(
+            "ply\nformat binary_little_endian 1.0\nelement vertex 3\nproperty float x\nproperty float y\nproperty float z\n\
+             element face 1\nproperty list {} int vertex_indices\nend_header\n",
+            count_kind,
+        ).into_bytes();
+        for vertex in [[0.0f32, 0.0, 0.0], [2.0, 0.0, 0.0], [0.0, 3.0, 1.5]] {
+            data.extend(vertex.iter().flat_map(|v| v.to_le_bytes()));
+        }
+        match count_kind {
+            "uchar" => data.push(count as u8),
+            _ => data.extend(count.to_le_bytes()),
+        }
+        data.extend(indices.iter().flat_map(|i| i.to_le_bytes()));
+        data
+    }
+
+    #[test]
+    fn ascii_polygons_are_split_into_a_triangle_fan() {
+        let mesh = load_ply_bytes("ascii_quad", ascii_ply("4 0 1 2 3").as_bytes()).unwrap();
+        assert_eq!(mesh.vertices, vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]]);
+        assert_eq!(mesh.triangles, vec![[0, 1, 2], [0, 2, 3]]);
+    }
+
+    #[test]
+    fn binary_little_endian_values_are_decoded() {
+        let mesh = load_ply_bytes("binary_triangle", &binary_ply("uchar", 3, &[0, 1, 2])).unwrap();
+        assert_eq!(mesh.vertices, vec![[0.0, 0.0, 0.0], [2.0, 0.0, 0.0], [0.0, 3.0, 1.5]]);
+        assert_eq!(mesh.triangles, vec![[0, 1, 2]]);
+    }
+
+    #[test]
+    fn invalid_lists_and_indices_are_rejected() {
+        // a count far beyond any real polygon, without the data to back it
+        let error = load_ply_bytes("huge_list", &binary_ply("uint", 1 << 30, &[0, 1, 2])).err().unwrap();
+        assert!(error.contains("Invalid PLY list length"), "{}", error);
+
+        let error = load_ply_bytes("negative_index", &binary_ply("uchar", 3, &[0, -1, 2])).err().unwrap();
+        assert!(error.contains("Invalid PLY index"), "{}", error);
+
+        let error = load_ply_bytes("fractional_count", ascii_ply("2.5 0 1 2").as_bytes()).err().unwrap();
+        assert!(error.contains("Invalid PLY index"), "{}", error);
+
+        let error = load_ply_bytes("missing_vertex", ascii_ply("3 0 1 7").as_bytes()).err().unwrap();
+        assert!(error.contains("don't exist"), "{}", error);
+    }
+}
@@ -0,0 +1,226 @@
+use pyo3::prelude::*;
+
+use crate::{
+    stats::{to_points, PointGrid},
+    utils::Coordinate,
+};
+
+/// The falloff of a light, matching `lights.wgsl`: its intensity drops to 1% at `light_radius`.
+const FALLOFF: f32 = 4.6;
+/// Side of the square windows SSIM compares the two images over
+const SSIM_WINDOW: usize = 7;
+/// The stabilizing constants of SSIM, for intensities in [0, 1]
+const SSIM_C1: f64 = 0.01 * 0.01;
+const SSIM_C2: f64 = 0.03 * 0.03;
+
+/// Scores of how well a formation represents the candidate pixels it was sampled from
+///
+/// `ssim` compares the formation, rendered as glowing lights like the GUI preview, to the
+/// thresholded image, from -1.0 to 1.0 (identical). The distances are in pixels of the
+/// thresholded image, and `coverage_uniformity` is 1.0 when every drone stands for the same
+/// number of candidate pixels.
+#[derive(Debug, Clone, Copy)]
+#[pyclass(name="FormationMetrics", module="raster_drone", frozen)]
+pub struct FormationMetrics {
+    /// Structural similarity between the rendered formation and the thresholded image
+    #[pyo3(get)]
+    pub ssim: f64,
+    /// Mean of the distance from each candidate pixel to its nearest drone, and from each
+    /// drone to its nearest candidate pixel
+    #[pyo3(get)]
+    pub chamfer_distance: f64,
+    /// Largest distance from a candidate pixel to its nearest drone, or from a drone to its
+    /// nearest candidate pixel
+    #[pyo3(get)]
+    pub hausdorff_distance: f64,
+    /// 1 / (1 + the coefficient of variation of the number of candidate pixels nearest to
+    /// each drone)
+    #[pyo3(get)]
+    pub coverage_uniformity: f64,
+    /// Radius of the lights the formation was rendered with
+    #[pyo3(get)]
+    pub light_radius: f32,
+}
+
+#[pymethods]
+impl FormationMetrics {
+    fn __repr__(&self) -> String {
+        format!(
+            "FormationMetrics(ssim={:.3}, chamfer_distance={:.3}, hausdorff_distance={:.3}, coverage_uniformity={:.3})",
+            self.ssim, self.chamfer_distance, self.hausdorff_distance, self.coverage_uniformity,
+        )
+    }
+}
+
+/// A light radius at which the lights of an evenly spread formation just blend together: the
+/// spacing of `n` drones laid out on a square lattice over `candidate_count` pixels.
+pub fn default_light_radius(candidate_count: usize, n: usize) -> f32 {
+    ((candidate_count as f32 / n.max(1) as f32).sqrt()).max(1.0)
+}
+
+/// Renders the formation on the CPU the way `lights.wgsl` does, as Gaussian splats of
+/// `light_radius` added together, into intensities from 0.0 to 1.0 in row-major order.
+pub fn render_lights(coords: &[Coordinate], width: u32, height: u32, light_radius: f32) -> Vec<f32> {
+    let (width, height) = (width as i64, height as i64);
+    let mut pixels = vec![0.0f32; (width * height) as usize];
+    let falloff = FALLOFF / (light_radius * light_radius).max(0.0001);
+    // past twice the radius, a light adds less than 1e-8
+    let reach = (2.0 * light_radius).ceil() as i64;
+
+    for coord in coords {
+        let (cx, cy) = (coord.x() as i64, coord.y() as i64);
+        for y in (cy - reach).max(0)..=(cy + reach).min(height - 1) {
+            for x in (cx - reach).max(0)..=(cx + reach).min(width - 1) {
+                let sq_dist = ((x - cx).pow(2) + (y - cy).pow(2)) as f32;
+                pixels[(y * width + x) as usize] += (-falloff * sq_dist).exp();
+            }
+        }
+    }
+    for pixel in &mut pixels {
+        *pixel = pixel.min(1.0);
+    }
+    pixels
+}
+
+/// The thresholded image, with 1.0 at each candidate pixel and 0.0 elsewhere, in row-major order.
+fn candidate_image(candidates: &[Coordinate], width: u32, height: u32) -> Vec<f32> {
+    let mut pixels = vec![0.0f32; (width * height) as usize];
+    for coord in candidates {
+        if coord.x() < width && coord.y() < height {
+            pixels[(coord.y() * width + coord.x()) as usize] = 1.0;
+        }
+    }
+    pixels
+}
+
+/// Mean structural similarity of two images of intensities in [0, 1], over every square window
+/// of `SSIM_WINDOW` pixels (or smaller, for smaller images).
+pub fn ssim(a: &[f32], b: &[f32], width: usize, height: usize) -> f64 {
+    let window = SSIM_WINDOW.min(width).min(height);
+    if window == 0 {
+        return 1.0
+    }
+    // summed-area tables of a, b, a², b² and ab, so each window sums in constant time
+    let stride = width + 1;
+    let mut sums = vec![[0.0f64; 5]; stride * (height + 1)];
+    for y in 0..height {
+        for x in 0..width {
+            let (va, vb) = (a[y * width + x] as f64, b[y * width + x] as f64);
+            let values = [va, vb, va * va, vb * vb, va * vb];
+            let (above, left, corner) = (sums[y * stride + x + 1], sums[(y + 1) * stride + x], sums[y * stride + x]);
+            sums[(y + 1) * stride + x + 1] = std::array::from_fn(|i| values[i] + above[i] + left[i] - corner[i]);
+        }
+    }
+
+    let count = (window * window) as f64;
+    let mut total = 0.0;
+    let mut windows = 0;
+    for y in 0..=(height - window) {
+        for x in 0..=(width - window) {
+            let (top_left, top_right) = (sums[y * stride + x], sums[y * stride + x + window]);
+            let (bottom_left, bottom_right) = (sums[(y + window) * stride + x], sums[(y + window) * stride + x + window]);
+            let [sum_a, sum_b, sum_aa, sum_bb, sum_ab]: [f64; 5] = std::array::from_fn(|i| {
+                bottom_right[i] - bottom_left[i] - top_right[i] + top_left[i]
+            });
+            let (mean_a, mean_b) = (sum_a / count, sum_b / count);
+            let var_a = sum_aa / count - mean_a * mean_a;
+            let var_b = sum_bb / count - mean_b * mean_b;
+            let covariance = sum_ab / count - mean_a * mean_b;
+
+            total += ((2.0 * mean_a * mean_b + SSIM_C1) * (2.0 * covariance + SSIM_C2))
+                / ((mean_a * mean_a + mean_b * mean_b + SSIM_C1) * (var_a + var_b + SSIM_C2));
+            windows += 1;
+        }
+    }
+    total / windows as f64
+}
+
+/// The distance from each of `points` to the nearest point of `grid`.
+fn nearest_distances(points: &[[f64; 2]], grid: &PointGrid) -> Vec<f64> {
+    points
+        .iter()
+        .filter_map(|&point| grid.nearest(point, None))
+        .map(|(_, sq_dist)| sq_dist.sqrt())
+        .collect()
+}
+
+/// Scores the formation `sampled` against the `candidates` it was sampled from, in an image of
+/// `width` x `height` pixels. The lights are rendered with `light_radius`, or with
+/// `default_light_radius` if it is `None`.
+///
+/// Returns `None` if either set of points is empty, as there is nothing to compare.
+pub fn evaluate_formation(
+    candidates: &[Coordinate],
+    sampled: &[Coordinate],
+    width: u32,
+    height: u32,
+    light_radius: Option<f32>,
+) -> Option<FormationMetrics> {
+    if candidates.is_empty() || sampled.is_empty() {
+        return None
+    }
+    let light_radius = light_radius.unwrap_or_else(|| default_light_radius(candidates.len(), sampled.len()));
+
+    let rendered = render_lights(sampled, width, height, light_radius);
+    let thresholded = candidate_image(candidates, width, height);
+    let ssim = ssim(&rendered, &thresholded, width as usize, height as usize);
+
+    let candidate_points = to_points(candidates);
+    let sampled_points = to_points(sampled);
+    let sampled_grid = PointGrid::new(sampled_points.clone());
+    let candidate_grid = PointGrid::new(candidate_points.clone());
+    let to_sampled = nearest_distances(&candidate_points, &sampled_grid);
+    let to_candidates = nearest_distances(&sampled_points, &candidate_grid);
+
+    let mean = |distances: &[f64]| distances.iter().sum::<f64>() / distances.len() as f64;
+    let chamfer_distance = (mean(&to_sampled) + mean(&to_candidates)) / 2.0;
+    let hausdorff_distance = to_sampled.iter().chain(&to_candidates).copied().fold(0.0, f64::max);
+
+    // the number of candidate pixels each drone is the nearest to, like the area of its Voronoi cell
+    let mut cell_sizes = vec![0.0f64; sampled.len()];
+    for &point in &candidate_points {
+        if let Some((nearest, _)) = sampled_grid.nearest(point, None) {
+            cell_sizes[nearest] += 1.0;
+        }
+    }
+    let mean_size = mean(&cell_sizes);
+    let variance = cell_sizes.iter().map(|size| (size - mean_size).powi(2)).sum::<f64>() / cell_sizes.len() as f64;
+    let coverage_uniformity = 1.0 / (1.0 + variance.sqrt() / mean_size);
+
+    Some(FormationMetrics {
+        ssim,
+        chamfer_distance,
+        hausdorff_distance,
+        coverage_uniformity,
+        light_radius,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ssim_of_identical_images_is_one() {
+        let (width, height) = (20, 12);
+        let img: Vec<f32> = (0..width * height).map(|i| ((i * 7) % 11) as f32 / 10.0).collect();
+        assert!((ssim(&img, &img, width, height) - 1.0).abs() < 1e-9);
+
+        let inverted: Vec<f32> = img.iter().map(|v| 1.0 - v).collect();
+        assert!(ssim(&img, &inverted, width, height) < 0.5);
+    }
+
+    #[test]
+    fn drones_on_every_candidate_score_perfectly() {
+        let candidates: Vec<Coordinate> = (0..5).flat_map(|y| (0..5).map(move |x| Coordinate::new(x * 4, y * 4))).collect();
+        let metrics = evaluate_formation(&candidates, &candidates, 20, 20, None).unwrap();
+        assert_eq!(metrics.chamfer_distance, 0.0);
+        assert_eq!(metrics.hausdorff_distance, 0.0);
+        assert_eq!(metrics.coverage_uniformity, 1.0);
+
+        // a single drone in the corner is 16 pixels away, along both axes, from the farthest candidate
+        let corner = evaluate_formation(&candidates, &[Coordinate::new(0, 0)], 20, 20, None).unwrap();
+        assert!((corner.hausdorff_distance - 16.0 * 2f64.sqrt()).abs() < 1e-9);
+        assert!(evaluate_formation(&candidates, &[], 20, 20, None).is_none());
+    }
+}
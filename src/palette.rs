@@ -0,0 +1,370 @@
+use pyo3::prelude::*;
+
+use crate::{
+    color::{lab_distance_squared, rgb_to_lab},
+    error::Error,
+    transformation::ColorCoordinate,
+    utils::Coordinate,
+};
+
+/// The palette that sampled colors are restricted to.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Palette {
+    /// A fixed list of colors, e.g. the colors an LED model reproduces well
+    Colors(Vec<[u8; 3]>),
+    /// A palette of this many colors, computed from the sampled colors themselves
+    Count(usize),
+}
+
+impl FromPyObject<'_> for Palette {
+    fn extract_bound(ob: &Bound<'_, PyAny>) -> PyResult<Self> {
+        if let Ok(count) = ob.extract::<usize>() {
+            if count == 0 {
+                return Err(Error::invalid_parameter("'palette' must have at least 1 color.").into());
+            }
+            Ok(Self::Count(count))
+        } else if let Ok(colors) = ob.extract::<Vec<(u8, u8, u8)>>() {
+            Ok(Self::Colors(colors.into_iter().map(|(r, g, b)| [r, g, b]).collect()))
+        } else {
+            Err(Error::invalid_parameter("'palette' must be a number of colors, or a list of (r, g, b) tuples.").into())
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum PaletteMethod {
+    KMeans,
+    MedianCut,
+}
+
+impl FromPyObject<'_> for PaletteMethod {
+    fn extract_bound(ob: &Bound<'_, PyAny>) -> PyResult<Self> {
+        if let Ok(s) = ob.extract::<&str>() {
+            match s.to_lowercase().as_str() {
+                "kmeans" => Ok(Self::KMeans),
+                "median_cut" => Ok(Self::MedianCut),
+                _ => Err(Error::invalid_parameter("The valid values for 'palette_method' are 'kmeans' and 'median_cut'.").into())
+            }
+        } else {
+            Ok(Self::KMeans)
+        }
+    }
+}
+
+/// The response curve of the drones' LEDs, applied to colors right before export.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct LedCalibration {
+    /// Exponent applied to each normalized channel. Values above 1.0 darken mid-tones.
+    pub gamma: f32,
+    /// Multiplier applied to each of the red, green and blue channels after the gamma curve.
+    pub gain: [f32; 3],
+}
+
+impl Default for LedCalibration {
+    fn default() -> Self {
+        Self {
+            gamma: 1.0,
+            gain: [1.0; 3],
+        }
+    }
+}
+
+impl LedCalibration {
+    pub fn apply(&self, color: [u8; 3]) -> [u8; 3] {
+        let mut output = [0; 3];
+        for channel in 0..3 {
+            let value = (color[channel] as f32 / 255.0).powf(self.gamma) * self.gain[channel];
+            output[channel] = (value * 255.0).round().clamp(0.0, 255.0) as u8;
+        }
+        output
+    }
+}
+
+/// Resolves a `Palette` into a list of colors, computing it from `colors` when needed.
+pub fn build_palette(colors: &[[u8; 3]], palette: &Palette, method: PaletteMethod) -> Vec<[u8; 3]> {
+    match palette {
+        Palette::Colors(palette_colors) => palette_colors.clone(),
+        Palette::Count(count) => match method {
+            PaletteMethod::KMeans => kmeans_palette(colors, *count),
+            PaletteMethod::MedianCut => median_cut_palette(colors, *count),
+        },
+    }
+}
+
+/// Computes a palette of up to `k` colors by k-means clustering in CIELAB.
+///
+/// The initial centers are picked by farthest-first traversal of the colors, so the
+/// result is deterministic and distinct colors get a center of their own early on.
+fn kmeans_palette(colors: &[[u8; 3]], k: usize) -> Vec<[u8; 3]> {
+    const ITERATIONS: usize = 20;
+
+    if colors.is_empty() || k == 0 {
+        return Vec::new();
+    }
+    let labs: Vec<[f32; 3]> = colors.iter().map(|&c| rgb_to_lab(c)).collect();
+
+    // --- Farthest-first initialization ---
+    let mut center_indices = vec![0];
+    let mut min_sq_distances: Vec<f64> = labs.iter().map(|lab| lab_distance_squared(lab, &labs[0])).collect();
+    while center_indices.len() < k {
+        let (farthest, &distance) = min_sq_distances
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .expect("There should be at least one color");
+        // every remaining color is already a center
+        if distance <= 0.0 {
+            break;
+        }
+        center_indices.push(farthest);
+        for (i, lab) in labs.iter().enumerate() {
+            min_sq_distances[i] = min_sq_distances[i].min(lab_distance_squared(lab, &labs[farthest]));
+        }
+    }
+
+    // --- Lloyd iterations, averaging the RGB colors of each cluster ---
+    let mut centers: Vec<[u8; 3]> = center_indices.iter().map(|&i| colors[i]).collect();
+    for _ in 0..ITERATIONS {
+        let center_labs: Vec<[f32; 3]> = centers.iter().map(|&c| rgb_to_lab(c)).collect();
+        let mut sums = vec![[0.0f64; 3]; centers.len()];
+        let mut counts = vec![0usize; centers.len()];
+        for (color, lab) in colors.iter().zip(&labs) {
+            let nearest = nearest_index(lab, &center_labs);
+            for channel in 0..3 {
+                sums[nearest][channel] += color[channel] as f64;
+            }
+            counts[nearest] += 1;
+        }
+
+        let updated: Vec<[u8; 3]> = centers
+            .iter()
+            .zip(sums.iter().zip(&counts))
+            .map(|(&center, (sum, &count))| {
+                if count == 0 {
+                    center
+                } else {
+                    sum.map(|channel| (channel / count as f64).round() as u8)
+                }
+            })
+            .collect();
+        if updated == centers {
+            break;
+        }
+        centers = updated;
+    }
+    centers
+}
+
+/// Computes a palette of up to `k` colors with the median cut algorithm.
+///
+/// The box of colors with the widest channel range is repeatedly split at the median of
+/// that channel, and each final box contributes its average color.
+fn median_cut_palette(colors: &[[u8; 3]], k: usize) -> Vec<[u8; 3]> {
+    if colors.is_empty() || k == 0 {
+        return Vec::new();
+    }
+
+    // the widest channel of a box, and its range
+    let widest_channel = |colors: &[[u8; 3]]| {
+        (0..3)
+            .map(|channel| {
+                let min = colors.iter().map(|c| c[channel]).min().unwrap_or(0);
+                let max = colors.iter().map(|c| c[channel]).max().unwrap_or(0);
+                (channel, max - min)
+            })
+            .max_by_key(|&(_, range)| range)
+            .unwrap_or((0, 0))
+    };
+
+    let mut boxes = vec![colors.to_vec()];
+    while boxes.len() < k {
+        let Some((index, (channel, range))) = boxes
+            .iter()
+            .map(|colors| widest_channel(colors))
+            .enumerate()
+            .max_by_key(|&(_, (_, range))| range)
+        else {
+            break;
+        };
+        // every box holds a single color
+        if range == 0 {
+            break;
+        }
+
+        let mut split = boxes.swap_remove(index);
+        split.sort_unstable_by_key(|c| c[channel]);
+        let upper = split.split_off(split.len() / 2);
+        boxes.push(split);
+        boxes.push(upper);
+    }
+
+    boxes
+        .iter()
+        .map(|colors| {
+            let mut sum = [0u64; 3];
+            for color in colors {
+                for channel in 0..3 {
+                    sum[channel] += color[channel] as u64;
+                }
+            }
+            sum.map(|channel| (channel as f64 / colors.len() as f64).round() as u8)
+        })
+        .collect()
+}
+
+fn nearest_index(lab: &[f32; 3], palette_labs: &[[f32; 3]]) -> usize {
+    palette_labs
+        .iter()
+        .enumerate()
+        .min_by(|(_, a), (_, b)| lab_distance_squared(lab, a).total_cmp(&lab_distance_squared(lab, b)))
+        .map(|(i, _)| i)
+        .unwrap_or(0)
+}
+
+/// Maps each drone's color to the perceptually closest color of `palette`.
+///
+/// With `dither`, the color error of each drone is diffused onto its nearest neighbours that
+/// haven't been quantized yet, going top to bottom and left to right across the formation,
+/// like Floyd-Steinberg dithering on a scattered set of points. Regions whose color falls
+/// between two palette colors then come out as a mix of both rather than a flat band.
+///
+/// # Arguments
+/// * `coords` - The position of each drone, used to find neighbours when dithering.
+/// * `colors` - The color of each drone, in the same order as `coords`. Must be as long as `coords`.
+/// * `palette` - The colors the drones are restricted to.
+/// * `dither` - Whether to diffuse the quantization error across neighbouring drones.
+///
+/// # Returns
+/// The quantized color of each drone. If the palette is empty, the colors are returned unchanged.
+pub fn quantize_colors(
+    coords: &[Coordinate],
+    colors: &[[u8; 3]],
+    palette: &[[u8; 3]],
+    dither: bool,
+) -> Vec<[u8; 3]> {
+    // number of neighbours sharing the error of each drone
+    const DIFFUSION_NEIGHBOURS: usize = 4;
+
+    assert_eq!(coords.len(), colors.len(), "every drone needs a color");
+    if palette.is_empty() {
+        return colors.to_vec();
+    }
+    let palette_labs: Vec<[f32; 3]> = palette.iter().map(|&c| rgb_to_lab(c)).collect();
+    let mut targets: Vec<[f32; 3]> = colors.iter().map(|&c| rgb_to_lab(c)).collect();
+
+    if !dither {
+        return targets.iter().map(|lab| palette[nearest_index(lab, &palette_labs)]).collect();
+    }
+
+    // scan order: top to bottom, then left to right
+    let mut order: Vec<usize> = (0..coords.len()).collect();
+    order.sort_by_key(|&i| (coords[i].y(), coords[i].x()));
+
+    let mut quantized = vec![[0u8; 3]; colors.len()];
+    let mut done = vec![false; colors.len()];
+    for &i in &order {
+        let nearest = nearest_index(&targets[i], &palette_labs);
+        quantized[i] = palette[nearest];
+        done[i] = true;
+
+        let error = [0, 1, 2].map(|channel| targets[i][channel] - palette_labs[nearest][channel]);
+
+        // the closest drones still waiting to be quantized share the error, weighted by proximity
+        let mut neighbours: Vec<(usize, f64)> = (0..coords.len())
+            .filter(|&j| !done[j])
+            .map(|j| (j, coords[i].distance_squared(&coords[j])))
+            .collect();
+        if neighbours.len() > DIFFUSION_NEIGHBOURS {
+            neighbours.select_nth_unstable_by(DIFFUSION_NEIGHBOURS - 1, |a, b| a.1.total_cmp(&b.1));
+            neighbours.truncate(DIFFUSION_NEIGHBOURS);
+        }
+
+        let weights: Vec<f64> = neighbours.iter().map(|&(_, d)| 1.0 / d.sqrt().max(1.0)).collect();
+        let total: f64 = weights.iter().sum();
+        for (&(j, _), weight) in neighbours.iter().zip(&weights) {
+            let share = (weight / total) as f32;
+            for channel in 0..3 {
+                targets[j][channel] += error[channel] * share;
+            }
+        }
+    }
+    quantized
+}
+
+/// Restricts the colors of sampled color pixels to a palette, like `quantize_colors`.
+///
+/// Palettes given as a number of colors are computed from the sampled colors with `method`.
+pub fn quantize_color_coordinates(
+    coords: &[ColorCoordinate],
+    palette: &Palette,
+    method: PaletteMethod,
+    dither: bool,
+) -> Vec<ColorCoordinate> {
+    let positions: Vec<Coordinate> = coords.iter().map(|c| c.coord()).collect();
+    let colors: Vec<[u8; 3]> = coords
+        .iter()
+        .map(|c| {
+            let [r, g, b, _] = c.color().0;
+            [r, g, b]
+        })
+        .collect();
+
+    let palette = build_palette(&colors, palette, method);
+    let quantized = quantize_colors(&positions, &colors, &palette, dither);
+
+    coords
+        .iter()
+        .zip(quantized)
+        .map(|(coord, [r, g, b])| {
+            ColorCoordinate::new(coord.x(), coord.y(), image::Rgba([r, g, b, coord.color().0[3]]), coord.brightness())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colors_snap_to_the_nearest_palette_color() {
+        let coords = [Coordinate::new(0, 0), Coordinate::new(1, 0), Coordinate::new(2, 0)];
+        let colors = [[250, 10, 10], [20, 20, 230], [240, 240, 240]];
+        let palette = [[255, 0, 0], [0, 0, 255], [255, 255, 255]];
+        assert_eq!(quantize_colors(&coords, &colors, &palette, false), palette.to_vec());
+        assert_eq!(quantize_colors(&coords, &colors, &[], false), colors.to_vec());
+    }
+
+    #[test]
+    fn dithering_mixes_the_palette_colors_around_a_color() {
+        let coords: Vec<Coordinate> = (0..16).map(|x| Coordinate::new(x, 0)).collect();
+        let colors = vec![[128, 128, 128]; 16];
+        let palette = [[0, 0, 0], [255, 255, 255]];
+
+        let flat = quantize_colors(&coords, &colors, &palette, false);
+        assert!(flat.iter().all(|&color| color == flat[0]));
+
+        let dithered = quantize_colors(&coords, &colors, &palette, true);
+        assert!(dithered.contains(&[0, 0, 0]));
+        assert!(dithered.contains(&[255, 255, 255]));
+    }
+
+    #[test]
+    fn computed_palettes_find_each_cluster_of_colors() {
+        let colors: Vec<[u8; 3]> = (0..10)
+            .flat_map(|i| [[240 + i, 10, 10], [10, 10, 240 + i]])
+            .collect();
+        for method in [PaletteMethod::KMeans, PaletteMethod::MedianCut] {
+            let palette = build_palette(&colors, &Palette::Count(2), method);
+            assert_eq!(palette.len(), 2, "{:?}", method);
+            assert!(palette.iter().any(|c| c[0] > 200 && c[2] < 50), "{:?}: {:?}", method, palette);
+            assert!(palette.iter().any(|c| c[2] > 200 && c[0] < 50), "{:?}: {:?}", method, palette);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "every drone needs a color")]
+    fn colors_must_match_the_coordinates() {
+        let coords = [Coordinate::new(0, 0), Coordinate::new(1, 0)];
+        quantize_colors(&coords, &[[0, 0, 0]], &[[255, 255, 255]], true);
+    }
+}
@@ -0,0 +1,615 @@
+use std::borrow::Cow;
+
+use image::DynamicImage;
+use log::{debug, warn};
+use pyo3::prelude::*;
+
+use crate::{
+    adjust::{adjust_image, AdjustParams},
+    error::Error,
+    export::{encode_by_extension, normalize_coordinates, save_image, write_output},
+    geometry::{transform_image, TransformParams},
+    mask::{remove_background, MaskParams},
+    metrics::{evaluate_formation, FormationMetrics},
+    progress::{run_with_progress, Cancelled, Progress, Stage},
+    raster::{coordinates_to_image, SamplingType},
+    sampling::{farthest_point_sampling_with_progress, grid_sampling, weighted_sampling_with_progress},
+    resize::{resize_image, Resize, ResizeFilter, ResizeMode},
+    source::{CoordinateInput, ImageSource},
+    stats::{effective_threshold, RunStats},
+    thresholding::bradley_adaptive_threshold,
+    transformation::{image_to_weighted_coordinates, ImgType},
+    utils::{image_array, Coordinate, CoordinateOutput},
+    vector::is_svg,
+};
+
+/// The settings of the expensive steps, from loading the image to extracting its candidate pixels.
+///
+/// Shared by the Python `Pipeline` and the GUI, which also sets the crop, background removal and
+/// tonal adjustments applied before thresholding.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct PreprocessingParams {
+    pub img_type: ImgType,
+    pub resize: Option<(u32, u32)>,
+    pub resize_mode: ResizeMode,
+    pub resize_filter: ResizeFilter,
+    pub threshold: f32,
+    pub bradley: bool,
+    pub bradley_threshold: u8,
+    pub bradley_size: u32,
+    pub mask: MaskParams,
+    pub transform: TransformParams,
+    pub adjust: AdjustParams,
+}
+
+impl PreprocessingParams {
+    pub fn resize(&self) -> Option<Resize> {
+        self.resize.map(|size| Resize::new(size, self.resize_mode, self.resize_filter))
+    }
+}
+
+impl Default for PreprocessingParams {
+    fn default() -> Self {
+        Self {
+            img_type: ImgType::BlackOnWhite,
+            resize: Some((256, 256)),
+            resize_mode: ResizeMode::Fit,
+            resize_filter: ResizeFilter::Triangle,
+            threshold: 0.01,
+            bradley: false,
+            bradley_threshold: 15,
+            bradley_size: 16,
+            mask: MaskParams::default(),
+            transform: TransformParams::default(),
+            adjust: AdjustParams::default(),
+        }
+    }
+}
+
+/// The settings of the cheap sampling step.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct SamplingParams {
+    pub sample_count: u32,
+    pub sampling_type: SamplingType,
+    /// Minimum distance between two points placed by weighted sampling
+    pub min_spacing: f64,
+}
+
+impl Default for SamplingParams {
+    fn default() -> Self {
+        Self {
+            sample_count: 30,
+            sampling_type: SamplingType::Farthest,
+            min_spacing: 0.0,
+        }
+    }
+}
+
+/// The candidate pixels of the preprocessed image, with the intensity weight of each.
+#[derive(Clone)]
+pub struct Candidates {
+    pub output: CoordinateOutput,
+    pub weights: Vec<f32>,
+}
+
+/// Applies the steps that prepare the source image for thresholding: the crop and rotation,
+/// the background removal and the tonal adjustments.
+pub fn prepare_image<'a>(params: &PreprocessingParams, source_img: &'a DynamicImage) -> Cow<'a, DynamicImage> {
+    // using a CoW pointer to avoid cloning unless necessary down the line
+    let mut img_cow: Cow<'a, DynamicImage> = Cow::Borrowed(source_img);
+
+    // crop, rotation, flips and padding come first, so every later step sees the final framing
+    if !params.transform.is_identity() {
+        img_cow = Cow::Owned(transform_image(&img_cow, &params.transform));
+    }
+
+    // the background is then removed, while the image still has its colors and alpha
+    if params.mask.is_active(&img_cow) {
+        img_cow = Cow::Owned(remove_background(&img_cow, &params.mask));
+    }
+
+    if !params.adjust.is_identity() {
+        img_cow = Cow::Owned(adjust_image(&img_cow, &params.adjust));
+    }
+    img_cow
+}
+
+/// Runs the thresholding steps of `process_image_to_coordinates` on an image that has
+/// already been loaded, returning every candidate pixel before sampling along with its
+/// intensity weight.
+///
+/// The Python `Pipeline` and the GUI both extract their candidates through here, after
+/// `prepare_image`.
+#[allow(clippy::too_many_arguments)]
+pub fn extract_candidates(
+    source_img: DynamicImage,
+    img_type: ImgType,
+    resize: Option<Resize>,
+    threshold: f32,
+    bradley: bool,
+    bradley_threshold: u8,
+    bradley_size: u32,
+    progress: &Progress,
+) -> Result<(CoordinateOutput, Vec<f32>), Cancelled> {
+    progress.stage(Stage::Preprocessing)?;
+    let source_size = (source_img.width(), source_img.height());
+
+    // adding a bradley thresholding step 
+    // do we want to apply this before or after resizing the image?
+    // let's say after
+    let img = if bradley {
+        DynamicImage::ImageLuma8(bradley_adaptive_threshold(&source_img.to_luma8(), bradley_size, bradley_threshold))
+    } else { source_img };
+
+    let img = if let Some(resize) = &resize {
+        resize_image(img, resize)
+    } else { img };
+
+    let width = img.width();
+    let height = img.height();
+
+
+    debug!("Preprocessed the image to {}x{}", width, height);
+
+    // 2. Convert the brightest pixels to coordinates
+    // Let's get all pixels with any brightness for this example.
+    progress.stage(Stage::Extracting)?;
+    let (initial_coords, weights) = image_to_weighted_coordinates(&img, threshold, img_type);
+    debug!("Extracted {} candidate pixels", initial_coords.len());
+
+    let stats = RunStats::preprocessed(
+        source_size,
+        (width, height),
+        initial_coords.len(),
+        effective_threshold(&weights, img_type),
+    ).record_timings(progress);
+
+    Ok((CoordinateOutput::new(initial_coords, width, height).with_stats(stats), weights))
+}
+
+/// Runs the sampling step of `process_image_to_coordinates` on the candidate pixels
+/// returned by `extract_candidates`.
+///
+/// The Python `Pipeline` and the GUI both sample their candidates through here.
+pub fn sample_candidates(
+    initial_coords: &[Coordinate],
+    weights: &[f32],
+    n: u32,
+    sample: SamplingType,
+    min_spacing: f64,
+    progress: &Progress,
+) -> Result<Vec<Coordinate>, Error> {
+    if initial_coords.is_empty() {
+        return Err(Error::EmptyResult(
+            "No candidate pixels were found in the image. Try a different 'threshold' or 'img_type'.".to_string()
+        ))
+    }
+    progress.stage(Stage::Sampling)?;
+
+    let sampled_coords = match sample {
+        SamplingType::Grid if n == 0 => {
+            return Err(Error::invalid_parameter("'n' is the size of the grid cells with 'grid' sampling, and must be at least 1."))
+        },
+        SamplingType::Grid => {
+            grid_sampling(initial_coords, n)
+        },
+        SamplingType::Farthest => {
+            farthest_point_sampling_with_progress(initial_coords, n, progress)?
+        },
+        SamplingType::Weighted => {
+            weighted_sampling_with_progress(initial_coords, weights, n, min_spacing, progress)?
+        },
+        SamplingType::Path => {
+            return Err(Error::invalid_parameter("'path' sampling is only available for SVG files."))
+        },
+        SamplingType::Color => {
+            return Err(Error::invalid_parameter("'color' sampling is only available for color images."))
+        }
+    };
+    if sample != SamplingType::Grid && sampled_coords.len() < n as usize {
+        warn!(
+            "Only {} of the {} requested points could be placed, from {} candidate pixels",
+            sampled_coords.len(), n, initial_coords.len()
+        );
+    }
+
+    Ok(sampled_coords)
+}
+
+/// A black and white pipeline that keeps the result of each stage between calls.
+///
+/// Like the GUI, each stage is cached along with the settings it ran with, and only reruns
+/// once those settings change: the source image is loaded once, the candidate pixels are
+/// extracted again only when a preprocessing setting changes, and sampling reuses them.
+///
+/// Arguments:
+///     input_path: str | bytes | numpy.ndarray | PIL.Image
+///         the source image, in any form accepted by `process_image_to_coordinates`
+///
+/// The other arguments are the preprocessing settings of `process_image_to_coordinates`, which
+/// are also attributes of the pipeline that can be changed afterwards.
+#[pyclass(name="Pipeline", module="raster_drone")]
+pub struct Pipeline {
+    source: ImageSource,
+    params: PreprocessingParams,
+    /// The loaded source image, along with the size it was rasterized at for SVG files
+    loaded: Option<(Option<(u32, u32)>, DynamicImage)>,
+    candidates: Option<(PreprocessingParams, Candidates)>,
+    sample: Option<(PreprocessingParams, SamplingParams, CoordinateOutput)>,
+}
+
+impl Pipeline {
+    /// Runs the preprocessing stages that are out of date with the current settings.
+    fn preprocess(&mut self, progress: &Progress) -> Result<&Candidates, Error> {
+        let params = self.params;
+        // only SVG files are loaded differently depending on the size
+        let load_size = match self.source.path() {
+            Some(path) if is_svg(path) => params.resize,
+            _ => None,
+        };
+        if self.loaded.as_ref().is_none_or(|(size, _)| *size != load_size) {
+            progress.stage(Stage::Loading)?;
+            let img = self.source.load(load_size)?;
+            self.loaded = Some((load_size, img));
+            self.candidates = None;
+        }
+        if self.candidates.as_ref().is_none_or(|(cached, _)| *cached != params) {
+            let (_, img) = self.loaded.as_ref().expect("the source image was just loaded");
+            let (output, weights) = extract_candidates(
+                prepare_image(&params, img).into_owned(),
+                params.img_type,
+                params.resize(),
+                params.threshold,
+                params.bradley,
+                params.bradley_threshold,
+                params.bradley_size,
+                progress,
+            )?;
+            self.candidates = Some((params, Candidates { output, weights }));
+        }
+        Ok(&self.candidates.as_ref().expect("the candidates were just extracted").1)
+    }
+
+    /// Samples the candidates, reusing the last sample if nothing changed since.
+    fn sample_cached(&mut self, sampling: SamplingParams, progress: &Progress) -> Result<CoordinateOutput, Error> {
+        let params = self.params;
+        if let Some((cached_params, cached_sampling, output)) = &self.sample {
+            if *cached_params == params && *cached_sampling == sampling {
+                return Ok(output.clone());
+            }
+        }
+
+        let candidates = self.preprocess(progress)?;
+        let sampled_coords = sample_candidates(
+            &candidates.output.coords(),
+            &candidates.weights,
+            sampling.sample_count,
+            sampling.sampling_type,
+            sampling.min_spacing,
+            progress,
+        )?;
+        let stats = candidates.output.stats().unwrap_or_default()
+            .sampled(&candidates.output.coords(), &sampled_coords)
+            .record_timings(progress);
+        let output = CoordinateOutput::new(sampled_coords, candidates.output.width(), candidates.output.height())
+            .with_stats(stats);
+        self.sample = Some((params, sampling, output.clone()));
+        Ok(output)
+    }
+}
+
+#[pymethods]
+impl Pipeline {
+    #[new]
+    #[pyo3(signature=(input_path, img_type=ImgType::BlackOnWhite, resize=Some((256, 256)), threshold=0.01, bradley=false, bradley_threshold=15, bradley_size=16, alpha_threshold=128, chroma_key=None, chroma_tolerance=20.0, remove_border_background=false, border_tolerance=10.0, resize_mode=ResizeMode::Fit, resize_filter=ResizeFilter::Triangle))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        input_path: ImageSource,
+        img_type: ImgType,
+        resize: Option<(u32, u32)>,
+        threshold: f32,
+        bradley: bool,
+        bradley_threshold: u8,
+        bradley_size: u32,
+        alpha_threshold: u8,
+        chroma_key: Option<(u8, u8, u8)>,
+        chroma_tolerance: f32,
+        remove_border_background: bool,
+        border_tolerance: f32,
+        resize_mode: ResizeMode,
+        resize_filter: ResizeFilter,
+    ) -> Self {
+        Self {
+            source: input_path,
+            params: PreprocessingParams {
+                img_type,
+                resize,
+                resize_mode,
+                resize_filter,
+                threshold,
+                bradley,
+                bradley_threshold,
+                bradley_size,
+                mask: MaskParams::new(alpha_threshold, chroma_key, chroma_tolerance, remove_border_background, border_tolerance),
+                ..PreprocessingParams::default()
+            },
+            loaded: None,
+            candidates: None,
+            sample: None,
+        }
+    }
+
+    /// Runs the preprocessing stages now, rather than on the first call that needs them
+    fn run(&mut self, py: Python<'_>) -> PyResult<()> {
+        run_with_progress(py, None, |progress| self.preprocess(progress).map(|_| ()))
+    }
+
+    /// Samples the candidate pixels of the image
+    ///
+    /// Only the sampling runs again between calls, unless a preprocessing setting changed since.
+    ///
+    /// Arguments:
+    ///     n: u32
+    ///         number of pixels to select
+    ///     method: str
+    ///         selecting type of sampling, either 'grid', 'farthest' or 'weighted'. Defaults to 'farthest'
+    ///     min_spacing: f64
+    ///         minimum distance between two selected pixels when using 'weighted' sampling. Defaults to 0.0
+    ///     progress: callable
+    ///         called with the (done, total) counts of the sampling step while it runs. Raising an exception from it stops the run. Defaults to None
+    ///
+    /// Returns:
+    ///     coordinates: CoordinateOutput
+    ///         the coordinates of each sampled pixel
+    #[pyo3(signature=(n, method=SamplingType::Farthest, min_spacing=0.0, progress=None))]
+    fn sample(
+        &mut self,
+        py: Python<'_>,
+        n: u32,
+        method: SamplingType,
+        min_spacing: f64,
+        progress: Option<Bound<'_, PyAny>>,
+    ) -> PyResult<CoordinateOutput> {
+        let sampling = SamplingParams {
+            sample_count: n,
+            sampling_type: method,
+            min_spacing,
+        };
+        run_with_progress(py, progress.as_ref(), |progress| self.sample_cached(sampling, progress))
+    }
+
+    /// Every candidate pixel of the preprocessed image, before sampling
+    fn candidates(&mut self, py: Python<'_>) -> PyResult<CoordinateOutput> {
+        run_with_progress(py, None, |progress| self.preprocess(progress).map(|candidates| candidates.output.clone()))
+    }
+
+    /// The candidate pixels as an (H, W) numpy array of uint8, holding 255 at each candidate and 0 elsewhere
+    fn candidate_mask<'py>(&mut self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let mask = run_with_progress(py, None, |progress| {
+            let candidates = self.preprocess(progress)?;
+            let output = &candidates.output;
+            Ok(coordinates_to_image(output.width(), output.height(), &output.coords()))
+        })?;
+        image_array(py, &DynamicImage::ImageLuma8(mask))
+    }
+
+    /// Scores a formation against the candidate pixels of the image, like `evaluate_formation`
+    ///
+    /// Arguments:
+    ///     coordinates: numpy.ndarray | list[(u32, u32)] | CoordinateOutput
+    ///         the (x, y) coordinates of the drones, such as returned by `sample`
+    ///     light_radius: f32
+    ///         radius of the rendered lights, in pixels. Defaults to None, which uses the spacing of the drones if they were spread evenly over the candidates
+    ///
+    /// Returns:
+    ///     metrics: FormationMetrics
+    ///         the scores of the formation
+    #[pyo3(signature=(coordinates, light_radius=None))]
+    fn evaluate(
+        &mut self,
+        py: Python<'_>,
+        coordinates: CoordinateInput,
+        light_radius: Option<f32>,
+    ) -> PyResult<FormationMetrics> {
+        if light_radius.is_some_and(|radius| radius <= 0.0) {
+            return Err(Error::invalid_parameter("'light_radius' must be greater than 0.").into())
+        }
+        run_with_progress(py, None, |progress| {
+            let output = &self.preprocess(progress)?.output;
+            evaluate_formation(&output.coords(), &coordinates.0, output.width(), output.height(), light_radius)
+                .ok_or_else(|| Error::EmptyResult("Both the candidates and the coordinates are needed to evaluate a formation.".to_string()))
+        })
+    }
+
+    /// Samples the candidate pixels and saves the result
+    ///
+    /// The file format is chosen from the extension of `output_path`: '.csv' for Skybrush Studio,
+    /// '.glb' for a binary glTF and '.ply' for a point cloud, while image extensions such as '.png'
+    /// save the sampled pixels as an image like `process_image`
+    ///
+    /// Arguments:
+    ///     output_path: str
+    ///         path where the result will be saved. Note that, if the intermediate directories do not exist, they will be created
+    ///     n: u32
+    ///         number of pixels to select
+    ///     method: str
+    ///         selecting type of sampling, either 'grid', 'farthest' or 'weighted'. Defaults to 'farthest'
+    ///     min_spacing: f64
+    ///         minimum distance between two selected pixels when using 'weighted' sampling. Defaults to 0.0
+    ///     max_dim: float
+    ///         size of the largest dimension of the formation, in meters. Defaults to 20.0
+    ///     progress: callable
+    ///         called with the (done, total) counts of the sampling step while it runs. Raising an exception from it stops the run. Defaults to None
+    #[pyo3(signature=(output_path, n, method=SamplingType::Farthest, min_spacing=0.0, max_dim=20.0, progress=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn export(
+        &mut self,
+        py: Python<'_>,
+        output_path: &str,
+        n: u32,
+        method: SamplingType,
+        min_spacing: f64,
+        max_dim: f64,
+        progress: Option<Bound<'_, PyAny>>,
+    ) -> PyResult<()> {
+        let sampling = SamplingParams {
+            sample_count: n,
+            sampling_type: method,
+            min_spacing,
+        };
+        run_with_progress(py, progress.as_ref(), |progress| {
+            let output = self.sample_cached(sampling, progress)?;
+            let path = std::path::Path::new(output_path);
+            let is_formation = path.extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| matches!(ext.to_lowercase().as_str(), "csv" | "glb" | "ply"));
+
+            if is_formation {
+                let normalized = normalize_coordinates(&output.coords(), max_dim).map_err(|cause| Error::export(path, cause))?;
+                let file_data = encode_by_extension(path, normalized, &[]).map_err(|cause| Error::export(path, cause))?;
+                write_output(path, &file_data)
+            } else {
+                let output_img = coordinates_to_image(output.width(), output.height(), &output.coords());
+                save_image(path, &output_img.into())
+            }
+        })
+    }
+
+    /// Replaces the source image, in any form accepted by `process_image_to_coordinates`
+    #[setter]
+    fn set_source(&mut self, input_path: ImageSource) {
+        self.source = input_path;
+        self.loaded = None;
+        self.candidates = None;
+        self.sample = None;
+    }
+
+    #[getter]
+    fn img_type(&self) -> &'static str {
+        match self.params.img_type {
+            ImgType::BlackOnWhite => "black_on_white",
+            ImgType::WhiteOnBlack => "white_on_black",
+        }
+    }
+    #[setter]
+    fn set_img_type(&mut self, img_type: ImgType) {
+        self.params.img_type = img_type;
+    }
+
+    #[getter]
+    fn resize(&self) -> Option<(u32, u32)> {
+        self.params.resize
+    }
+    #[setter]
+    fn set_resize(&mut self, resize: Option<(u32, u32)>) {
+        self.params.resize = resize;
+    }
+
+    #[getter]
+    fn resize_mode(&self) -> &'static str {
+        match self.params.resize_mode {
+            ResizeMode::Fit => "fit",
+            ResizeMode::Upscale => "upscale",
+            ResizeMode::Fill => "fill",
+            ResizeMode::Exact => "exact",
+        }
+    }
+    #[setter]
+    fn set_resize_mode(&mut self, resize_mode: ResizeMode) {
+        self.params.resize_mode = resize_mode;
+    }
+
+    #[getter]
+    fn resize_filter(&self) -> &'static str {
+        match self.params.resize_filter {
+            ResizeFilter::Nearest => "nearest",
+            ResizeFilter::Triangle => "triangle",
+            ResizeFilter::Lanczos => "lanczos",
+        }
+    }
+    #[setter]
+    fn set_resize_filter(&mut self, resize_filter: ResizeFilter) {
+        self.params.resize_filter = resize_filter;
+    }
+
+    #[getter]
+    fn threshold(&self) -> f32 {
+        self.params.threshold
+    }
+    #[setter]
+    fn set_threshold(&mut self, threshold: f32) {
+        self.params.threshold = threshold;
+    }
+
+    #[getter]
+    fn bradley(&self) -> bool {
+        self.params.bradley
+    }
+    #[setter]
+    fn set_bradley(&mut self, bradley: bool) {
+        self.params.bradley = bradley;
+    }
+
+    #[getter]
+    fn bradley_threshold(&self) -> u8 {
+        self.params.bradley_threshold
+    }
+    #[setter]
+    fn set_bradley_threshold(&mut self, bradley_threshold: u8) {
+        self.params.bradley_threshold = bradley_threshold;
+    }
+
+    #[getter]
+    fn bradley_size(&self) -> u32 {
+        self.params.bradley_size
+    }
+    #[setter]
+    fn set_bradley_size(&mut self, bradley_size: u32) {
+        self.params.bradley_size = bradley_size;
+    }
+
+    #[getter]
+    fn alpha_threshold(&self) -> u8 {
+        self.params.mask.alpha_threshold
+    }
+    #[setter]
+    fn set_alpha_threshold(&mut self, alpha_threshold: u8) {
+        self.params.mask.alpha_threshold = alpha_threshold;
+    }
+
+    #[getter]
+    fn chroma_key(&self) -> Option<(u8, u8, u8)> {
+        self.params.mask.chroma_key.map(|[r, g, b]| (r, g, b))
+    }
+    #[setter]
+    fn set_chroma_key(&mut self, chroma_key: Option<(u8, u8, u8)>) {
+        self.params.mask.chroma_key = chroma_key.map(|(r, g, b)| [r, g, b]);
+    }
+
+    #[getter]
+    fn chroma_tolerance(&self) -> f32 {
+        self.params.mask.chroma_tolerance
+    }
+    #[setter]
+    fn set_chroma_tolerance(&mut self, chroma_tolerance: f32) {
+        self.params.mask.chroma_tolerance = chroma_tolerance;
+    }
+
+    #[getter]
+    fn remove_border_background(&self) -> bool {
+        self.params.mask.flood_fill
+    }
+    #[setter]
+    fn set_remove_border_background(&mut self, remove_border_background: bool) {
+        self.params.mask.flood_fill = remove_border_background;
+    }
+
+    #[getter]
+    fn border_tolerance(&self) -> f32 {
+        self.params.mask.flood_tolerance
+    }
+    #[setter]
+    fn set_border_tolerance(&mut self, border_tolerance: f32) {
+        self.params.mask.flood_tolerance = border_tolerance;
+    }
+}
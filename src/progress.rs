@@ -0,0 +1,260 @@
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::debug;
+
+use pyo3::prelude::*;
+
+use crate::{error::Error, logging::refresh_log_level};
+
+/// How often the Python callback is called and the signal handlers run during a long run.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A step of a run, as reported to its `Progress`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Stage {
+    Loading,
+    Preprocessing,
+    Extracting,
+    Sampling,
+    /// Trying combinations of settings, for `auto_tune`
+    Tuning,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match self {
+            Self::Loading => "Loading",
+            Self::Preprocessing => "Preprocessing",
+            Self::Extracting => "Extracting candidates",
+            Self::Sampling => "Sampling",
+            Self::Tuning => "Trying settings",
+        };
+        write!(f, "{}", label)
+    }
+}
+
+/// The run was cancelled through its `Progress` before it could finish.
+#[derive(Debug, Clone, Copy)]
+pub struct Cancelled;
+
+/// Tracks how far a long run has got, and lets another thread cancel it.
+///
+/// The run reports each stage it enters and how far it is through that stage. Both calls
+/// fail with `Cancelled` once `cancel` has been called, so the run stops at its next step.
+/// The progress can be read from another thread while the run goes on, which is how the GUI
+/// draws its progress bar and the Python API calls its callback. The time spent in each
+/// stage is logged once the stage is over.
+#[derive(Debug, Default)]
+pub struct Progress {
+    cancelled: Arc<AtomicBool>,
+    /// The current stage, along with when it started
+    stage: Mutex<Option<(Stage, Instant)>>,
+    /// How long each finished stage took, in order
+    timings: Mutex<Vec<(Stage, Duration)>>,
+    done: AtomicU64,
+    total: AtomicU64,
+}
+
+impl Progress {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A progress for one part of a larger run, such as one image of a batch, which is
+    /// cancelled along with this one but keeps its own counts.
+    pub fn subtask(&self) -> Self {
+        Self {
+            cancelled: Arc::clone(&self.cancelled),
+            stage: Mutex::new(None),
+            timings: Mutex::new(Vec::new()),
+            done: AtomicU64::new(0),
+            total: AtomicU64::new(0),
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Enters a new stage, whose own progress starts from zero.
+    pub fn stage(&self, stage: Stage) -> Result<(), Cancelled> {
+        let previous = self.stage.lock().unwrap_or_else(|e| e.into_inner()).replace((stage, Instant::now()));
+        if let Some((previous, started)) = previous {
+            let elapsed = started.elapsed();
+            debug!("{} took {:.1?}", previous, elapsed);
+            self.timings.lock().unwrap_or_else(|e| e.into_inner()).push((previous, elapsed));
+        }
+        self.done.store(0, Ordering::Relaxed);
+        self.total.store(0, Ordering::Relaxed);
+        self.check()
+    }
+
+    /// Reports that `done` of the `total` steps of the current stage are finished.
+    pub fn report(&self, done: u64, total: u64) -> Result<(), Cancelled> {
+        self.total.store(total, Ordering::Relaxed);
+        self.done.store(done, Ordering::Relaxed);
+        self.check()
+    }
+
+    /// Reports that one more step of the current stage is finished.
+    pub fn advance(&self) -> Result<(), Cancelled> {
+        self.done.fetch_add(1, Ordering::Relaxed);
+        self.check()
+    }
+
+    /// The current stage, if the run has started.
+    pub fn current_stage(&self) -> Option<Stage> {
+        self.stage.lock().unwrap_or_else(|e| e.into_inner()).map(|(stage, _)| stage)
+    }
+
+    /// How long each stage took so far, in order, including the current stage up to now.
+    pub fn timings(&self) -> Vec<(Stage, Duration)> {
+        let mut timings = self.timings.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        if let Some((stage, started)) = *self.stage.lock().unwrap_or_else(|e| e.into_inner()) {
+            timings.push((stage, started.elapsed()));
+        }
+        timings
+    }
+
+    /// The number of finished steps of the current stage, and the total number of steps.
+    /// The total is 0 while a stage hasn't reported how long it is.
+    pub fn counts(&self) -> (u64, u64) {
+        (self.done.load(Ordering::Relaxed), self.total.load(Ordering::Relaxed))
+    }
+
+    fn check(&self) -> Result<(), Cancelled> {
+        if self.is_cancelled() {
+            Err(Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        if let Some((stage, started)) = self.stage.get_mut().unwrap_or_else(|e| e.into_inner()).take() {
+            debug!("{} took {:.1?}", stage, started.elapsed());
+        }
+    }
+}
+
+/// Runs `work` with the GIL released, while keeping the calling thread responsive to Python.
+///
+/// The work runs on a separate thread, while the calling thread wakes up regularly to run
+/// the Python signal handlers, so that Ctrl-C raises `KeyboardInterrupt` instead of being
+/// ignored until the end, and to call `callback` with the `(done, total)` counts of the
+/// progress whenever they changed. An exception raised by either cancels the work, and is
+/// raised once the work has stopped.
+pub fn run_with_progress<T: Send>(
+    py: Python<'_>,
+    callback: Option<&Bound<'_, PyAny>>,
+    work: impl FnOnce(&Progress) -> Result<T, Error> + Send,
+) -> PyResult<T> {
+    if callback.is_some_and(|callback| !callback.is_callable()) {
+        return Err(Error::invalid_parameter("'progress' must be a callable taking the (done, total) counts.").into())
+    }
+    refresh_log_level(py);
+    let callback = callback.map(|callback| callback.clone().unbind());
+    let progress = Progress::new();
+    let mut interrupt: Option<PyErr> = None;
+    let mut reported = None;
+
+    let result = py.allow_threads(|| thread::scope(|scope| {
+        let caller = thread::current();
+        let progress = &progress;
+        let worker = scope.spawn(move || {
+            let result = work(progress);
+            caller.unpark();
+            result
+        });
+        while !worker.is_finished() {
+            thread::park_timeout(POLL_INTERVAL);
+            if !worker.is_finished() && interrupt.is_none() {
+                if let Err(e) = Python::with_gil(|py| poll(py, progress, callback.as_ref(), &mut reported)) {
+                    progress.cancel();
+                    interrupt = Some(e);
+                }
+            }
+        }
+        worker.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    }));
+
+    if let Some(e) = interrupt {
+        return Err(e)
+    }
+    let output = result?;
+    // the last counts, which the work may have reached between two polls
+    poll(py, &progress, callback.as_ref(), &mut reported)?;
+    Ok(output)
+}
+
+/// Runs the signal handlers, then calls `callback` if the counts changed since `reported`.
+fn poll(
+    py: Python<'_>,
+    progress: &Progress,
+    callback: Option<&Py<PyAny>>,
+    reported: &mut Option<(u64, u64)>,
+) -> PyResult<()> {
+    py.check_signals()?;
+    let counts = progress.counts();
+    match callback {
+        Some(callback) if counts.1 > 0 && *reported != Some(counts) => {
+            *reported = Some(counts);
+            callback.call1(py, counts).map(drop)
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pyo3::exceptions::{PyKeyboardInterrupt, PyZeroDivisionError};
+
+    use super::*;
+    use crate::{sampling::farthest_point_sampling_with_progress, utils::Coordinate};
+
+    #[test]
+    fn cancelled_runs_stop_with_the_cancelled_error() {
+        let pixels: Vec<Coordinate> = (0..100).map(|x| Coordinate::new(x, 0)).collect();
+        let progress = Progress::new();
+        let subtask = progress.subtask();
+        progress.cancel();
+
+        // a subtask is cancelled along with its parent, and the error carries through `?`
+        let run = || -> Result<Vec<Coordinate>, Error> { Ok(farthest_point_sampling_with_progress(&pixels, 10, &subtask)?) };
+        assert!(matches!(run(), Err(Error::Cancelled)));
+        assert!(matches!(progress.stage(Stage::Sampling), Err(Cancelled)));
+
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| assert!(PyErr::from(Error::Cancelled).is_instance_of::<PyKeyboardInterrupt>(py)));
+    }
+
+    #[test]
+    fn callback_errors_cancel_the_run_and_are_raised() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let callback = py.eval(c"lambda done, total: 1 / 0", None, None).unwrap();
+            let result = run_with_progress(py, Some(&callback), |progress| -> Result<(), Error> {
+                // runs until the failing callback cancels it
+                loop {
+                    progress.report(1, 10)?;
+                    thread::sleep(Duration::from_millis(1));
+                }
+            });
+            assert!(result.unwrap_err().is_instance_of::<PyZeroDivisionError>(py));
+        });
+    }
+}
@@ -1,7 +1,7 @@
-use pyo3::{exceptions::PyValueError, prelude::*};
+use pyo3::prelude::*;
 use image::{GrayImage, Luma, Rgba, RgbaImage};
 
-use crate::{transformation::ColorCoordinate, utils::Coordinate};
+use crate::{error::Error, transformation::ColorCoordinate, utils::Coordinate};
 
 /// Creates a new black and white image from a list of coordinates.
 ///
@@ -81,6 +81,25 @@ pub fn coordinates_to_color_image(
 pub enum SamplingType {
     Grid,
     Farthest,
+    /// Places points at equal spacing along the paths of an SVG, without rasterizing it
+    Path,
+    /// Places points with a density proportional to the intensity of each pixel
+    Weighted,
+    /// Spreads points over both position and color, so that every distinct color region is represented
+    Color,
+}
+
+impl SamplingType {
+    /// The name of the sampling type in the Python API
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Grid => "grid",
+            Self::Farthest => "farthest",
+            Self::Path => "path",
+            Self::Weighted => "weighted",
+            Self::Color => "color",
+        }
+    }
 }
 
 impl FromPyObject<'_> for SamplingType {
@@ -89,10 +108,11 @@ impl FromPyObject<'_> for SamplingType {
             match s.to_lowercase().as_str() {
                 "grid" => Ok(Self::Grid),
                 "farthest" => Ok(Self::Farthest),
+                "path" => Ok(Self::Path),
+                "weighted" => Ok(Self::Weighted),
+                "color" => Ok(Self::Color),
                 _ => Err(
-                    PyValueError::new_err(
-                        "The valid values for `sampling` include 'grid' and 'farthest'."
-                    )
+                    Error::invalid_parameter("The valid values for `sampling` include 'grid', 'farthest', 'path', 'weighted' and 'color'.").into()
                 )
             }
         } else {
@@ -0,0 +1,135 @@
+use image::{imageops::FilterType, DynamicImage};
+use pyo3::prelude::*;
+
+use crate::error::Error;
+
+/// How an image is brought to the requested size.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ResizeMode {
+    /// Fits within the size, preserving aspect ratio. Smaller images are left as they are
+    Fit,
+    /// Fits within the size, preserving aspect ratio, enlarging smaller images as needed
+    Upscale,
+    /// Covers the whole size, preserving aspect ratio, and crops the overflow around the center
+    Fill,
+    /// Stretches to exactly the size, ignoring aspect ratio
+    Exact,
+}
+
+impl FromPyObject<'_> for ResizeMode {
+    fn extract_bound(ob: &Bound<'_, PyAny>) -> PyResult<Self> {
+        if let Ok(s) = ob.extract::<&str>() {
+            match s.to_lowercase().as_str() {
+                "fit" => Ok(Self::Fit),
+                "upscale" => Ok(Self::Upscale),
+                "fill" => Ok(Self::Fill),
+                "exact" => Ok(Self::Exact),
+                _ => Err(Error::invalid_parameter("The valid values for 'resize_mode' are 'fit', 'upscale', 'fill' and 'exact'.").into())
+            }
+        } else {
+            Ok(Self::Fit)
+        }
+    }
+}
+
+/// The resampling filter used when resizing.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ResizeFilter {
+    /// Keeps hard pixel edges, best for pixel art and small logos being enlarged
+    Nearest,
+    /// Bilinear filtering, a fast all-rounder
+    Triangle,
+    /// Sharpest result, at the cost of some ringing around hard edges
+    Lanczos,
+}
+
+impl FromPyObject<'_> for ResizeFilter {
+    fn extract_bound(ob: &Bound<'_, PyAny>) -> PyResult<Self> {
+        if let Ok(s) = ob.extract::<&str>() {
+            match s.to_lowercase().as_str() {
+                "nearest" => Ok(Self::Nearest),
+                "triangle" => Ok(Self::Triangle),
+                "lanczos" => Ok(Self::Lanczos),
+                _ => Err(Error::invalid_parameter("The valid values for 'resize_filter' are 'nearest', 'triangle' and 'lanczos'.").into())
+            }
+        } else {
+            Ok(Self::Triangle)
+        }
+    }
+}
+
+impl ResizeFilter {
+    fn filter_type(self) -> FilterType {
+        match self {
+            Self::Nearest => FilterType::Nearest,
+            Self::Triangle => FilterType::Triangle,
+            Self::Lanczos => FilterType::Lanczos3,
+        }
+    }
+}
+
+/// The size an image is resized to before thresholding, and how.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Resize {
+    pub width: u32,
+    pub height: u32,
+    pub mode: ResizeMode,
+    pub filter: ResizeFilter,
+}
+
+impl Resize {
+    pub fn new((width, height): (u32, u32), mode: ResizeMode, filter: ResizeFilter) -> Self {
+        Self {
+            width,
+            height,
+            mode,
+            filter,
+        }
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+}
+
+/// Resizes an image according to `resize`.
+///
+/// The candidate pixel density of the pipeline follows the size of the resized image,
+/// so enlarging small images gives the sampling more pixels to choose from.
+pub fn resize_image(img: DynamicImage, resize: &Resize) -> DynamicImage {
+    let (width, height) = (resize.width.max(1), resize.height.max(1));
+    let filter = resize.filter.filter_type();
+    match resize.mode {
+        ResizeMode::Fit if img.width() <= width && img.height() <= height => img,
+        ResizeMode::Fit | ResizeMode::Upscale => img.resize(width, height, filter),
+        ResizeMode::Fill => img.resize_to_fill(width, height, filter),
+        ResizeMode::Exact => img.resize_exact(width, height, filter),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::RgbImage;
+
+    use super::*;
+
+    fn resized(mode: ResizeMode, (width, height): (u32, u32)) -> (u32, u32) {
+        let img = DynamicImage::ImageRgb8(RgbImage::new(40, 20));
+        let img = resize_image(img, &Resize::new((width, height), mode, ResizeFilter::Nearest));
+        (img.width(), img.height())
+    }
+
+    #[test]
+    fn each_mode_gives_its_own_size() {
+        // fit only ever shrinks, keeping the aspect ratio
+        assert_eq!(resized(ResizeMode::Fit, (100, 100)), (40, 20));
+        assert_eq!(resized(ResizeMode::Fit, (20, 20)), (20, 10));
+        // upscale also enlarges
+        assert_eq!(resized(ResizeMode::Upscale, (100, 100)), (100, 50));
+        assert_eq!(resized(ResizeMode::Upscale, (20, 20)), (20, 10));
+        // fill covers the size and crops the overflow
+        assert_eq!(resized(ResizeMode::Fill, (30, 30)), (30, 30));
+        // exact ignores the aspect ratio
+        assert_eq!(resized(ResizeMode::Exact, (10, 30)), (10, 30));
+    }
+}
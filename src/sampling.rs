@@ -1,5 +1,7 @@
+use crate::color::{lab_distance_squared, rgb_to_lab};
+use crate::progress::{Cancelled, Progress};
 use crate::transformation::ColorCoordinate;
-use crate::utils::Coordinate;
+use crate::utils::{Coordinate, Coordinate3D};
 use std::collections::HashMap;
 use std::cmp::Ordering;
 
@@ -18,59 +20,17 @@ pub fn farthest_point_sampling(
     pixels: &[Coordinate], 
     n: u32
 ) -> Vec<Coordinate> {
-    let n = n as usize;
-    let m = pixels.len();
-
-    // --- Handle Edge Cases ---
-    if n == 0 || m == 0 {
-        return Vec::new();
-    }
-    // If we need to select all or more pixels than are available, just return a copy.
-    if n >= m {
-        return pixels.to_vec();
-    }
-
-    // --- Initialization ---
-    let mut selected_pixels = Vec::with_capacity(n);
-    // This will store the minimum *squared* distance from each pixel to the selected set.
-    let mut min_sq_distances = vec![f64::INFINITY; m];
-
-    // --- Step 1: Select the starting point ---
-    // As requested, we'll start with the last pixel in the input slice.
-    let first_pixel_index = m - 1;
-    let mut last_selected_pixel = pixels[first_pixel_index];
-
-    selected_pixels.push(last_selected_pixel);
-    // Mark this pixel as "selected" by setting its distance to 0.
-    min_sq_distances[first_pixel_index] = 0.0;
-
-    // --- Step 2: Iteratively select the remaining n-1 points ---
-    for _ in 1..n {
-        // Update the minimum distances for all points based on the *last* point we added.
-        for (i, p) in pixels.iter().enumerate() {
-            // We only need to check points that haven't been selected yet.
-            if min_sq_distances[i] > 0.0 {
-                let sq_dist = p.distance_squared(&last_selected_pixel);
-                // If the distance to our newest point is smaller than the previous minimum, update it.
-                min_sq_distances[i] = min_sq_distances[i].min(sq_dist);
-            }
-        }
-
-        // Find the pixel that is now farthest from the entire selected set.
-        // We do this by finding the maximum value in our `min_sq_distances` array.
-        let (farthest_index, _) = min_sq_distances
-            .iter()
-            .enumerate()
-            .max_by(|(_, &a), (_, &b)| a.partial_cmp(&b).unwrap_or(Ordering::Equal))
-            .expect("Distances should have at least one valid value");
-
-        // Add the new farthest pixel to our selection.
-        last_selected_pixel = pixels[farthest_index];
-        selected_pixels.push(last_selected_pixel);
-        min_sq_distances[farthest_index] = 0.0; // And mark it as selected.
-    }
+    farthest_point_sampling_with_progress(pixels, n, &Progress::new()).expect("an untracked run is never cancelled")
+}
 
-    selected_pixels
+/// Selects `n` points like `farthest_point_sampling`, reporting each selected point to
+/// `progress` and stopping early once it is cancelled.
+pub fn farthest_point_sampling_with_progress(
+    pixels: &[Coordinate],
+    n: u32,
+    progress: &Progress,
+) -> Result<Vec<Coordinate>, Cancelled> {
+    farthest_point_sampling_by(pixels, n, Coordinate::distance_squared, progress)
 }
 
 /// Selects points using a grid-based (voxel hashing) approach.
@@ -115,70 +75,392 @@ pub fn grid_sampling(
     grid.into_values().collect()
 }
 
+/// Selects `n` colored points by spatial Farthest Point Sampling, keeping the color of each.
+///
+/// This is `color_farthest_point_sampling` with a `color_weight` of `0.0`, so the colors don't
+/// affect which points are selected.
 pub fn color_albedo_sampling(pixels: &[ColorCoordinate], n: u32) -> Vec<ColorCoordinate> {
+    color_albedo_sampling_with_progress(pixels, n, &Progress::new()).expect("an untracked run is never cancelled")
+}
+
+/// Selects `n` points like `color_albedo_sampling`, reporting each selected point to
+/// `progress` and stopping early once it is cancelled.
+pub fn color_albedo_sampling_with_progress(
+    pixels: &[ColorCoordinate],
+    n: u32,
+    progress: &Progress,
+) -> Result<Vec<ColorCoordinate>, Cancelled> {
+    color_farthest_point_sampling_with_progress(pixels, n, 0.0, progress)
+}
+
+/// Selects `n` points from a set of 3D points using the Farthest Point Sampling algorithm.
+///
+/// This is the 3D counterpart of `farthest_point_sampling`, and is likewise O(n * m).
+///
+/// # Arguments
+/// * `points` - A slice of `Coordinate3D` points to sample from.
+/// * `n` - The number of points to select.
+///
+/// # Returns
+/// A `Vec<Coordinate3D>` containing the `n` selected points.
+pub fn farthest_point_sampling_3d(
+    points: &[Coordinate3D],
+    n: u32
+) -> Vec<Coordinate3D> {
+    farthest_point_sampling_3d_with_progress(points, n, &Progress::new()).expect("an untracked run is never cancelled")
+}
 
-    // take this bundled coordinate, color, and brightness data
-    // and use it to select a subsample
+/// Selects `n` points like `farthest_point_sampling_3d`, reporting each selected point to
+/// `progress` and stopping early once it is cancelled.
+pub fn farthest_point_sampling_3d_with_progress(
+    points: &[Coordinate3D],
+    n: u32,
+    progress: &Progress,
+) -> Result<Vec<Coordinate3D>, Cancelled> {
+    farthest_point_sampling_by(points, n, Coordinate3D::distance_squared, progress)
+}
 
-    // naive way: perform regular albedo sampling like earlier using farthest point sampling alone
+/// The Farthest Point Sampling shared by the 2D, 3D and color samplers, over any points with a
+/// squared distance between them.
+fn farthest_point_sampling_by<T: Copy>(
+    pixels: &[T],
+    n: u32,
+    distance_squared: impl Fn(&T, &T) -> f64,
+    progress: &Progress,
+) -> Result<Vec<T>, Cancelled> {
+    farthest_point_sampling_seeded_by(pixels, &[], n, distance_squared, progress)
+}
 
+/// Farthest Point Sampling that starts from the points already selected in `seeds`, which are
+/// not part of the result. Without seeds, every distance starts out infinite, so the first
+/// point selected is the last pixel in the input slice.
+fn farthest_point_sampling_seeded_by<T: Copy>(
+    pixels: &[T],
+    seeds: &[T],
+    n: u32,
+    distance_squared: impl Fn(&T, &T) -> f64,
+    progress: &Progress,
+) -> Result<Vec<T>, Cancelled> {
     let n = n as usize;
     let m = pixels.len();
 
     // --- Handle Edge Cases ---
     if n == 0 || m == 0 {
-        return Vec::new();
+        return Ok(Vec::new());
     }
     // If we need to select all or more pixels than are available, just return a copy.
-    if n >= m {
-        return pixels.to_vec();
+    if seeds.is_empty() && n >= m {
+        return Ok(pixels.to_vec());
     }
 
     // --- Initialization ---
+    // This will store the minimum *squared* distance from each pixel to the selected set,
+    // starting with the seeds.
+    let mut min_sq_distances: Vec<f64> = pixels
+        .iter()
+        .map(|p| {
+            seeds
+                .iter()
+                .map(|seed| distance_squared(p, seed))
+                .fold(f64::INFINITY, f64::min)
+        })
+        .collect();
     let mut selected_pixels = Vec::with_capacity(n);
-    // This will store the minimum *squared* distance from each pixel to the selected set.
-    let mut min_sq_distances = vec![f64::INFINITY; m];
 
-    // --- Step 1: Select the starting point ---
-    // As requested, we'll start with the last pixel in the input slice.
-    let first_pixel_index = m - 1;
-    let mut last_selected_pixel = pixels[first_pixel_index];
+    // --- Iteratively select the pixel farthest from the selection ---
+    while selected_pixels.len() < n {
+        progress.report(selected_pixels.len() as u64, n as u64)?;
+        // We do this by finding the maximum value in our `min_sq_distances` array.
+        let (farthest_index, &farthest_distance) = min_sq_distances
+            .iter()
+            .enumerate()
+            .max_by(|(_, &a), (_, &b)| a.partial_cmp(&b).unwrap_or(Ordering::Equal))
+            .expect("Distances should have at least one valid value");
+
+        // every remaining pixel is already selected
+        if farthest_distance <= 0.0 {
+            break;
+        }
 
-    selected_pixels.push(last_selected_pixel);
-    // Mark this pixel as "selected" by setting its distance to 0.
-    min_sq_distances[first_pixel_index] = 0.0;
+        // Add the new farthest pixel to our selection, and mark it as selected.
+        let last_selected_pixel = pixels[farthest_index];
+        selected_pixels.push(last_selected_pixel);
+        min_sq_distances[farthest_index] = 0.0;
 
-    // --- Step 2: Iteratively select the remaining n-1 points ---
-    for _ in 1..n {
         // Update the minimum distances for all points based on the *last* point we added.
         for (i, p) in pixels.iter().enumerate() {
             // We only need to check points that haven't been selected yet.
             if min_sq_distances[i] > 0.0 {
-                let sq_dist = p.distance_squared(&last_selected_pixel);
-                // If the distance to our newest point is smaller than the previous minimum, update it.
+                let sq_dist = distance_squared(p, &last_selected_pixel);
                 min_sq_distances[i] = min_sq_distances[i].min(sq_dist);
             }
         }
+    }
+    progress.report(n as u64, n as u64)?;
 
-        // Find the pixel that is now farthest from the entire selected set.
-        // We do this by finding the maximum value in our `min_sq_distances` array.
-        let (farthest_index, _) = min_sq_distances
-            .iter()
-            .enumerate()
-            .max_by(|(_, &a), (_, &b)| a.partial_cmp(&b).unwrap_or(Ordering::Equal))
-            .expect("Distances should have at least one valid value");
+    Ok(selected_pixels)
+}
 
-        // Add the new farthest pixel to our selection.
-        last_selected_pixel = pixels[farthest_index];
-        selected_pixels.push(last_selected_pixel);
-        min_sq_distances[farthest_index] = 0.0; // And mark it as selected.
+/// Continues Farthest Point Sampling from an existing selection, picking `n` more points
+/// from `pixels` that are as far as possible from both `seeds` and each other.
+///
+/// This is used to fill the gaps left by an earlier sample, e.g. so that the drones of a
+/// back layer sit between the drones of the layers in front of it. Pixels that are
+/// already part of `seeds` are never selected again.
+///
+/// This implementation is O((n + s) * m), where 's' is the number of seeds.
+///
+/// # Arguments
+/// * `pixels` - A slice of `Coordinate` points to sample from.
+/// * `seeds` - The points that have already been selected.
+/// * `n` - The number of new points to select.
+///
+/// # Returns
+/// A `Vec<Coordinate>` containing the `n` newly selected points, without the seeds.
+pub fn farthest_point_sampling_from(
+    pixels: &[Coordinate],
+    seeds: &[Coordinate],
+    n: u32
+) -> Vec<Coordinate> {
+    farthest_point_sampling_from_with_progress(pixels, seeds, n, &Progress::new()).expect("an untracked run is never cancelled")
+}
+
+/// Selects `n` points like `farthest_point_sampling_from`, reporting each selected point to
+/// `progress` and stopping early once it is cancelled.
+pub fn farthest_point_sampling_from_with_progress(
+    pixels: &[Coordinate],
+    seeds: &[Coordinate],
+    n: u32,
+    progress: &Progress,
+) -> Result<Vec<Coordinate>, Cancelled> {
+    farthest_point_sampling_seeded_by(pixels, seeds, n, Coordinate::distance_squared, progress)
+}
+
+/// Selects `n` points from a set of weighted pixels, with a density proportional to their weight.
+///
+/// This is a weighted variant of Farthest Point Sampling: at every step, the pixel with the
+/// highest `weight * squared distance to the selection` is picked. Heavier pixels can therefore
+/// sit closer together, so shading and gradients in the image come through as changes in the
+/// density of drones. Pixels closer than `min_spacing` to a selected pixel are never picked,
+/// which may leave fewer than `n` points.
+///
+/// This implementation is O(n * m), like `farthest_point_sampling`.
+///
+/// # Arguments
+/// * `pixels` - A slice of `Coordinate` points to sample from.
+/// * `weights` - The weight of each pixel, typically in `[0.0, 1.0]`. Panics unless there is one per pixel.
+/// * `n` - The number of points to select.
+/// * `min_spacing` - The minimum distance between two selected points, in pixels.
+///
+/// # Returns
+/// A `Vec<Coordinate>` containing at most `n` selected points.
+pub fn weighted_sampling(
+    pixels: &[Coordinate],
+    weights: &[f32],
+    n: u32,
+    min_spacing: f64,
+) -> Vec<Coordinate> {
+    weighted_sampling_with_progress(pixels, weights, n, min_spacing, &Progress::new()).expect("an untracked run is never cancelled")
+}
+
+/// Selects points like `weighted_sampling`, reporting each selected point to `progress` and
+/// stopping early once it is cancelled.
+pub fn weighted_sampling_with_progress(
+    pixels: &[Coordinate],
+    weights: &[f32],
+    n: u32,
+    min_spacing: f64,
+    progress: &Progress,
+) -> Result<Vec<Coordinate>, Cancelled> {
+    weighted_sampling_by(pixels, weights, n, min_spacing, Coordinate::distance_squared, progress)
+}
+
+/// Selects `n` points from a set of color pixels like `weighted_sampling`, using the
+/// brightness of each pixel as its weight so that brighter regions receive more drones.
+pub fn color_weighted_sampling(
+    pixels: &[ColorCoordinate],
+    n: u32,
+    min_spacing: f64,
+) -> Vec<ColorCoordinate> {
+    color_weighted_sampling_with_progress(pixels, n, min_spacing, &Progress::new()).expect("an untracked run is never cancelled")
+}
+
+/// Selects points like `color_weighted_sampling`, reporting each selected point to `progress`
+/// and stopping early once it is cancelled.
+pub fn color_weighted_sampling_with_progress(
+    pixels: &[ColorCoordinate],
+    n: u32,
+    min_spacing: f64,
+    progress: &Progress,
+) -> Result<Vec<ColorCoordinate>, Cancelled> {
+    let weights: Vec<f32> = pixels.iter().map(|p| p.brightness() / 255.0).collect();
+    weighted_sampling_by(pixels, &weights, n, min_spacing, ColorCoordinate::distance_squared, progress)
+}
+
+fn weighted_sampling_by<T: Copy>(
+    points: &[T],
+    weights: &[f32],
+    n: u32,
+    min_spacing: f64,
+    distance_squared: impl Fn(&T, &T) -> f64,
+    progress: &Progress,
+) -> Result<Vec<T>, Cancelled> {
+    assert_eq!(points.len(), weights.len(), "every point needs a weight");
+    let n = n as usize;
+    let m = points.len();
+
+    // --- Handle Edge Cases ---
+    if n == 0 || m == 0 {
+        return Ok(Vec::new());
+    }
+    if n >= m && min_spacing <= 0.0 {
+        return Ok(points.to_vec());
+    }
+
+    let min_sq_spacing = min_spacing.max(0.0).powi(2);
+    let mut selected_points = Vec::with_capacity(n);
+    let mut min_sq_distances = vec![f64::INFINITY; m];
+    let mut available = vec![true; m];
+
+    // --- Step 1: Start with the heaviest point ---
+    let (mut last_selected_index, _) = weights
+        .iter()
+        .enumerate()
+        .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(Ordering::Equal))
+        .expect("There should be at least one point");
+
+    // --- Step 2: Iteratively select the point with the best weighted distance ---
+    loop {
+        let last_selected_point = points[last_selected_index];
+        selected_points.push(last_selected_point);
+        available[last_selected_index] = false;
+        if selected_points.len() >= n {
+            break;
+        }
+        progress.report(selected_points.len() as u64, n as u64)?;
+
+        let mut best: Option<(usize, f64)> = None;
+        for (i, p) in points.iter().enumerate() {
+            if !available[i] {
+                continue;
+            }
+            let sq_dist = distance_squared(p, &last_selected_point);
+            min_sq_distances[i] = min_sq_distances[i].min(sq_dist);
+            if min_sq_distances[i] < min_sq_spacing {
+                // too close to the selection, so it can never be picked again
+                available[i] = false;
+                continue;
+            }
+            let score = min_sq_distances[i] * weights[i].max(0.0) as f64;
+            if best.is_none_or(|(_, best_score)| score > best_score) {
+                best = Some((i, score));
+            }
+        }
+
+        match best {
+            Some((index, _)) => last_selected_index = index,
+            None => break,
+        }
     }
+    // the spacing may have run out of points before reaching `n`
+    progress.report(n as u64, n as u64)?;
+
+    Ok(selected_points)
+}
 
-    selected_pixels
+/// Selects `n` points from a set of color pixels using Farthest Point Sampling, with a distance
+/// that combines position and perceived color.
+///
+/// The squared distance between two pixels is their squared spatial distance plus the squared
+/// CIELAB color difference scaled by `color_weight`. A small region whose color stands out from
+/// its surroundings is therefore far from every selected pixel, and receives drones of its own
+/// even when it would be skipped by purely spatial sampling.
+///
+/// This implementation is O(n * m), like `farthest_point_sampling`.
+///
+/// # Arguments
+/// * `pixels` - A slice of `ColorCoordinate` points to sample from.
+/// * `n` - The number of points to select.
+/// * `color_weight` - How many pixels of distance one unit of color difference is worth.
+///   With `0.0`, this is the same as spatial farthest point sampling.
+///
+/// # Returns
+/// A `Vec<ColorCoordinate>` containing the `n` selected points.
+pub fn color_farthest_point_sampling(
+    pixels: &[ColorCoordinate],
+    n: u32,
+    color_weight: f64,
+) -> Vec<ColorCoordinate> {
+    color_farthest_point_sampling_with_progress(pixels, n, color_weight, &Progress::new()).expect("an untracked run is never cancelled")
 }
 
+/// Selects `n` points like `color_farthest_point_sampling`, reporting each selected point to
+/// `progress` and stopping early once it is cancelled.
+pub fn color_farthest_point_sampling_with_progress(
+    pixels: &[ColorCoordinate],
+    n: u32,
+    color_weight: f64,
+    progress: &Progress,
+) -> Result<Vec<ColorCoordinate>, Cancelled> {
+    // without a color weight, the colors never affect the distance
+    if color_weight == 0.0 {
+        return farthest_point_sampling_by(pixels, n, ColorCoordinate::distance_squared, progress);
+    }
+
+    // the CIELAB color of every pixel, computed once up front
+    let pixels_with_labs: Vec<(ColorCoordinate, [f32; 3])> = pixels
+        .iter()
+        .map(|&p| {
+            let [r, g, b, _] = p.color().0;
+            (p, rgb_to_lab([r, g, b]))
+        })
+        .collect();
+    let color_weight_sq = color_weight * color_weight;
+
+    let selected = farthest_point_sampling_by(
+        &pixels_with_labs,
+        n,
+        |(a, a_lab), (b, b_lab)| a.distance_squared(b) + color_weight_sq * lab_distance_squared(a_lab, b_lab),
+        progress,
+    )?;
+    Ok(selected.into_iter().map(|(p, _)| p).collect())
+}
 
+#[cfg(test)]
+mod tests {
+    use super::*;
 
+    #[test]
+    fn heavier_pixels_receive_more_points() {
+        // a line of pixels, ten times heavier on its left half
+        let pixels: Vec<Coordinate> = (0..200).map(|x| Coordinate::new(x, 0)).collect();
+        let weights: Vec<f32> = (0..200).map(|x| if x < 100 { 1.0 } else { 0.1 }).collect();
 
+        let selected = weighted_sampling(&pixels, &weights, 30, 0.0);
+        assert_eq!(selected.len(), 30);
+        let left = selected.iter().filter(|p| p.x() < 100).count();
+        assert!(left >= 2 * (selected.len() - left), "{} of {} points on the heavy half", left, selected.len());
+    }
 
+    #[test]
+    fn weighted_points_keep_the_minimum_spacing() {
+        let pixels: Vec<Coordinate> = (0..400).map(|i| Coordinate::new(i % 20, i / 20)).collect();
+        let weights = vec![1.0; pixels.len()];
+
+        let selected = weighted_sampling(&pixels, &weights, 400, 5.0);
+        // the spacing runs out of pixels well before all of them are selected
+        assert!(selected.len() < 400);
+        for (i, a) in selected.iter().enumerate() {
+            for b in &selected[i + 1..] {
+                assert!(a.distance_squared(b) >= 25.0, "{:?} and {:?} are too close", a, b);
+            }
+        }
+    }
 
+    #[test]
+    #[should_panic(expected = "every point needs a weight")]
+    fn weights_must_match_the_points() {
+        let pixels = [Coordinate::new(0, 0), Coordinate::new(1, 0)];
+        weighted_sampling(&pixels, &[1.0], 1, 0.0);
+    }
+}
@@ -0,0 +1,306 @@
+use std::path::{Path, PathBuf};
+
+use image::{DynamicImage, GrayImage, RgbImage, RgbaImage};
+use pyo3::{
+    buffer::{Element, PyBuffer},
+    prelude::*,
+    types::{PyList, PyTuple},
+};
+
+use crate::{
+    animation::load_frames,
+    error::Error,
+    utils::{Coordinate, CoordinateOutput},
+    vector::load_image,
+};
+
+/// An image given to the Python API, either as a file to load or as pixels already in memory.
+#[derive(Debug, Clone)]
+pub enum ImageSource {
+    Path(PathBuf),
+    Image(DynamicImage),
+}
+
+impl FromPyObject<'_> for ImageSource {
+    /// Accepts a path (`str` or `os.PathLike`), the encoded bytes of an image file, a PIL image,
+    /// or an array of pixels of shape (H, W), (H, W, 3) or (H, W, 4) through the buffer protocol,
+    /// such as a NumPy array of `uint8`, or of floats in [0, 1].
+    fn extract_bound(ob: &Bound<'_, PyAny>) -> PyResult<Self> {
+        if let Ok(path) = ob.extract::<PathBuf>() {
+            return Ok(Self::Path(path));
+        }
+        // PIL images don't expose their pixels through the buffer protocol
+        if ob.hasattr("getbands")? && ob.hasattr("tobytes")? {
+            return pil_to_image(ob).map(Self::Image);
+        }
+        array_to_image(ob).map(Self::Image)
+    }
+}
+
+impl ImageSource {
+    /// Loads the image, rasterizing SVG files within `resize` like `load_image`.
+    pub fn load(&self, resize: Option<(u32, u32)>) -> Result<DynamicImage, Error> {
+        match self {
+            Self::Path(path) => {
+                check_readable(path)?;
+                load_image(path, resize).map_err(|cause| Error::Decode { path: Some(path.clone()), cause })
+            }
+            Self::Image(img) => Ok(img.clone()),
+        }
+    }
+
+    /// The path of the image, if it is loaded from a file.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Self::Path(path) => Some(path),
+            Self::Image(_) => None,
+        }
+    }
+}
+
+/// The frames of an animation given to the Python API, either as a GIF or directory of
+/// numbered frames, or as a list of images in any form accepted by `ImageSource`.
+#[derive(Debug, Clone)]
+pub enum FramesSource {
+    Path(PathBuf),
+    Frames(Vec<ImageSource>),
+}
+
+impl FromPyObject<'_> for FramesSource {
+    fn extract_bound(ob: &Bound<'_, PyAny>) -> PyResult<Self> {
+        if let Ok(path) = ob.extract::<PathBuf>() {
+            Ok(Self::Path(path))
+        } else if ob.is_instance_of::<PyList>() || ob.is_instance_of::<PyTuple>() {
+            Ok(Self::Frames(ob.extract()?))
+        } else {
+            Err(Error::invalid_parameter("Animations must be a path, or a list of images.").into())
+        }
+    }
+}
+
+impl FramesSource {
+    pub fn load(&self, resize: Option<(u32, u32)>) -> Result<Vec<DynamicImage>, Error> {
+        match self {
+            Self::Path(path) => {
+                check_readable(path)?;
+                load_frames(path, resize).map_err(|cause| Error::Decode { path: Some(path.clone()), cause })
+            }
+            Self::Frames(frames) if frames.is_empty() => Err(Error::invalid_parameter("The list of frames is empty.")),
+            Self::Frames(frames) => frames.iter().map(|frame| frame.load(resize)).collect(),
+        }
+    }
+}
+
+/// Tells a file that can't be read at all apart from one that isn't a valid image.
+pub fn check_readable(path: &Path) -> Result<(), Error> {
+    std::fs::metadata(path)
+        .map(|_| ())
+        .map_err(|cause| Error::Load { path: path.to_path_buf(), cause: cause.to_string() })
+}
+
+fn pil_to_image(ob: &Bound<'_, PyAny>) -> PyResult<DynamicImage> {
+    let rgba = ob.call_method1("convert", ("RGBA",))?;
+    let (width, height): (u32, u32) = rgba.getattr("size")?.extract()?;
+    let data: Vec<u8> = rgba.call_method0("tobytes")?.extract()?;
+    RgbaImage::from_raw(width, height, data)
+        .map(DynamicImage::ImageRgba8)
+        .ok_or_else(|| Error::invalid_parameter("The PIL image has an unexpected size.").into())
+}
+
+fn array_to_image(ob: &Bound<'_, PyAny>) -> PyResult<DynamicImage> {
+    let py = ob.py();
+    let unsupported = || PyErr::from(Error::invalid_parameter(
+        "Images must be a path, the bytes of an image file, a PIL image, or a uint8 or float array of shape (H, W), (H, W, 3) or (H, W, 4)."
+    ));
+
+    if let Ok(buffer) = PyBuffer::<u8>::get(ob) {
+        // a flat byte buffer holds an encoded image file
+        if buffer.dimensions() == 1 {
+            let data = buffer.to_vec(py)?;
+            return image::load_from_memory(&data)
+                .map_err(|e| Error::Decode { path: None, cause: e.to_string() }.into());
+        }
+        return pixels_to_image(buffer.shape(), buffer.to_vec(py)?).ok_or_else(unsupported);
+    }
+    if let Ok(buffer) = PyBuffer::<f32>::get(ob) {
+        let data = normalize_floats(buffer.to_vec(py)?.into_iter().map(|v| v as f64));
+        return pixels_to_image(buffer.shape(), data).ok_or_else(unsupported);
+    }
+    if let Ok(buffer) = PyBuffer::<f64>::get(ob) {
+        let data = normalize_floats(buffer.to_vec(py)?.into_iter());
+        return pixels_to_image(buffer.shape(), data).ok_or_else(unsupported);
+    }
+    Err(unsupported())
+}
+
+/// Converts float channels in [0, 1] to bytes.
+fn normalize_floats(values: impl Iterator<Item = f64>) -> Vec<u8> {
+    values.map(|v| (v * 255.0).round().clamp(0.0, 255.0) as u8).collect()
+}
+
+/// Builds an image from row-major pixel data, based on the shape of the array holding it.
+fn pixels_to_image(shape: &[usize], data: Vec<u8>) -> Option<DynamicImage> {
+    let (height, width) = (*shape.first()? as u32, *shape.get(1)? as u32);
+    match shape {
+        [_, _] | [_, _, 1] => GrayImage::from_raw(width, height, data).map(DynamicImage::ImageLuma8),
+        [_, _, 3] => RgbImage::from_raw(width, height, data).map(DynamicImage::ImageRgb8),
+        [_, _, 4] => RgbaImage::from_raw(width, height, data).map(DynamicImage::ImageRgba8),
+        _ => None,
+    }
+}
+
+/// Pixel coordinates given to the Python API, as a `CoordinateOutput`, an (N, 2) array of
+/// (x, y) rows, or a list of (x, y) tuples.
+pub struct CoordinateInput(pub Vec<Coordinate>);
+
+impl FromPyObject<'_> for CoordinateInput {
+    fn extract_bound(ob: &Bound<'_, PyAny>) -> PyResult<Self> {
+        if let Ok(output) = ob.downcast::<CoordinateOutput>() {
+            return Ok(Self(output.borrow().coords()));
+        }
+        if let Some(rows) = buffer_rows(ob)? {
+            if rows.columns != 2 || rows.values.iter().any(|&v| v < 0.0) {
+                return Err(Error::invalid_parameter("Coordinates must be an array of shape (N, 2) holding non-negative values.").into());
+            }
+            let coords = rows.values
+                .chunks_exact(2)
+                .map(|xy| Coordinate::new(xy[0].round() as u32, xy[1].round() as u32))
+                .collect();
+            return Ok(Self(coords));
+        }
+        let coords: Vec<(u32, u32)> = ob.extract()?;
+        Ok(Self(coords.into_iter().map(|(x, y)| Coordinate::new(x, y)).collect()))
+    }
+}
+
+/// Colors given to the Python API, as an (N, 3) or (N, 4) array of uint8 (or float in [0, 1])
+/// rows, or a list of (r, g, b, a) tuples. Colors without alpha are opaque.
+pub struct ColorInput(pub Vec<[u8; 4]>);
+
+impl FromPyObject<'_> for ColorInput {
+    fn extract_bound(ob: &Bound<'_, PyAny>) -> PyResult<Self> {
+        if let Some(rows) = buffer_rows(ob)? {
+            if rows.columns != 3 && rows.columns != 4 {
+                return Err(Error::invalid_parameter("Colors must be an array of shape (N, 3) or (N, 4).").into());
+            }
+            let scale = if rows.is_float { 255.0 } else { 1.0 };
+            let colors = rows.values
+                .chunks_exact(rows.columns)
+                .map(|row| {
+                    let channel = |i: usize| row.get(i).map_or(255, |v| (v * scale).round().clamp(0.0, 255.0) as u8);
+                    [channel(0), channel(1), channel(2), channel(3)]
+                })
+                .collect();
+            return Ok(Self(colors));
+        }
+        let colors: Vec<(u8, u8, u8, u8)> = ob.extract()?;
+        Ok(Self(colors.into_iter().map(|(r, g, b, a)| [r, g, b, a]).collect()))
+    }
+}
+
+/// The values of a two-dimensional numeric array, converted to `f64`.
+struct BufferRows {
+    values: Vec<f64>,
+    columns: usize,
+    is_float: bool,
+}
+
+/// Reads a two-dimensional array through the buffer protocol, whatever its numeric type.
+/// Returns `None` for objects that aren't such arrays.
+fn buffer_rows(ob: &Bound<'_, PyAny>) -> PyResult<Option<BufferRows>> {
+    fn read<T: Element + Copy>(
+        ob: &Bound<'_, PyAny>,
+        is_float: bool,
+        to_f64: fn(T) -> f64,
+    ) -> PyResult<Option<BufferRows>> {
+        let Ok(buffer) = PyBuffer::<T>::get(ob) else {
+            return Ok(None);
+        };
+        let [_, columns] = *buffer.shape() else {
+            return Ok(None);
+        };
+        let values = buffer.to_vec(ob.py())?.into_iter().map(to_f64).collect();
+        Ok(Some(BufferRows { values, columns, is_float }))
+    }
+
+    // each reader acquires its own buffer, so they are only tried until one matches
+    type Reader = fn(&Bound<'_, PyAny>) -> PyResult<Option<BufferRows>>;
+    let readers: [Reader; 10] = [
+        |ob| read::<u8>(ob, false, |v| v as f64),
+        |ob| read::<i8>(ob, false, |v| v as f64),
+        |ob| read::<u16>(ob, false, |v| v as f64),
+        |ob| read::<i16>(ob, false, |v| v as f64),
+        |ob| read::<u32>(ob, false, |v| v as f64),
+        |ob| read::<i32>(ob, false, |v| v as f64),
+        |ob| read::<u64>(ob, false, |v| v as f64),
+        |ob| read::<i64>(ob, false, |v| v as f64),
+        |ob| read::<f32>(ob, true, |v| v as f64),
+        |ob| read::<f64>(ob, true, |v| v),
+    ];
+    for reader in readers {
+        if let Some(rows) = reader(ob)? {
+            return Ok(Some(rows));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+
+    use super::*;
+
+    /// Runs `f` on the 2D buffer that `expression` evaluates to, such as a cast memoryview.
+    fn with_buffer<R>(expression: &CStr, f: impl FnOnce(&Bound<'_, PyAny>) -> R) -> R {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| f(&py.eval(expression, None, None).unwrap()))
+    }
+
+    #[test]
+    fn buffer_rows_read_every_dtype_as_rows() {
+        let rows = with_buffer(c"memoryview(bytes([1, 2, 3, 4, 5, 6])).cast('B', [2, 3])", |ob| buffer_rows(ob).unwrap().unwrap());
+        assert_eq!((rows.values, rows.columns, rows.is_float), (vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0], 3, false));
+
+        let rows = with_buffer(
+            c"memoryview(__import__('struct').pack('<4h', -1, 2, -3, 4)).cast('h', [2, 2])",
+            |ob| buffer_rows(ob).unwrap().unwrap(),
+        );
+        assert_eq!((rows.values, rows.columns, rows.is_float), (vec![-1.0, 2.0, -3.0, 4.0], 2, false));
+
+        let rows = with_buffer(
+            c"memoryview(__import__('struct').pack('<2d', 0.25, 0.5)).cast('d', [1, 2])",
+            |ob| buffer_rows(ob).unwrap().unwrap(),
+        );
+        assert_eq!((rows.values, rows.columns, rows.is_float), (vec![0.25, 0.5], 2, true));
+
+        // arrays that aren't two-dimensional aren't rows
+        assert!(with_buffer(c"memoryview(bytes(4))", |ob| buffer_rows(ob).unwrap().is_none()));
+        assert!(with_buffer(c"memoryview(bytes(8)).cast('B', [2, 2, 2])", |ob| buffer_rows(ob).unwrap().is_none()));
+    }
+
+    #[test]
+    fn array_inputs_are_checked_against_their_shape() {
+        let coords = with_buffer(
+            c"memoryview(__import__('struct').pack('<4I', 1, 2, 3, 4)).cast('I', [2, 2])",
+            |ob| ob.extract::<CoordinateInput>().unwrap().0,
+        );
+        assert_eq!(coords, vec![Coordinate::new(1, 2), Coordinate::new(3, 4)]);
+
+        assert!(with_buffer(c"memoryview(bytes(6)).cast('B', [2, 3])", |ob| ob.extract::<CoordinateInput>().is_err()));
+        assert!(with_buffer(
+            c"memoryview(__import__('struct').pack('<2h', -1, 2)).cast('h', [1, 2])",
+            |ob| ob.extract::<CoordinateInput>().is_err(),
+        ));
+
+        // colors without alpha are opaque, and floats are scaled from [0, 1]
+        let colors = with_buffer(c"memoryview(bytes([10, 20, 30])).cast('B', [1, 3])", |ob| ob.extract::<ColorInput>().unwrap().0);
+        assert_eq!(colors, vec![[10, 20, 30, 255]]);
+        let colors = with_buffer(
+            c"memoryview(__import__('struct').pack('<4d', 0.0, 0.5, 1.0, 0.2)).cast('d', [1, 4])",
+            |ob| ob.extract::<ColorInput>().unwrap().0,
+        );
+        assert_eq!(colors, vec![[0, 128, 255, 51]]);
+        assert!(with_buffer(c"memoryview(bytes(4)).cast('B', [2, 2])", |ob| ob.extract::<ColorInput>().is_err()));
+    }
+}
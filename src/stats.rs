@@ -0,0 +1,284 @@
+use std::{collections::HashMap, time::Duration};
+
+use pyo3::{prelude::*, types::PyDict};
+
+use crate::{
+    progress::{Progress, Stage},
+    transformation::ImgType,
+    utils::Coordinate,
+};
+
+/// A uniform grid over a set of 2D points, to find the nearest of them to any position
+/// without comparing it to every point.
+pub struct PointGrid {
+    points: Vec<[f64; 2]>,
+    cell_size: f64,
+    cells: HashMap<(i64, i64), Vec<usize>>,
+    /// The smallest and largest cell indices holding a point, which bound the search
+    min_cell: (i64, i64),
+    max_cell: (i64, i64),
+}
+
+impl PointGrid {
+    /// Builds the grid, with cells sized so that each holds about one point on average.
+    pub fn new(points: Vec<[f64; 2]>) -> Self {
+        let (min, max) = bounds(&points).unwrap_or(([0.0, 0.0], [0.0, 0.0]));
+        let area = ((max[0] - min[0]) * (max[1] - min[1])).max(1.0);
+        let cell_size = (area / points.len().max(1) as f64).sqrt().max(1.0);
+
+        let mut cells: HashMap<(i64, i64), Vec<usize>> = HashMap::new();
+        for (i, point) in points.iter().enumerate() {
+            cells.entry(cell_of(point, cell_size)).or_default().push(i);
+        }
+        let min_cell = cells.keys().fold((i64::MAX, i64::MAX), |acc, cell| (acc.0.min(cell.0), acc.1.min(cell.1)));
+        let max_cell = cells.keys().fold((i64::MIN, i64::MIN), |acc, cell| (acc.0.max(cell.0), acc.1.max(cell.1)));
+
+        Self {
+            points,
+            cell_size,
+            cells,
+            min_cell,
+            max_cell,
+        }
+    }
+
+    /// The index of the point nearest to `position` other than `exclude`, along with its
+    /// squared distance, or `None` if there is no other point.
+    pub fn nearest(&self, position: [f64; 2], exclude: Option<usize>) -> Option<(usize, f64)> {
+        let (cx, cy) = cell_of(&position, self.cell_size);
+        // the number of rings around the starting cell that cover every occupied cell
+        let max_ring = [
+            cx - self.min_cell.0,
+            self.max_cell.0 - cx,
+            cy - self.min_cell.1,
+            self.max_cell.1 - cy,
+        ]
+        .into_iter()
+        .max()
+        .unwrap_or(0)
+        .max(0);
+
+        let mut best: Option<(usize, f64)> = None;
+        for ring in 0..=max_ring {
+            for x in (cx - ring)..=(cx + ring) {
+                for y in (cy - ring)..=(cy + ring) {
+                    // only the cells on the border of the ring are new
+                    if (x - cx).abs() != ring && (y - cy).abs() != ring {
+                        continue;
+                    }
+                    for &i in self.cells.get(&(x, y)).into_iter().flatten() {
+                        if Some(i) == exclude {
+                            continue;
+                        }
+                        let [px, py] = self.points[i];
+                        let sq_dist = (px - position[0]).powi(2) + (py - position[1]).powi(2);
+                        if best.is_none_or(|(_, best_dist)| sq_dist < best_dist) {
+                            best = Some((i, sq_dist));
+                        }
+                    }
+                }
+            }
+            // every point beyond this ring is at least `ring` cells away
+            if best.is_some_and(|(_, best_dist)| best_dist <= (ring as f64 * self.cell_size).powi(2)) {
+                break;
+            }
+        }
+        best
+    }
+
+    /// The distance from each point to its nearest neighbour among the other points.
+    pub fn nearest_neighbour_distances(&self) -> Vec<f64> {
+        (0..self.points.len())
+            .filter_map(|i| self.nearest(self.points[i], Some(i)))
+            .map(|(_, sq_dist)| sq_dist.sqrt())
+            .collect()
+    }
+}
+
+fn cell_of(point: &[f64; 2], cell_size: f64) -> (i64, i64) {
+    ((point[0] / cell_size).floor() as i64, (point[1] / cell_size).floor() as i64)
+}
+
+fn bounds(points: &[[f64; 2]]) -> Option<([f64; 2], [f64; 2])> {
+    let first = *points.first()?;
+    Some(points.iter().fold((first, first), |(min, max), p| {
+        ([min[0].min(p[0]), min[1].min(p[1])], [max[0].max(p[0]), max[1].max(p[1])])
+    }))
+}
+
+pub fn to_points(coords: &[Coordinate]) -> Vec<[f64; 2]> {
+    coords.iter().map(|c| [c.x() as f64, c.y() as f64]).collect()
+}
+
+/// The luminance, from 0 to 255, of the faintest candidate pixel, which is where the
+/// brightness threshold ended up cutting the image. The weights are those returned by
+/// `image_to_weighted_coordinates`, from which the luminance of each pixel can be recovered.
+pub fn effective_threshold(weights: &[f32], img_type: ImgType) -> Option<f32> {
+    let faintest = weights.iter().copied().reduce(f32::min)?;
+    Some(match img_type {
+        ImgType::BlackOnWhite => (1.0 - faintest) * 255.0,
+        ImgType::WhiteOnBlack => faintest * 255.0,
+    })
+}
+
+/// Statistics of a run of the pipeline, from the source image to the sampled points
+///
+/// The sizes and counts come from the preprocessing stages, while the spacing, bounding box
+/// and coverage describe the sampled points. Distances are in pixels of the resized image.
+#[derive(Debug, Clone, Default)]
+#[pyclass(name="RunStats", module="raster_drone", frozen)]
+pub struct RunStats {
+    /// Size of the source image, before resizing
+    #[pyo3(get)]
+    pub source_size: (u32, u32),
+    /// Size of the image the candidate pixels were extracted from
+    #[pyo3(get)]
+    pub resized_size: (u32, u32),
+    /// Number of candidate pixels left after thresholding
+    #[pyo3(get)]
+    pub candidate_count: usize,
+    /// Luminance, from 0 to 255, of the faintest candidate pixel, which is where the threshold
+    /// ended up cutting the image. `None` for color images, which aren't thresholded
+    #[pyo3(get)]
+    pub effective_threshold: Option<f32>,
+    /// Number of sampled points
+    #[pyo3(get)]
+    pub sample_count: usize,
+    /// Smallest distance between a sampled point and its nearest neighbour
+    #[pyo3(get)]
+    pub min_spacing: Option<f64>,
+    /// Mean distance between each sampled point and its nearest neighbour
+    #[pyo3(get)]
+    pub mean_spacing: Option<f64>,
+    /// (min_x, min_y, max_x, max_y) of the sampled points
+    #[pyo3(get)]
+    pub bounding_box: Option<(u32, u32, u32, u32)>,
+    /// Fraction of the candidate pixels within `mean_spacing` of a sampled point, which is 1.0
+    /// when every part of the drawing has a drone close by
+    #[pyo3(get)]
+    pub coverage: Option<f64>,
+    /// Time spent in each stage, in order
+    pub timings: Vec<(Stage, Duration)>,
+}
+
+impl RunStats {
+    /// The statistics of the preprocessing stages, before any sampling.
+    pub fn preprocessed(
+        source_size: (u32, u32),
+        resized_size: (u32, u32),
+        candidate_count: usize,
+        effective_threshold: Option<f32>,
+    ) -> Self {
+        Self {
+            source_size,
+            resized_size,
+            candidate_count,
+            effective_threshold,
+            ..Self::default()
+        }
+    }
+
+    /// Fills in the statistics of the points sampled from `candidates`.
+    pub fn sampled(mut self, candidates: &[Coordinate], sampled: &[Coordinate]) -> Self {
+        self.sample_count = sampled.len();
+        self.bounding_box = sampled.iter().fold(None, |bbox, c| {
+            let (min_x, min_y, max_x, max_y) = bbox.unwrap_or((c.x(), c.y(), c.x(), c.y()));
+            Some((min_x.min(c.x()), min_y.min(c.y()), max_x.max(c.x()), max_y.max(c.y())))
+        });
+
+        let grid = PointGrid::new(to_points(sampled));
+        let spacings = grid.nearest_neighbour_distances();
+        self.min_spacing = spacings.iter().copied().reduce(f64::min);
+        self.mean_spacing = (!spacings.is_empty()).then(|| spacings.iter().sum::<f64>() / spacings.len() as f64);
+
+        self.coverage = self.mean_spacing.filter(|_| !candidates.is_empty()).map(|radius| {
+            let covered = to_points(candidates)
+                .into_iter()
+                .filter(|&p| grid.nearest(p, None).is_some_and(|(_, sq_dist)| sq_dist <= radius * radius))
+                .count();
+            covered as f64 / candidates.len() as f64
+        });
+        self
+    }
+
+    /// Records the time taken by the stages of `progress`, replacing earlier timings of the
+    /// same stages so that a partial rerun keeps the timings of the stages it skipped.
+    pub fn record_timings(mut self, progress: &Progress) -> Self {
+        for (stage, elapsed) in progress.timings() {
+            match self.timings.iter_mut().find(|(recorded, _)| *recorded == stage) {
+                Some(recorded) => recorded.1 = elapsed,
+                None => self.timings.push((stage, elapsed)),
+            }
+        }
+        self
+    }
+}
+
+#[pymethods]
+impl RunStats {
+    /// Seconds spent in each stage, as a dict from the name of the stage to its duration
+    #[getter]
+    fn timings<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let timings = PyDict::new(py);
+        for (stage, elapsed) in &self.timings {
+            timings.set_item(stage.to_string(), elapsed.as_secs_f64())?;
+        }
+        Ok(timings)
+    }
+
+    fn __repr__(&self) -> String {
+        let or_none = |value: Option<f64>| value.map_or("None".to_string(), |value| format!("{:.3}", value));
+        format!(
+            "RunStats(source_size={:?}, resized_size={:?}, candidate_count={}, sample_count={}, mean_spacing={}, coverage={})",
+            self.source_size,
+            self.resized_size,
+            self.candidate_count,
+            self.sample_count,
+            or_none(self.mean_spacing),
+            or_none(self.coverage),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grid_finds_the_same_nearest_point_as_a_full_scan() {
+        // scattered points from a small linear congruential generator, clustered in one corner
+        let mut state: u64 = 12345;
+        let mut next = || {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 33) as f64 / (1u64 << 31) as f64
+        };
+        let points: Vec<[f64; 2]> = (0..200).map(|i| {
+            let spread = if i % 4 == 0 { 500.0 } else { 40.0 };
+            [next() * spread, next() * spread]
+        }).collect();
+        let grid = PointGrid::new(points.clone());
+
+        for _ in 0..100 {
+            let position = [next() * 600.0 - 50.0, next() * 600.0 - 50.0];
+            let expected = points
+                .iter()
+                .map(|p| (p[0] - position[0]).powi(2) + (p[1] - position[1]).powi(2))
+                .fold(f64::INFINITY, f64::min);
+            let (_, sq_dist) = grid.nearest(position, None).unwrap();
+            assert_eq!(sq_dist, expected);
+        }
+
+        let lone = PointGrid::new(vec![[3.0, 4.0]]);
+        assert_eq!(lone.nearest([0.0, 0.0], None), Some((0, 25.0)));
+        assert_eq!(lone.nearest([0.0, 0.0], Some(0)), None);
+        assert!(lone.nearest_neighbour_distances().is_empty());
+    }
+
+    #[test]
+    fn effective_threshold_is_the_luminance_of_the_faintest_candidate() {
+        let weights = [0.8, 0.2, 0.6];
+        assert_eq!(effective_threshold(&weights, ImgType::WhiteOnBlack), Some(0.2 * 255.0));
+        assert_eq!(effective_threshold(&weights, ImgType::BlackOnWhite), Some((1.0 - 0.2) * 255.0));
+        assert_eq!(effective_threshold(&[], ImgType::BlackOnWhite), None);
+    }
+}
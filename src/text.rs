@@ -0,0 +1,145 @@
+use std::path::Path;
+
+use ab_glyph::{point, Font, FontVec, PxScale, ScaleFont};
+use image::{DynamicImage, GrayImage, Luma};
+use pyo3::prelude::*;
+
+use crate::error::Error;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TextAlign {
+    Left,
+    Center,
+    Right,
+}
+
+impl FromPyObject<'_> for TextAlign {
+    fn extract_bound(ob: &Bound<'_, PyAny>) -> PyResult<Self> {
+        if let Ok(s) = ob.extract::<&str>() {
+            match s.to_lowercase().as_str() {
+                "left" => Ok(Self::Left),
+                "center" => Ok(Self::Center),
+                "right" => Ok(Self::Right),
+                _ => Err(Error::invalid_parameter("The valid values for 'align' are 'left', 'center' and 'right'.").into())
+            }
+        } else {
+            Ok(Self::Center)
+        }
+    }
+}
+
+/// The largest font height `process_text` accepts, in pixels, which keeps the rendered image
+/// to a reasonable size.
+pub const MAX_TEXT_SIZE: f32 = 2048.0;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct TextLayout {
+    /// Height of the font in pixels.
+    pub size: f32,
+    pub align: TextAlign,
+    /// Multiplier on the font's natural line height.
+    pub line_spacing: f32,
+    /// Extra space added between consecutive letters, in pixels. May be negative.
+    pub letter_spacing: f32,
+    /// Empty border kept around the text, in pixels.
+    pub padding: u32,
+}
+
+impl Default for TextLayout {
+    fn default() -> Self {
+        Self {
+            size: 128.0,
+            align: TextAlign::Center,
+            line_spacing: 1.0,
+            letter_spacing: 0.0,
+            padding: 8,
+        }
+    }
+}
+
+/// Renders a string with a TTF/OTF font into an image, as white text on a black background.
+///
+/// Lines are split on `\n` and aligned against the widest line. The text is drawn in white
+/// so that it can go through the regular pipeline with `ImgType::WhiteOnBlack`, with the
+/// anti-aliased glyph edges ranked below the solid glyph interiors.
+///
+/// # Arguments
+/// * `text` - The string to render.
+/// * `font_path` - Path to a TTF or OTF font file.
+/// * `layout` - Size, alignment and spacing options.
+///
+/// # Returns
+/// A grayscale `DynamicImage` sized to fit the text, or an error message if the font can't be loaded.
+pub fn render_text(text: &str, font_path: &Path, layout: &TextLayout) -> Result<DynamicImage, String> {
+    let data = std::fs::read(font_path).map_err(|e| format!("{:?}", e))?;
+    let font = FontVec::try_from_vec(data).map_err(|e| format!("{:?}", e))?;
+    let font = font.as_scaled(PxScale::from(layout.size));
+
+    // --- Lay out each line as a list of positioned glyphs ---
+    let lines: Vec<(Vec<ab_glyph::Glyph>, f32)> = text
+        .lines()
+        .map(|line| {
+            let mut glyphs = Vec::new();
+            let mut caret = 0.0f32;
+            let mut previous: Option<ab_glyph::GlyphId> = None;
+            for c in line.chars() {
+                let id = font.glyph_id(c);
+                if let Some(previous) = previous {
+                    caret += font.kern(previous, id) + layout.letter_spacing;
+                }
+                glyphs.push(id.with_scale_and_position(layout.size, point(caret, 0.0)));
+                caret += font.h_advance(id);
+                previous = Some(id);
+            }
+            (glyphs, caret.max(0.0))
+        })
+        .collect();
+
+    if lines.is_empty() {
+        return Err("Cannot render an empty string".to_string());
+    }
+
+    let line_height = font.height() * layout.line_spacing;
+    let text_width = lines.iter().map(|(_, width)| *width).fold(0.0, f32::max);
+    let text_height = line_height * (lines.len() - 1) as f32 + font.height();
+
+    let padding = layout.padding as f32;
+    let width = (text_width + 2.0 * padding).ceil().max(1.0) as u32;
+    let height = (text_height + 2.0 * padding).ceil().max(1.0) as u32;
+    let mut img = GrayImage::new(width, height);
+
+    // --- Draw every glyph, offset for its line and alignment ---
+    for (row, (glyphs, line_width)) in lines.into_iter().enumerate() {
+        let offset_x = padding + match layout.align {
+            TextAlign::Left => 0.0,
+            TextAlign::Center => (text_width - line_width) / 2.0,
+            TextAlign::Right => text_width - line_width,
+        };
+        let baseline = padding + font.ascent() + row as f32 * line_height;
+
+        for mut glyph in glyphs {
+            glyph.position = point(glyph.position.x + offset_x, baseline);
+            let Some(outline) = font.outline_glyph(glyph) else {
+                // whitespace has no outline
+                continue;
+            };
+            let bounds = outline.px_bounds();
+            outline.draw(|x, y, coverage| {
+                let px = bounds.min.x as i32 + x as i32;
+                let py = bounds.min.y as i32 + y as i32;
+                if px >= 0 && py >= 0 && (px as u32) < width && (py as u32) < height {
+                    let pixel = img.get_pixel_mut(px as u32, py as u32);
+                    // overlapping glyphs keep the strongest coverage
+                    pixel.0[0] = pixel.0[0].max((coverage.clamp(0.0, 1.0) * 255.0) as u8);
+                }
+            });
+        }
+    }
+
+    // whitespace-only text would leave nothing to sample
+    if img.pixels().all(|p| *p == Luma([0])) {
+        return Err("The rendered text contains no visible glyphs".to_string());
+    }
+
+    Ok(DynamicImage::ImageLuma8(img))
+}
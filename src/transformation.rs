@@ -1,7 +1,7 @@
-use pyo3::{exceptions::PyValueError, prelude::*};
+use pyo3::prelude::*;
 use image::{DynamicImage, GenericImageView};
 
-use crate::utils::Coordinate;
+use crate::{error::Error, utils::Coordinate};
 
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum ImgType {
@@ -15,7 +15,7 @@ impl FromPyObject<'_> for ImgType {
             match s.to_lowercase().as_str() {
                 "black_on_white" => Ok(Self::BlackOnWhite),
                 "white_on_black" => Ok(Self::WhiteOnBlack),
-                _ => Err(PyValueError::new_err("The valid values for 'img_type' are 'black_on_white' and 'white_on_black'."))
+                _ => Err(Error::invalid_parameter("The valid values for 'img_type' are 'black_on_white' and 'white_on_black'.").into())
             }
         } else {
             Ok(Self::BlackOnWhite)
@@ -31,6 +31,17 @@ impl FromPyObject<'_> for ImgType {
 /// # Returns
 /// A `Vec<Coordinate>` containing the coordinates of the selected pixels.
 pub fn image_to_coordinates(img: &DynamicImage, percentile: f32, img_type: ImgType) -> Vec<Coordinate> {
+    image_to_weighted_coordinates(img, percentile, img_type).0
+}
+
+/// Like `image_to_coordinates`, but also keeps the intensity of each selected pixel as a weight.
+///
+/// The weight is in `[0.0, 1.0]` and measures how strongly the pixel belongs to the drawing:
+/// its darkness for `ImgType::BlackOnWhite`, and its brightness for `ImgType::WhiteOnBlack`.
+///
+/// # Returns
+/// The coordinates of the selected pixels, along with their weights in the same order.
+pub fn image_to_weighted_coordinates(img: &DynamicImage, percentile: f32, img_type: ImgType) -> (Vec<Coordinate>, Vec<f32>) {
     // Clamp the percentile to a valid range [0.0, 1.0].
     let percentile = percentile.clamp(0.0, 1.0);
 
@@ -69,12 +80,18 @@ pub fn image_to_coordinates(img: &DynamicImage, percentile: f32, img_type: ImgTy
     let total_pixels = pixel_brightness_data.len();
     let num_to_take = (total_pixels as f32 * percentile).round() as usize;
 
-    // Take the top `num_to_take` brightest pixels and extract just their coordinates.
+    // Take the top `num_to_take` brightest pixels, turning their brightness into a weight.
     pixel_brightness_data
         .into_iter()
         .take(num_to_take)
-        .map(|(_brightness, coord)| coord)
-        .collect()
+        .map(|(brightness, coord)| {
+            let weight = match img_type {
+                ImgType::BlackOnWhite => 1.0 - brightness / 255.0,
+                ImgType::WhiteOnBlack => brightness / 255.0,
+            };
+            (coord, weight)
+        })
+        .unzip()
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -101,6 +118,12 @@ impl ColorCoordinate {
     pub fn color(&self) -> image::Rgba<u8> {
         self.color
     }
+    pub fn coord(&self) -> Coordinate {
+        self.coord
+    }
+    pub fn brightness(&self) -> f32 {
+        self.brightness
+    }
     // since we only use it for comparison, it's more performant to use
     // the square of euclidean distances, so that we avoid
     // an expensive square root operation
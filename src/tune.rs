@@ -0,0 +1,327 @@
+use image::DynamicImage;
+use log::{debug, info};
+use pyo3::{prelude::*, types::PyDict};
+use rayon::prelude::*;
+
+use crate::{
+    metrics::{default_light_radius, evaluate_formation, render_lights, ssim, FormationMetrics},
+    progress::{Cancelled, Progress, Stage},
+    raster::SamplingType,
+    resize::{resize_image, Resize},
+    transformation::ImgType,
+    utils::{image_array, Coordinate, CoordinateOutput},
+};
+
+/// One combination of settings tried by `search`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct TuneChoice {
+    pub threshold: f32,
+    /// The Bradley window size, or `None` for the global threshold alone
+    pub bradley_size: Option<u32>,
+    pub resize: (u32, u32),
+    pub sampling: SamplingType,
+}
+
+/// The values `search` tries for each setting, in every combination.
+#[derive(Debug, Clone)]
+pub struct TuneSpace {
+    pub thresholds: Vec<f32>,
+    pub bradley_sizes: Vec<Option<u32>>,
+    pub resolutions: Vec<(u32, u32)>,
+    pub samplers: Vec<SamplingType>,
+}
+
+impl Default for TuneSpace {
+    fn default() -> Self {
+        Self {
+            thresholds: vec![0.01, 0.05, 0.1, 0.25],
+            bradley_sizes: vec![None, Some(8), Some(16), Some(32)],
+            resolutions: vec![(128, 128), (256, 256)],
+            samplers: vec![SamplingType::Farthest, SamplingType::Weighted],
+        }
+    }
+}
+
+impl TuneSpace {
+    /// The preprocessing settings of each combination, which are shared by its samplers.
+    fn preprocessing_choices(&self) -> Vec<(f32, Option<u32>, (u32, u32))> {
+        let mut choices = Vec::new();
+        for &resize in &self.resolutions {
+            for &bradley_size in &self.bradley_sizes {
+                for &threshold in &self.thresholds {
+                    choices.push((threshold, bradley_size, resize));
+                }
+            }
+        }
+        choices
+    }
+
+    /// The largest of the resolutions, at which the formations are compared.
+    pub fn largest_resolution(&self) -> Option<(u32, u32)> {
+        self.resolutions.iter().copied().max_by_key(|(width, height)| width * height)
+    }
+}
+
+/// The image every formation of a search is compared to: the ink of the source image, from
+/// 0.0 for the background to 1.0 for the strongest lines, in row-major order.
+///
+/// Each setting changes the candidate pixels, so comparing a formation to its own thresholded
+/// image would reward settings that throw most of the image away. The source is the one
+/// reference they all share.
+pub struct Reference {
+    pixels: Vec<f32>,
+    width: u32,
+    height: u32,
+}
+
+impl Reference {
+    /// The reference of `img`, resized like the largest resolution of the search. The ink of a
+    /// pixel is its darkness for `ImgType::BlackOnWhite` and its brightness otherwise, like the
+    /// weights of `image_to_weighted_coordinates`.
+    pub fn new(img: DynamicImage, resize: &Resize, img_type: ImgType) -> Self {
+        let img = resize_image(img, resize).to_rgba8();
+        let pixels = img
+            .pixels()
+            .map(|pixel| {
+                let [r, g, b, a] = pixel.0.map(|channel| channel as f32);
+                let brightness = (0.299 * r + 0.587 * g + 0.114 * b) * (a / 255.0) / 255.0;
+                match img_type {
+                    ImgType::BlackOnWhite => 1.0 - brightness,
+                    ImgType::WhiteOnBlack => brightness,
+                }
+            })
+            .collect();
+
+        Self {
+            pixels,
+            width: img.width(),
+            height: img.height(),
+        }
+    }
+
+    /// The SSIM between the reference and the lights of `sampled`, rendered at the scale of the
+    /// reference from an image of `width` x `height` pixels holding `candidate_count` candidates.
+    fn score(&self, sampled: &[Coordinate], width: u32, height: u32, candidate_count: usize) -> f64 {
+        let scale_x = self.width as f64 / width.max(1) as f64;
+        let scale_y = self.height as f64 / height.max(1) as f64;
+        let scaled: Vec<Coordinate> = sampled
+            .iter()
+            .map(|c| {
+                let x = ((c.x() as f64 + 0.5) * scale_x - 0.5).round().max(0.0) as u32;
+                let y = ((c.y() as f64 + 0.5) * scale_y - 0.5).round().max(0.0) as u32;
+                Coordinate::new(x, y)
+            })
+            .collect();
+        let scaled_candidates = (candidate_count as f64 * scale_x * scale_y).round() as usize;
+        let light_radius = default_light_radius(scaled_candidates, sampled.len());
+
+        let rendered = render_lights(&scaled, self.width, self.height, light_radius);
+        ssim(&rendered, &self.pixels, self.width as usize, self.height as usize)
+    }
+}
+
+/// A formation found by `auto_tune`, with the settings that produced it
+///
+/// `kwargs` gives the settings as keyword arguments of `process_image_to_coordinates` and
+/// `process_image`, to run them again on the full pipeline.
+#[derive(Clone)]
+#[pyclass(name="TuneResult", module="raster_drone", frozen)]
+pub struct TuneResult {
+    pub choice: TuneChoice,
+    /// SSIM between the lights of the formation and the ink of the source image
+    #[pyo3(get)]
+    pub score: f64,
+    /// Scores of the formation against its own candidate pixels
+    #[pyo3(get)]
+    pub metrics: Option<FormationMetrics>,
+    /// The sampled formation
+    #[pyo3(get)]
+    pub coordinates: CoordinateOutput,
+    /// Number of candidate pixels the formation was sampled from
+    #[pyo3(get)]
+    pub candidate_count: usize,
+}
+
+#[pymethods]
+impl TuneResult {
+    #[getter]
+    fn threshold(&self) -> f64 {
+        shortest_f64(self.choice.threshold)
+    }
+
+    #[getter]
+    fn bradley_size(&self) -> Option<u32> {
+        self.choice.bradley_size
+    }
+
+    #[getter]
+    fn resize(&self) -> (u32, u32) {
+        self.choice.resize
+    }
+
+    #[getter]
+    fn sample(&self) -> &'static str {
+        self.choice.sampling.name()
+    }
+
+    /// The settings as a dict of keyword arguments of `process_image_to_coordinates`
+    fn kwargs<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let kwargs = PyDict::new(py);
+        kwargs.set_item("sample", self.choice.sampling.name())?;
+        kwargs.set_item("resize", self.choice.resize)?;
+        kwargs.set_item("threshold", shortest_f64(self.choice.threshold))?;
+        kwargs.set_item("bradley", self.choice.bradley_size.is_some())?;
+        if let Some(bradley_size) = self.choice.bradley_size {
+            kwargs.set_item("bradley_size", bradley_size)?;
+        }
+        Ok(kwargs)
+    }
+
+    /// The formation rendered as glowing lights, as an (H, W) uint8 numpy array
+    fn preview<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let (width, height) = (self.coordinates.width(), self.coordinates.height());
+        let light_radius = self.metrics.map_or(3.0, |metrics| metrics.light_radius);
+        let pixels = render_lights(&self.coordinates.coords(), width, height, light_radius);
+        let pixels = pixels.into_iter().map(|p| (p * 255.0).round() as u8).collect();
+        let img = image::GrayImage::from_raw(width, height, pixels).expect("one pixel was rendered per position");
+        image_array(py, &DynamicImage::ImageLuma8(img))
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "TuneResult(score={:.3}, sample='{}', resize={:?}, threshold={}, bradley_size={})",
+            self.score,
+            self.choice.sampling.name(),
+            self.choice.resize,
+            self.choice.threshold,
+            self.choice.bradley_size.map_or("None".to_string(), |size| size.to_string()),
+        )
+    }
+}
+
+/// The `f64` written the same as `value`, so that a threshold of 0.1 doesn't come out as
+/// 0.10000000149011612 in Python.
+fn shortest_f64(value: f32) -> f64 {
+    value.to_string().parse().unwrap_or(value as f64)
+}
+
+/// Tries every combination of `space` on a formation of `n` drones, and returns the `top` best
+/// formations, best first, as scored against `reference`.
+///
+/// Settings that leave fewer than `n` candidate pixels are skipped, as they can't place every
+/// drone, and so are resolutions that resize the image to the same size as an earlier one.
+/// `preprocess` extracts the candidate pixels for a threshold, Bradley window size and
+/// resolution, along with any data the sampling needs, such as the intensity weights, and
+/// `sample` draws the formation from them. The preprocessing choices run in parallel, and
+/// each reports its progress to a subtask of `progress`, while `progress` itself counts the
+/// combinations tried.
+pub fn search<C, E>(
+    space: &TuneSpace,
+    n: u32,
+    reference: &Reference,
+    top: usize,
+    progress: &Progress,
+    preprocess: impl Fn(f32, Option<u32>, (u32, u32), &Progress) -> Result<(CoordinateOutput, C), E> + Sync,
+    sample: impl Fn(&CoordinateOutput, &C, SamplingType, &Progress) -> Result<Vec<Coordinate>, E> + Sync,
+) -> Result<Vec<TuneResult>, E>
+where
+    E: From<Cancelled> + Send,
+{
+    let choices = space.preprocessing_choices();
+    progress.stage(Stage::Tuning)?;
+    progress.report(0, (choices.len() * space.samplers.len()) as u64)?;
+
+    let results: Vec<Vec<TuneResult>> = choices
+        .par_iter()
+        .map(|&(threshold, bradley_size, resize)| {
+            let subtask = progress.subtask();
+            let (candidates, data) = preprocess(threshold, bradley_size, resize, &subtask)?;
+            let candidate_coords = candidates.coords();
+
+            let mut results = Vec::new();
+            for &sampling in &space.samplers {
+                if candidate_coords.len() >= n as usize {
+                    let sampled = sample(&candidates, &data, sampling, &subtask)?;
+                    let (width, height) = (candidates.width(), candidates.height());
+                    let choice = TuneChoice { threshold, bradley_size, resize, sampling };
+                    let score = reference.score(&sampled, width, height, candidate_coords.len());
+                    debug!("{:?} scored {:.3}", choice, score);
+
+                    results.push(TuneResult {
+                        choice,
+                        score,
+                        metrics: evaluate_formation(&candidate_coords, &sampled, width, height, None),
+                        coordinates: CoordinateOutput::new(sampled, width, height),
+                        candidate_count: candidate_coords.len(),
+                    });
+                }
+                progress.advance()?;
+            }
+            Ok(results)
+        })
+        .collect::<Result<_, E>>()?;
+
+    let mut results: Vec<TuneResult> = results.into_iter().flatten().collect();
+    let mut seen = Vec::new();
+    results.retain(|result| {
+        let choice = result.choice;
+        let key = (result.coordinates.width(), result.coordinates.height(), choice.threshold, choice.bradley_size, choice.sampling);
+        let is_new = !seen.contains(&key);
+        seen.push(key);
+        is_new
+    });
+    results.sort_by(|a, b| b.score.total_cmp(&a.score));
+    if let Some(best) = results.first() {
+        info!("The best of {} formations scored {:.3}", results.len(), best.score);
+    }
+    results.truncate(top);
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{GrayImage, Luma};
+
+    use super::*;
+    use crate::{resize::{ResizeFilter, ResizeMode}, sampling::farthest_point_sampling};
+
+    #[test]
+    fn search_ranks_the_formations_closest_to_the_source_first() {
+        // a black square in the middle of a white image
+        let inside = |x: u32, y: u32| (4..12).contains(&x) && (4..12).contains(&y);
+        let img = DynamicImage::ImageLuma8(GrayImage::from_fn(16, 16, |x, y| Luma([if inside(x, y) { 0 } else { 255 }])));
+        let reference = Reference::new(img, &Resize::new((16, 16), ResizeMode::Fit, ResizeFilter::Triangle), ImgType::BlackOnWhite);
+
+        let space = TuneSpace {
+            thresholds: vec![0.1, 0.5, 0.9],
+            bradley_sizes: vec![None],
+            resolutions: vec![(16, 16)],
+            samplers: vec![SamplingType::Farthest],
+        };
+        let results = search(
+            &space,
+            4,
+            &reference,
+            5,
+            &Progress::new(),
+            |threshold, _, _, _| {
+                // the square itself, a line along the edge, or too few pixels to place every drone
+                let candidates: Vec<Coordinate> = if threshold < 0.2 {
+                    (0..16).flat_map(|y| (0..16).map(move |x| Coordinate::new(x, y))).filter(|c| inside(c.x(), c.y())).collect()
+                } else if threshold < 0.6 {
+                    (0..16).map(|y| Coordinate::new(0, y)).collect()
+                } else {
+                    vec![Coordinate::new(8, 8)]
+                };
+                Ok::<_, Cancelled>((CoordinateOutput::new(candidates, 16, 16), ()))
+            },
+            |candidates, _, _, _| Ok(farthest_point_sampling(&candidates.coords(), 4)),
+        ).unwrap();
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].choice.threshold, 0.1);
+        assert_eq!(results[1].choice.threshold, 0.5);
+        assert!(results[0].score > results[1].score);
+        assert_eq!(results[0].coordinates.coords().len(), 4);
+    }
+}
@@ -1,6 +1,20 @@
-use pyo3::{pyclass, pymethods, IntoPyObject};
+use pyo3::{
+    exceptions::PyIndexError,
+    pyclass, pymethods,
+    types::{PyAnyMethods, PyByteArray},
+    Bound, IntoPyObject, PyAny, PyResult, Python,
+};
 
-use crate::transformation::ColorCoordinate;
+use image::DynamicImage;
+
+use crate::{
+    error::Error,
+    export::{
+        encode_by_extension, gltf_binary, normalize_coordinates_3d, normalize_frames, write_output, GltfParams,
+    },
+    stats::RunStats,
+    transformation::ColorCoordinate,
+};
 
 #[repr(C)]
 #[derive(Debug, Clone, Copy, PartialEq, IntoPyObject, bytemuck::Pod, bytemuck::Zeroable)]
@@ -29,12 +43,38 @@ impl Coordinate {
 
 /// Like a Coordinate except we're putting it in f64 instead for the purposes of exporting to
 /// Skybrush and normalizing
+///
+/// x runs left to right and y bottom to top across the formation, while z is the depth
+/// in front of or behind the formation's plane, which is 0.0 for flat formations
 #[derive(Debug, Clone, Copy, PartialEq)]
-pub struct ExportCoordinate([f64; 2]);
+pub struct ExportCoordinate([f64; 3]);
 
 impl ExportCoordinate {
     pub fn new(x: f64, y: f64) -> Self {
-        Self([x, y])
+        Self([x, y, 0.0])
+    }
+    pub fn new_3d(x: f64, y: f64, z: f64) -> Self {
+        Self([x, y, z])
+    }
+    pub fn x(&self) -> f64 {
+        self.0[0]
+    }
+    pub fn y(&self) -> f64 {
+        self.0[1]
+    }
+    pub fn z(&self) -> f64 {
+        self.0[2]
+    }
+}
+
+/// A point in 3D space, such as a point sampled from the surface of a mesh
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, IntoPyObject)]
+pub struct Coordinate3D([f64; 3]);
+
+impl Coordinate3D {
+    pub fn new(x: f64, y: f64, z: f64) -> Self {
+        Self([x, y, z])
     }
     pub fn x(&self) -> f64 {
         self.0[0]
@@ -42,6 +82,50 @@ impl ExportCoordinate {
     pub fn y(&self) -> f64 {
         self.0[1]
     }
+    pub fn z(&self) -> f64 {
+        self.0[2]
+    }
+    // since we only use it for comparison, it's more performant to use
+    // the square of euclidean distances, so that we avoid
+    // an expensive square root operation
+    pub fn distance_squared(&self, rhs: &Self) -> f64 {
+        let dx = self.x() - rhs.x();
+        let dy = self.y() - rhs.y();
+        let dz = self.z() - rhs.z();
+        dx.mul_add(dx, dy.mul_add(dy, dz * dz))
+    }
+}
+
+/// Builds a numpy array of `dtype` from raw native-endian data.
+///
+/// The data is copied once into a buffer that numpy wraps as is, rather than creating a
+/// Python object for every element.
+pub(crate) fn numpy_array<'py>(py: Python<'py>, data: &[u8], dtype: &str, shape: Vec<usize>) -> PyResult<Bound<'py, PyAny>> {
+    let numpy = py.import("numpy")?;
+    let buffer = PyByteArray::new(py, data);
+    numpy
+        .call_method1("frombuffer", (buffer, dtype))?
+        .call_method1("reshape", (shape,))
+}
+
+/// An image as a numpy array of uint8, of shape (H, W) for grayscale images and (H, W, 4) otherwise
+pub(crate) fn image_array<'py>(py: Python<'py>, img: &DynamicImage) -> PyResult<Bound<'py, PyAny>> {
+    let (width, height) = (img.width() as usize, img.height() as usize);
+    match img {
+        DynamicImage::ImageLuma8(gray) => numpy_array(py, gray.as_raw(), "uint8", vec![height, width]),
+        _ => numpy_array(py, img.to_rgba8().as_raw(), "uint8", vec![height, width, 4]),
+    }
+}
+
+/// Coordinates as an (N, 2) numpy array of uint32 (x, y) rows
+pub(crate) fn coordinates_array<'py>(py: Python<'py>, coords: &[Coordinate]) -> PyResult<Bound<'py, PyAny>> {
+    numpy_array(py, bytemuck::cast_slice(coords), "uint32", vec![coords.len(), 2])
+}
+
+/// Colors as an (N, 4) numpy array of uint8 (r, g, b, a) rows
+pub(crate) fn colors_array<'py>(py: Python<'py>, coords: &[ColorCoordinate]) -> PyResult<Bound<'py, PyAny>> {
+    let colors: Vec<u8> = coords.iter().flat_map(|c| c.color().0).collect();
+    numpy_array(py, &colors, "uint8", vec![coords.len(), 4])
 }
 
 #[derive(Clone)]
@@ -50,6 +134,7 @@ pub struct CoordinateOutput {
     coords: Vec<Coordinate>,
     width: u32,
     height: u32,
+    stats: Option<RunStats>,
 }
 
 impl CoordinateOutput {
@@ -62,8 +147,12 @@ impl CoordinateOutput {
             coords,
             width,
             height,
+            stats: None,
         }
     }
+    pub fn with_stats(self, stats: RunStats) -> Self {
+        Self { stats: Some(stats), ..self }
+    }
     pub fn borrow_coords(self) -> Vec<Coordinate> {
         self.coords
     }
@@ -80,6 +169,15 @@ impl CoordinateOutput {
     pub fn coords(&self) -> Vec<Coordinate> {
         self.coords.clone()
     }
+    /// The coordinates as an (N, 2) numpy array of uint32 (x, y) rows
+    pub fn coords_array<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        coordinates_array(py, &self.coords)
+    }
+    /// Statistics of the run that produced the coordinates, or None for coordinates that
+    /// didn't come from an image, such as those of 'path' sampling
+    pub fn stats(&self) -> Option<RunStats> {
+        self.stats.clone()
+    }
 }
 
 #[derive(Clone)]
@@ -88,6 +186,7 @@ pub struct ColorCoordinateOutput {
     coords: Vec<ColorCoordinate>,
     width: u32,
     height: u32,
+    stats: Option<RunStats>,
 }
 
 impl ColorCoordinateOutput {
@@ -100,19 +199,174 @@ impl ColorCoordinateOutput {
             coords,
             width,
             height,
+            stats: None,
         }
     }
+    pub fn with_stats(self, stats: RunStats) -> Self {
+        Self { stats: Some(stats), ..self }
+    }
     pub fn borrow_coords(self) -> Vec<ColorCoordinate> {
         self.coords
     }
+    pub fn coords(self) -> Vec<ColorCoordinate> {
+        self.coords
+    }
+}
+
+#[pymethods]
+impl ColorCoordinateOutput {
     pub fn width(&self) -> u32 {
         self.width
     }
     pub fn height(&self) -> u32 {
         self.height
     }
-    pub fn coords(self) -> Vec<ColorCoordinate> {
-        self.coords
+    /// The (x, y) coordinates of the sampled pixels
+    #[pyo3(name="coords")]
+    fn py_coords(&self) -> Vec<Coordinate> {
+        self.coords.iter().map(|c| c.coord()).collect()
+    }
+    /// The (r, g, b, a) colors of the sampled pixels, in the same order as `coords`
+    fn colors(&self) -> Vec<(u8, u8, u8, u8)> {
+        self.coords.iter().map(|c| {
+            let [r, g, b, a] = c.color().0;
+            (r, g, b, a)
+        }).collect()
+    }
+    /// The coordinates as an (N, 2) numpy array of uint32 (x, y) rows
+    fn coords_array<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let coords: Vec<Coordinate> = self.coords.iter().map(|c| c.coord()).collect();
+        coordinates_array(py, &coords)
+    }
+    /// The colors as an (N, 4) numpy array of uint8 (r, g, b, a) rows
+    fn colors_array<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        colors_array(py, &self.coords)
+    }
+    /// Statistics of the run that produced the coordinates
+    pub fn stats(&self) -> Option<RunStats> {
+        self.stats.clone()
     }
 }
 
+
+/// The sampled coordinates of every frame of an animation, where index `i` of each
+/// frame is the same drone throughout.
+#[derive(Clone)]
+#[pyclass(name="FrameSequenceOutput", module="raster_drone", sequence)]
+pub struct FrameSequenceOutput {
+    frames: Vec<Vec<Coordinate>>,
+    width: u32,
+    height: u32,
+}
+
+impl FrameSequenceOutput {
+    pub fn new(
+        frames: Vec<Vec<Coordinate>>,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        Self {
+            frames,
+            width,
+            height,
+        }
+    }
+}
+
+#[pymethods]
+impl FrameSequenceOutput {
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+    pub fn frames(&self) -> Vec<Vec<Coordinate>> {
+        self.frames.clone()
+    }
+    /// The coordinates of every frame as an (F, N, 2) numpy array of uint32 (x, y) rows,
+    /// where `[:, i]` is the path of drone `i` through the animation
+    pub fn frames_array<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let drones = self.frames.first().map_or(0, |frame| frame.len());
+        if self.frames.iter().any(|frame| frame.len() != drones) {
+            return Err(Error::invalid_parameter("The frames hold different numbers of drones.").into());
+        }
+        let coords: Vec<Coordinate> = self.frames.concat();
+        numpy_array(py, bytemuck::cast_slice(&coords), "uint32", vec![self.frames.len(), drones, 2])
+    }
+    fn __len__(&self) -> usize {
+        self.frames.len()
+    }
+    fn __getitem__(&self, index: isize) -> PyResult<CoordinateOutput> {
+        let len = self.frames.len() as isize;
+        let index = if index < 0 { index + len } else { index };
+        if index < 0 || index >= len {
+            return Err(PyIndexError::new_err("frame index out of range"));
+        }
+        Ok(CoordinateOutput::new(self.frames[index as usize].clone(), self.width, self.height))
+    }
+    /// Exports the animation as a binary glTF (.glb), with every drone keyframed through the frames
+    ///
+    /// Arguments:
+    ///     output_path: str
+    ///         path where the .glb file will be saved. Note that, if the intermediate directories do not exist, they will be created
+    ///     max_dim: float
+    ///         size of the largest dimension of the show, in meters. Defaults to 20.0
+    ///     seconds_per_frame: float
+    ///         time between consecutive frames, in seconds. Defaults to 1.0
+    #[pyo3(signature=(output_path, max_dim=20.0, seconds_per_frame=1.0))]
+    pub fn export_gltf(&self, output_path: &str, max_dim: f64, seconds_per_frame: f32) -> PyResult<()> {
+        let path = std::path::Path::new(output_path);
+        let formations = normalize_frames(&self.frames, max_dim).map_err(|cause| Error::export(path, cause))?;
+        let params = GltfParams {
+            seconds_per_formation: seconds_per_frame,
+            ..GltfParams::default()
+        };
+        let file_data = gltf_binary(&formations, &[], &params).map_err(|cause| Error::export(path, cause))?;
+
+        Ok(write_output(path, &file_data)?)
+    }
+}
+
+/// Coordinates sampled in 3D, such as from the surface of a mesh
+#[derive(Clone)]
+#[pyclass(name="Coordinate3DOutput", module="raster_drone")]
+pub struct Coordinate3DOutput {
+    coords: Vec<Coordinate3D>,
+}
+
+impl Coordinate3DOutput {
+    pub fn new(coords: Vec<Coordinate3D>) -> Self {
+        Self { coords }
+    }
+}
+
+#[pymethods]
+impl Coordinate3DOutput {
+    pub fn coords(&self) -> Vec<Coordinate3D> {
+        self.coords.clone()
+    }
+    /// The coordinates as an (N, 3) numpy array of float64 (x, y, z) rows
+    pub fn coords_array<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let values: Vec<f64> = self.coords.iter().flat_map(|c| [c.x(), c.y(), c.z()]).collect();
+        numpy_array(py, bytemuck::cast_slice(&values), "float64", vec![self.coords.len(), 3])
+    }
+    /// Exports the formation, scaled so that its largest dimension is `max_dim` meters
+    ///
+    /// The file format is chosen from the extension of `output_path`: '.csv' for Skybrush Studio,
+    /// '.glb' for a binary glTF, or '.ply' for a point cloud
+    ///
+    /// Arguments:
+    ///     output_path: str
+    ///         path where the formation will be saved. Note that, if the intermediate directories do not exist, they will be created
+    ///     max_dim: float
+    ///         size of the largest dimension of the formation, in meters. Defaults to 20.0
+    #[pyo3(signature=(output_path, max_dim=20.0))]
+    pub fn export(&self, output_path: &str, max_dim: f64) -> PyResult<()> {
+        let path = std::path::Path::new(output_path);
+        let normalized = normalize_coordinates_3d(&self.coords, max_dim).map_err(|cause| Error::export(path, cause))?;
+        let file_data = encode_by_extension(path, normalized, &[]).map_err(|cause| Error::export(path, cause))?;
+
+        Ok(write_output(path, &file_data)?)
+    }
+}
@@ -0,0 +1,204 @@
+use std::path::Path;
+
+use image::{DynamicImage, RgbaImage};
+use resvg::{tiny_skia, usvg};
+
+use crate::utils::Coordinate;
+
+/// Checks whether a path points to an SVG file, based on its extension.
+pub fn is_svg(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| matches!(ext.to_lowercase().as_str(), "svg" | "svgz"))
+}
+
+/// Loads an image from disk, rasterizing SVG files at the requested resolution.
+///
+/// Raster formats are loaded as-is through `image::open`. SVGs are rendered so that
+/// they fit within `resize` (scaling up if necessary), so that thin strokes survive
+/// instead of being lost by rasterizing small and resizing afterwards.
+pub fn load_image(path: &Path, resize: Option<(u32, u32)>) -> Result<DynamicImage, String> {
+    if is_svg(path) {
+        load_svg(path, resize)
+    } else {
+        image::open(path).map_err(|e| format!("{:?}", e))
+    }
+}
+
+fn parse_svg(path: &Path) -> Result<usvg::Tree, String> {
+    let data = std::fs::read(path).map_err(|e| format!("{:?}", e))?;
+    usvg::Tree::from_data(&data, &usvg::Options::default()).map_err(|e| format!("{:?}", e))
+}
+
+/// Computes the output size and scale factor that fit an SVG canvas within `resize`,
+/// preserving aspect ratio. Without `resize`, the SVG's own size is used.
+fn fit_svg(tree: &usvg::Tree, resize: Option<(u32, u32)>) -> (u32, u32, f32) {
+    let size = tree.size();
+    let scale = match resize {
+        Some((width, height)) => (width as f32 / size.width()).min(height as f32 / size.height()),
+        None => 1.0,
+    };
+    let width = ((size.width() * scale).round() as u32).max(1);
+    let height = ((size.height() * scale).round() as u32).max(1);
+    (width, height, scale)
+}
+
+/// Rasterizes an SVG file onto a white background, fitting it within `resize`.
+pub fn load_svg(path: &Path, resize: Option<(u32, u32)>) -> Result<DynamicImage, String> {
+    let tree = parse_svg(path)?;
+    let (width, height, scale) = fit_svg(&tree, resize);
+
+    let mut pixmap = tiny_skia::Pixmap::new(width, height)
+        .ok_or_else(|| format!("Invalid SVG raster size {}x{}", width, height))?;
+    // the pipeline treats fully transparent pixels as having no brightness,
+    // so we composite onto white to keep black-on-white artwork intact
+    pixmap.fill(tiny_skia::Color::WHITE);
+    resvg::render(&tree, tiny_skia::Transform::from_scale(scale, scale), &mut pixmap.as_mut());
+
+    // the background is opaque, so the premultiplied data is already straight alpha
+    let img = RgbaImage::from_raw(width, height, pixmap.take())
+        .ok_or_else(|| "SVG raster has an unexpected size".to_string())?;
+    Ok(DynamicImage::ImageRgba8(img))
+}
+
+/// Flattens every visible path in a group into polylines, in output pixel space.
+fn collect_polylines(group: &usvg::Group, scale: f32, polylines: &mut Vec<Vec<(f32, f32)>>) {
+    // number of straight segments used to approximate each curve
+    const CURVE_STEPS: usize = 16;
+
+    for node in group.children() {
+        match node {
+            usvg::Node::Group(group) => collect_polylines(group, scale, polylines),
+            usvg::Node::Path(path) if path.is_visible() => {
+                let transform = path.abs_transform().post_scale(scale, scale);
+                let map = |p: tiny_skia::Point| {
+                    let mut p = p;
+                    transform.map_point(&mut p);
+                    (p.x, p.y)
+                };
+
+                let mut current: Vec<(f32, f32)> = Vec::new();
+                let mut start = (0.0, 0.0);
+                for segment in path.data().segments() {
+                    match segment {
+                        tiny_skia::PathSegment::MoveTo(p) => {
+                            if current.len() > 1 {
+                                polylines.push(std::mem::take(&mut current));
+                            }
+                            start = map(p);
+                            current = vec![start];
+                        }
+                        tiny_skia::PathSegment::LineTo(p) => current.push(map(p)),
+                        tiny_skia::PathSegment::QuadTo(c, p) => {
+                            let from = current.last().copied().unwrap_or(start);
+                            let (c, p) = (map(c), map(p));
+                            for step in 1..=CURVE_STEPS {
+                                let t = step as f32 / CURVE_STEPS as f32;
+                                let u = 1.0 - t;
+                                current.push((
+                                    u * u * from.0 + 2.0 * u * t * c.0 + t * t * p.0,
+                                    u * u * from.1 + 2.0 * u * t * c.1 + t * t * p.1,
+                                ));
+                            }
+                        }
+                        tiny_skia::PathSegment::CubicTo(c1, c2, p) => {
+                            let from = current.last().copied().unwrap_or(start);
+                            let (c1, c2, p) = (map(c1), map(c2), map(p));
+                            for step in 1..=CURVE_STEPS {
+                                let t = step as f32 / CURVE_STEPS as f32;
+                                let u = 1.0 - t;
+                                let (a, b, c, d) = (u * u * u, 3.0 * u * u * t, 3.0 * u * t * t, t * t * t);
+                                current.push((
+                                    a * from.0 + b * c1.0 + c * c2.0 + d * p.0,
+                                    a * from.1 + b * c1.1 + c * c2.1 + d * p.1,
+                                ));
+                            }
+                        }
+                        tiny_skia::PathSegment::Close => current.push(start),
+                    }
+                }
+                if current.len() > 1 {
+                    polylines.push(current);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Places `n` points at equal spacing along the path geometry of an SVG, without rasterizing.
+///
+/// All paths are treated as one continuous outline, so the spacing is the same across
+/// separate shapes. The coordinates are expressed in the pixel space of the SVG fitted
+/// within `resize`, so they can be used exactly like sampled raster coordinates.
+///
+/// # Returns
+/// The sampled coordinates along with the width and height of that pixel space.
+pub fn svg_path_sampling(
+    path: &Path,
+    n: u32,
+    resize: Option<(u32, u32)>,
+) -> Result<(Vec<Coordinate>, u32, u32), String> {
+    let tree = parse_svg(path)?;
+    let (width, height, scale) = fit_svg(&tree, resize);
+
+    let mut polylines = Vec::new();
+    collect_polylines(tree.root(), scale, &mut polylines);
+
+    let segment_length = |a: (f32, f32), b: (f32, f32)| ((b.0 - a.0).powi(2) + (b.1 - a.1).powi(2)).sqrt();
+    let total_length: f32 = polylines
+        .iter()
+        .flat_map(|line| line.windows(2))
+        .map(|pair| segment_length(pair[0], pair[1]))
+        .sum();
+
+    if n == 0 || total_length <= 0.0 {
+        return Ok((Vec::new(), width, height));
+    }
+
+    let spacing = total_length / n as f32;
+    // start half a step in, so that closed shapes don't get two points at their seam
+    let mut next_target = spacing / 2.0;
+    let mut travelled = 0.0;
+    let mut coords = Vec::with_capacity(n as usize);
+
+    for pair in polylines.iter().flat_map(|line| line.windows(2)) {
+        let (a, b) = (pair[0], pair[1]);
+        let length = segment_length(a, b);
+        while next_target <= travelled + length && coords.len() < n as usize {
+            let t = if length > 0.0 { (next_target - travelled) / length } else { 0.0 };
+            let x = (a.0 + (b.0 - a.0) * t).round().clamp(0.0, (width - 1) as f32);
+            let y = (a.1 + (b.1 - a.1) * t).round().clamp(0.0, (height - 1) as f32);
+            coords.push(Coordinate::new(x as u32, y as u32));
+            next_target += spacing;
+        }
+        travelled += length;
+    }
+
+    Ok((coords, width, height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn path_sampling_spaces_points_evenly_along_the_outline() {
+        // a square outline 80 pixels a side, so 320 pixels around
+        let path = std::env::temp_dir().join("raster_drone_path_sampling.svg");
+        std::fs::write(
+            &path,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="100"><path d="M10 10 H90 V90 H10 Z" fill="none" stroke="black"/></svg>"#,
+        ).unwrap();
+        let (coords, width, height) = svg_path_sampling(&path, 32, None).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!((width, height), (100, 100));
+        assert_eq!(coords.len(), 32);
+        // every 10 pixels, starting half a step in
+        let top: Vec<u32> = coords.iter().take(8).map(|c| c.x()).collect();
+        assert_eq!(top, vec![15, 25, 35, 45, 55, 65, 75, 85]);
+        assert!(coords.iter().take(8).all(|c| c.y() == 10));
+        assert!(coords.iter().all(|c| [10, 90].contains(&c.x()) || [10, 90].contains(&c.y())));
+    }
+}